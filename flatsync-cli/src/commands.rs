@@ -1,22 +1,22 @@
-use clap::Subcommand;
-use libflatsync_common::dbus::DaemonProxy;
-use libflatsync_common::providers::github::{GitHubProvider, GH_APP_INSTALLATION_URL};
+use clap::{Args, Subcommand};
+use gio::prelude::*;
+use libflatsync_common::config::APP_ID;
+use libflatsync_common::dbus::{DaemonProxy, JoinStrategy, OldSinkAction, SyncMode};
+use libflatsync_common::providers::github::{
+    GitHubAccount, GitHubConfig, GitHubProvider, GH_APP_INSTALLATION_URL,
+};
+use libflatsync_common::providers::gitlab::{GitLabProvider, GITLAB_DEFAULT_INSTANCE_URL};
 use libflatsync_common::providers::oauth_client::OauthClientDeviceFlow;
 use libflatsync_common::providers::providers_list::Providers;
+use libflatsync_common::{FilterRule, VersionPolicy};
 use log::*;
+use std::path::PathBuf;
 use std::process;
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Initialize the FlatSync daemon, store the credentials in the keyring, and back up the Flatpak list for the first time
-    Init {
-        #[arg(long)]
-        #[arg(default_value = "github")]
-        provider: Providers,
-
-        #[arg(long)]
-        gist_id: Option<String>,
-    },
+    Init(InitArgs),
     Autostart {
         /// Whether to install the autostart file
         #[arg(long, default_value_t = false)]
@@ -24,6 +24,142 @@ pub enum Commands {
     },
     /// Start the syncing process manually
     SyncNow,
+    /// Move the synced list to another, already set up data sink and make it the active one
+    Migrate {
+        /// Provider to migrate to
+        #[arg(long)]
+        to: Providers,
+        /// What to do with the previously active data sink
+        #[arg(long, default_value = "keep")]
+        old_sink: OldSinkAction,
+        /// Replace a different list already stored on the target
+        #[arg(long, default_value_t = false)]
+        force: bool,
+    },
+    /// Pair with another device on the network for the `lan` provider.
+    /// Without a code, this device waits for another one to pair with it.
+    Pair {
+        /// The code shown on the other device
+        #[arg(long)]
+        code: Option<String>,
+    },
+    /// List the devices this one is paired with for the `lan` provider, or forget one of them
+    Peers {
+        /// Name of the device to forget
+        #[arg(long)]
+        unpair: Option<String>,
+    },
+    /// Show the health of the data sinks, or stop replicating to one of the mirrors
+    Sinks {
+        /// Provider of the mirror to stop replicating to
+        #[arg(long)]
+        remove_mirror: Option<Providers>,
+    },
+    /// Show what the next sync would change on this device, without changing anything
+    Plan,
+    /// List the changes from other devices that contradicted local ones during the latest sync
+    Conflicts,
+    /// List the devices syncing through the same data sink and what is installed on each, or rename this one
+    Devices {
+        /// New name for this device, empty to use the hostname again
+        #[arg(long)]
+        rename: Option<String>,
+        /// Also list the refs installed on each device
+        #[arg(long, default_value_t = false)]
+        refs: bool,
+    },
+    /// List the profiles, edit one of them, or choose the ones this device syncs
+    Profiles {
+        /// Profile to edit
+        #[arg(long)]
+        profile: Option<String>,
+        /// Full ref (e.g. `app/org.gnome.Maps/x86_64/stable`) or app ID to add to the profile
+        #[arg(long, requires = "profile")]
+        add: Vec<String>,
+        /// Full ref or app ID to remove from the profile
+        #[arg(long, requires = "profile")]
+        remove: Vec<String>,
+        /// Delete the profile
+        #[arg(long, requires = "profile", default_value_t = false)]
+        delete: bool,
+        /// Comma separated profiles this device syncs, replacing the current ones. Pass "" to sync all refs again
+        #[arg(long, value_delimiter = ',')]
+        subscribe: Option<Vec<String>>,
+    },
+    /// List the rules selecting the refs to sync, or change them.
+    /// Rules look like `exclude id=org.freedesktop.Platform.GL.* kind=runtime`, conditions are id (glob), kind, origin, branch, arch and installation
+    Filters {
+        /// Rule to add
+        #[arg(long)]
+        add: Vec<String>,
+        /// Rule to remove
+        #[arg(long)]
+        remove: Vec<String>,
+        /// Whether to share the rules with the other devices syncing them
+        #[arg(long)]
+        sync: Option<bool>,
+    },
+    /// Show which version of a ref is installed when syncing it and the refs with their own policy, or change them
+    Versions {
+        /// Policy for all refs which aren't pinned
+        #[arg(long)]
+        policy: Option<VersionPolicy>,
+        /// Full ref (e.g. `app/org.gnome.Maps/x86_64/stable`) to give its own policy on all devices
+        #[arg(long)]
+        pin: Option<String>,
+        /// Policy of the pinned ref
+        #[arg(long, requires = "pin", default_value = "exact-commit")]
+        pin_policy: VersionPolicy,
+        /// Commit to install the pinned ref at, instead of the one installed on the device it is synced from
+        #[arg(long, requires = "pin")]
+        commit: Option<String>,
+        /// Full ref to let follow each device's policy again
+        #[arg(long)]
+        unpin: Option<String>,
+        /// Whether to update installed refs to the commit installed on the other devices, or the one they are pinned to
+        #[arg(long)]
+        propagate_updates: Option<bool>,
+    },
+    /// List the rules mapping the names of remotes on other devices to local ones, or change them.
+    /// Remotes pointing to the same URL or collection ID are matched without a rule
+    Remotes {
+        /// Rule to add, like `flathub-1=flathub` if other devices call this device's `flathub` remote `flathub-1`
+        #[arg(long)]
+        alias: Vec<String>,
+        /// Name of a remote on other devices to remove the rule for
+        #[arg(long)]
+        unalias: Vec<String>,
+    },
+    /// List the changes from other devices waiting for approval, approve or reject them, or change which changes need approval
+    Pending {
+        /// ID of a change to approve
+        #[arg(long)]
+        approve: Vec<u32>,
+        /// ID of a change to reject
+        #[arg(long)]
+        reject: Vec<u32>,
+        /// Approve all pending changes
+        #[arg(long, default_value_t = false, conflicts_with_all = ["approve", "reject", "reject_all"])]
+        approve_all: bool,
+        /// Reject all pending changes
+        #[arg(long, default_value_t = false, conflicts_with_all = ["approve", "reject"])]
+        reject_all: bool,
+        /// Whether changes are applied right away or wait for approval
+        #[arg(long)]
+        mode: Option<SyncMode>,
+    },
+    /// Show the uninstallations of a sync stopped for removing too many refs and confirm them, or change the limits
+    Brake {
+        /// Let the stopped sync proceed
+        #[arg(long, default_value_t = false)]
+        confirm: bool,
+        /// Number of refs a sync may uninstall without confirmation, 0 for no limit
+        #[arg(long)]
+        max_uninstalls: Option<u32>,
+        /// Percentage of the installed refs a sync may uninstall without confirmation, 0 for no limit
+        #[arg(long, value_parser = clap::value_parser!(u32).range(0..=100))]
+        max_percentage: Option<u32>,
+    },
     /// Controls the Autosync Behaviour
     Autosync {
         #[arg(long, default_value_t = true)]
@@ -40,30 +176,648 @@ pub enum Commands {
     },
 }
 
-pub async fn init(
-    proxy: &DaemonProxy<'_>,
+#[derive(Debug, Args)]
+pub struct InitArgs {
+    #[arg(long)]
+    #[arg(default_value = "github")]
     provider: Providers,
+
+    /// Set the provider up as a mirror every change is replicated to, instead of replacing the primary data sink
+    #[arg(long, default_value_t = false)]
+    mirror: bool,
+
+    /// ID of an existing gist or snippet to sync with
+    #[arg(long)]
     gist_id: Option<String>,
-) -> Result<(), zbus::Error> {
+
+    /// How to reconcile the refs installed here with an existing list, like the one of `--gist-id` or one found at the provider's location
+    #[arg(long, default_value = "merge")]
+    join: JoinStrategy,
+
+    /// Base URL of the GitHub Enterprise Server, GitLab or Forgejo instance, used by the `github`, `gitlab` and `forgejo` providers
+    #[arg(long)]
+    instance_url: Option<String>,
+
+    /// Client ID of the GitHub App or OAuth application registered on the instance, used by the `github`, `gitlab` and `forgejo` providers
+    #[arg(long)]
+    client_id: Option<String>,
+
+    /// Base URL of the GitHub REST API, overriding the one derived from `--instance-url`
+    #[arg(long)]
+    api_url: Option<String>,
+
+    /// GitHub OAuth device authorization URL, overriding the one derived from `--instance-url`
+    #[arg(long)]
+    device_auth_url: Option<String>,
+
+    /// GitHub OAuth token URL, overriding the one derived from `--instance-url`
+    #[arg(long)]
+    token_url: Option<String>,
+
+    /// Directory synced between devices by another tool, used by the `local-directory` provider
+    #[arg(long)]
+    directory: Option<PathBuf>,
+
+    /// URL of the directory to store the list in, used by the `webdav` provider, or of the server, used by the `flatsync-server` provider
+    #[arg(long)]
+    url: Option<String>,
+
+    /// User name to authenticate with, used by the `webdav` provider
+    #[arg(long)]
+    username: Option<String>,
+
+    /// Endpoint URL of the object storage, used by the `s3` provider
+    #[arg(long)]
+    endpoint: Option<String>,
+
+    /// Bucket to store the list in, used by the `s3` provider
+    #[arg(long)]
+    bucket: Option<String>,
+
+    /// Key prefix to store the list under, used by the `s3` provider
+    #[arg(long)]
+    prefix: Option<String>,
+
+    /// Region of the bucket, used by the `s3` provider
+    #[arg(long)]
+    region: Option<String>,
+
+    /// Access key ID to authenticate with, used by the `s3` provider
+    #[arg(long)]
+    access_key_id: Option<String>,
+
+    /// URL of the repository to commit to, used by the `git` provider, or its `owner/name`, used by the `forgejo` provider
+    #[arg(long)]
+    repository: Option<String>,
+
+    /// Branch of the repository to commit to, used by the `git` and `forgejo` providers
+    #[arg(long)]
+    branch: Option<String>,
+}
+
+pub async fn init(proxy: &DaemonProxy<'_>, args: InitArgs) -> Result<(), zbus::Error> {
     info!("Initializing FlatSync daemon");
 
-    match provider {
-        Providers::Github => init_for_github(proxy, gist_id).await?,
+    let provider = args.provider;
+    let sink = if args.mirror {
+        if proxy.sink_provider().await? == provider.name() {
+            error!("{:?} is already the primary data sink.", provider.name());
+            return Err(zbus::Error::Failure("Provider is already in use".into()));
+        }
+        InitSink {
+            proxy,
+            mirror: Some(provider.name()),
+        }
+    } else {
+        proxy.set_sink_provider(provider.name()).await?;
+        InitSink {
+            proxy,
+            mirror: None,
+        }
+    };
+
+    let join = args.join;
+    match args.provider {
+        Providers::Github => init_for_github(&sink, args).await?,
+        Providers::LocalDirectory => init_for_local_directory(&sink, args.directory, join).await?,
+        Providers::Git => init_for_git(&sink, args.repository, args.branch, join).await?,
+        Providers::Webdav => init_for_webdav(&sink, args.url, args.username, join).await?,
+        Providers::S3 => init_for_s3(&sink, args).await?,
+        Providers::FlatsyncServer => init_for_flatsync_server(&sink, args.url, join).await?,
+        Providers::Forgejo => init_for_forgejo(&sink, args).await?,
+        Providers::Lan => init_for_lan(&sink, join).await?,
+        Providers::Gitlab => {
+            let instance_url = args
+                .instance_url
+                .unwrap_or_else(|| GITLAB_DEFAULT_INSTANCE_URL.into());
+            init_for_gitlab(&sink, instance_url, args.client_id, args.gist_id, args.join).await?
+        }
+    }
+
+    if let Some(provider) = sink.mirror {
+        proxy.add_mirror_sink(provider).await?;
+        info!(
+            "Replicating to {:?} from now on, starting with the next change",
+            provider
+        );
     }
 
     Ok(())
 }
 
-async fn init_for_github(
+/// The data sink `init` sets up, either the primary one or a mirror next to it.
+struct InitSink<'a, 'p> {
+    proxy: &'a DaemonProxy<'p>,
+    /// The provider of the mirror, which is configured without touching the primary data sink
+    mirror: Option<&'static str>,
+}
+
+impl InitSink<'_, '_> {
+    async fn set_secret(&self, secret: &str) -> Result<(), zbus::Error> {
+        match self.mirror {
+            Some(provider) => self.proxy.set_mirror_secret(provider, secret).await,
+            None => self.proxy.set_gist_secret(secret).await,
+        }
+    }
+
+    /// Points the sink to an existing list. The primary sink joins it right away, a mirror gets it replaced on the next push.
+    async fn join(&self, id: &str, strategy: JoinStrategy) -> Result<(), zbus::Error> {
+        match self.mirror {
+            Some(provider) => self.proxy.set_mirror_id(provider, id).await,
+            None => self.proxy.join_gist(id, strategy.name()).await,
+        }
+    }
+
+    /// Creates the list on the primary sink and returns its ID. Mirrors get theirs on the next push instead.
+    async fn create(&self) -> Result<Option<String>, zbus::Error> {
+        match self.mirror {
+            Some(_) => Ok(None),
+            None => self.proxy.create_gist().await.map(Some),
+        }
+    }
+
+    /// Creates the list on the primary sink, or takes over the one already at its location, and joins it.
+    /// Returns the list's ID, if one was created or found.
+    async fn create_or_join(&self, strategy: JoinStrategy) -> Result<Option<String>, zbus::Error> {
+        let id = self.create().await?;
+        if let Some(id) = &id {
+            self.join(id, strategy).await?;
+        }
+        Ok(id)
+    }
+}
+
+pub async fn migrate(
     proxy: &DaemonProxy<'_>,
-    gist_id: Option<String>,
+    to: Providers,
+    old_sink: OldSinkAction,
+    force: bool,
+) -> Result<(), zbus::Error> {
+    info!("Migrating the FlatSync list to {:?}", to.name());
+
+    match proxy.migrate_sink(to.name(), old_sink.name(), force).await {
+        Ok(id) => {
+            info!("Successfully migrated, now syncing with {:?}", id);
+            Ok(())
+        }
+        Err(e) => {
+            error!("{}", e);
+            error!(
+                "Please make sure {:?} is set up, e.g. by running `flatsync-cli init --provider {} --mirror` first.",
+                to.name(),
+                to.name()
+            );
+            Err(e)
+        }
+    }
+}
+
+pub async fn pair(proxy: &DaemonProxy<'_>, code: Option<String>) -> Result<(), zbus::Error> {
+    match code {
+        Some(code) => {
+            info!("Looking for the device showing the code...");
+            let name = proxy.pair_lan_device(&code).await?;
+            info!("Successfully paired with {:?}", name);
+        }
+        None => {
+            let code = proxy.start_lan_pairing().await?;
+            info!(
+                "Enter the code {} on the other device within the next 5 minutes by running `flatsync-cli pair --code {}`.",
+                code, code
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn peers(proxy: &DaemonProxy<'_>, unpair: Option<String>) -> Result<(), zbus::Error> {
+    if let Some(name) = unpair {
+        proxy.unpair_lan_device(&name).await?;
+        info!("Unpaired {:?}", name);
+    }
+
+    for name in proxy.lan_peers().await? {
+        info!("{}", name);
+    }
+
+    Ok(())
+}
+
+pub async fn sinks(
+    proxy: &DaemonProxy<'_>,
+    remove_mirror: Option<Providers>,
+) -> Result<(), zbus::Error> {
+    if let Some(provider) = remove_mirror {
+        proxy.remove_mirror_sink(provider.name()).await?;
+        info!("Stopped replicating to {:?}", provider.name());
+    }
+
+    for status in proxy.sink_health().await? {
+        let last_success = Some(status.last_success)
+            .filter(|t| *t > 0)
+            .and_then(|t| glib::DateTime::from_unix_local(t).ok())
+            .and_then(|t| t.format_iso8601().ok())
+            .map_or("never".to_string(), |t| t.to_string());
+
+        info!(
+            "{} ({}): {}, last successful sync: {}",
+            status.provider,
+            if status.primary { "primary" } else { "mirror" },
+            if status.healthy { "healthy" } else { "failing" },
+            last_success
+        );
+        if !status.last_error.is_empty() {
+            warn!("  {}", status.last_error);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn plan(proxy: &DaemonProxy<'_>) -> Result<(), zbus::Error> {
+    let steps = proxy.get_sync_plan().await?;
+    if steps.is_empty() {
+        info!("This device is up to date");
+    }
+
+    for step in steps {
+        if step.origin.is_empty() {
+            info!(
+                "{} {} ({} installation): {}",
+                step.action, step.name, step.installation, step.reason
+            );
+        } else {
+            info!(
+                "{} {} from {} ({} installation): {}",
+                step.action, step.name, step.origin, step.installation, step.reason
+            );
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn conflicts(proxy: &DaemonProxy<'_>) -> Result<(), zbus::Error> {
+    let conflicts = proxy.sync_conflicts().await?;
+    if conflicts.is_empty() {
+        info!("No conflicts during the latest sync");
+    }
+
+    for conflict in conflicts {
+        warn!(
+            "{} {} ({} installation): {}, kept the local version",
+            conflict.kind, conflict.name, conflict.installation, conflict.reason
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn devices(
+    proxy: &DaemonProxy<'_>,
+    rename: Option<String>,
+    refs: bool,
+) -> Result<(), zbus::Error> {
+    if let Some(name) = rename {
+        proxy.set_device_name(&name).await?;
+        info!("Renamed this device to {:?}", proxy.device_name().await?);
+    }
+
+    for device in proxy.devices().await? {
+        let last_seen = glib::DateTime::from_unix_local(device.last_seen)
+            .ok()
+            .and_then(|t| t.format_iso8601().ok())
+            .map_or("unknown".to_string(), |t| t.to_string());
+
+        info!(
+            "{}{} ({}, {}): {} refs, last seen: {}",
+            device.name,
+            if device.this_device {
+                " [this device]"
+            } else {
+                ""
+            },
+            device.hostname,
+            device.arch,
+            device.refs.len(),
+            last_seen
+        );
+        if refs {
+            for (installation, ref_) in device.refs {
+                info!("  {} ({})", ref_, installation);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn profiles(
+    proxy: &DaemonProxy<'_>,
+    profile: Option<String>,
+    add: Vec<String>,
+    remove: Vec<String>,
+    delete: bool,
+    subscribe: Option<Vec<String>>,
+) -> Result<(), zbus::Error> {
+    if let Some(profile) = profile {
+        for ref_ in add {
+            proxy.add_to_profile(&profile, &ref_).await?;
+            info!("Added {} to profile {:?}", ref_, profile);
+        }
+        for ref_ in remove {
+            proxy.remove_from_profile(&profile, &ref_).await?;
+            info!("Removed {} from profile {:?}", ref_, profile);
+        }
+        if delete {
+            proxy.delete_profile(&profile).await?;
+            info!("Deleted profile {:?}", profile);
+        }
+    }
+
+    if let Some(subscribe) = subscribe {
+        let subscribe = subscribe
+            .iter()
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+        proxy.set_subscribed_profiles(&subscribe).await?;
+    }
+
+    if proxy.subscribed_profiles().await?.is_empty() {
+        info!("This device syncs all refs");
+    }
+
+    for profile in proxy.profiles().await? {
+        info!(
+            "{}{}: {}",
+            profile.name,
+            if profile.subscribed {
+                " [subscribed]"
+            } else {
+                ""
+            },
+            profile.refs.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn filters(
+    proxy: &DaemonProxy<'_>,
+    add: Vec<String>,
+    remove: Vec<String>,
+    sync: Option<bool>,
 ) -> Result<(), zbus::Error> {
+    if let Some(sync) = sync {
+        proxy.set_sync_filter_rules(sync).await?;
+    }
+
+    if !add.is_empty() || !remove.is_empty() {
+        let mut rules = proxy.filter_rules().await?;
+        // Compare normalized rules, so that removing works regardless of spacing and condition order
+        let remove = remove
+            .iter()
+            .map(|rule| rule.parse::<FilterRule>().map(|r| r.to_string()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+        rules.retain(|rule| !remove.contains(rule));
+        rules.extend(add);
+
+        let rules = rules.iter().map(|r| r.as_str()).collect::<Vec<_>>();
+        proxy.set_filter_rules(&rules).await?;
+    }
+
+    if proxy.sync_filter_rules().await? {
+        info!("Filter rules are shared with the other devices syncing them");
+    }
+    for rule in proxy.filter_rules().await? {
+        info!("{}", rule);
+    }
+
+    Ok(())
+}
+
+pub async fn versions(
+    proxy: &DaemonProxy<'_>,
+    policy: Option<VersionPolicy>,
+    pin: Option<String>,
+    pin_policy: VersionPolicy,
+    commit: Option<String>,
+    unpin: Option<String>,
+    propagate_updates: Option<bool>,
+) -> Result<(), zbus::Error> {
+    if let Some(policy) = policy {
+        proxy.set_version_policy(policy.name()).await?;
+    }
+    if let Some(propagate) = propagate_updates {
+        proxy.set_propagate_updates(propagate).await?;
+    }
+    if let Some(ref_) = pin {
+        proxy
+            .pin_version(&ref_, pin_policy.name(), &commit.unwrap_or_default())
+            .await?;
+    }
+    if let Some(ref_) = unpin {
+        proxy.unpin_version(&ref_).await?;
+    }
+
+    info!("Version policy: {}", proxy.version_policy().await?);
+    if proxy.propagate_updates().await? {
+        info!("Installed refs are updated to the commit installed on the other devices");
+    }
+    for (ref_, policy, commit) in proxy.version_pins().await? {
+        if commit.is_empty() {
+            info!("{}: {}", ref_, policy);
+        } else {
+            info!("{}: {} ({})", ref_, policy, commit);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn remotes(
+    proxy: &DaemonProxy<'_>,
+    alias: Vec<String>,
+    unalias: Vec<String>,
+) -> Result<(), zbus::Error> {
+    if !alias.is_empty() || !unalias.is_empty() {
+        let mut aliases = proxy.remote_aliases().await?;
+        // A remote of other devices maps to a single local one, so new rules replace existing ones for the same remote
+        let shared_name = |rule: &str| {
+            rule.split('=')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_string()
+        };
+        let replaced = alias
+            .iter()
+            .map(|rule| shared_name(rule))
+            .collect::<Vec<_>>();
+        aliases.retain(|rule| {
+            let name = shared_name(rule);
+            !unalias.contains(&name) && !replaced.contains(&name)
+        });
+        aliases.extend(alias);
+
+        let aliases = aliases.iter().map(|a| a.as_str()).collect::<Vec<_>>();
+        proxy.set_remote_aliases(&aliases).await?;
+    }
+
+    let aliases = proxy.remote_aliases().await?;
+    if aliases.is_empty() {
+        info!("No remote aliases, remotes are matched by name, URL and collection ID");
+    }
+    for alias in aliases {
+        info!("{}", alias);
+    }
+
+    Ok(())
+}
+
+pub async fn pending(
+    proxy: &DaemonProxy<'_>,
+    approve: Vec<u32>,
+    reject: Vec<u32>,
+    approve_all: bool,
+    reject_all: bool,
+    mode: Option<SyncMode>,
+) -> Result<(), zbus::Error> {
+    if let Some(mode) = mode {
+        proxy.set_sync_mode(mode.name()).await?;
+    }
+
+    let rejected = if reject_all {
+        Some(proxy.reject_all_pending().await?)
+    } else if !reject.is_empty() {
+        Some(proxy.reject_pending(&reject).await?)
+    } else {
+        None
+    };
+    if let Some(rejected) = rejected {
+        info!("Rejected {} change(s)", rejected);
+    }
+
+    let approved = if approve_all {
+        Some(proxy.approve_all_pending().await?)
+    } else if !approve.is_empty() {
+        Some(proxy.approve_pending(&approve).await?)
+    } else {
+        None
+    };
+    if let Some(approved) = approved {
+        info!(
+            "Approved {} change(s), they are applied during the next sync",
+            approved
+        );
+    }
+
+    info!("Sync mode: {}", proxy.sync_mode().await?);
+    let changes = proxy.pending_changes().await?;
+    if changes.is_empty() {
+        info!("No changes are waiting for approval");
+    }
+
+    for change in changes {
+        let step = change.step;
+        info!(
+            "[{}] {} {} ({} installation): {}",
+            change.id, step.action, step.name, step.installation, step.reason
+        );
+    }
+
+    Ok(())
+}
+
+pub async fn brake(
+    proxy: &DaemonProxy<'_>,
+    confirm: bool,
+    max_uninstalls: Option<u32>,
+    max_percentage: Option<u32>,
+) -> Result<(), zbus::Error> {
+    if max_uninstalls.is_some() || max_percentage.is_some() {
+        let (count, percentage) = proxy.uninstall_limits().await?;
+        proxy
+            .set_uninstall_limits(
+                max_uninstalls.unwrap_or(count),
+                max_percentage.unwrap_or(percentage),
+            )
+            .await?;
+    }
+
+    if confirm {
+        if proxy.confirm_uninstalls().await? {
+            info!("Confirmed, the stopped sync proceeds now");
+        } else {
+            info!("No sync was stopped, there is nothing to confirm");
+        }
+        return Ok(());
+    }
+
+    let (count, percentage) = proxy.uninstall_limits().await?;
+    info!(
+        "Syncs may uninstall up to {} refs and {}% of the installed ones without confirmation (0 means no limit)",
+        count, percentage
+    );
+
+    let uninstalls = proxy.held_uninstalls().await?;
+    if uninstalls.is_empty() {
+        info!("No sync is waiting for confirmation");
+    } else {
+        warn!(
+            "The latest sync was stopped, it would uninstall {} ref(s). Run with `--confirm` to proceed:",
+            uninstalls.len()
+        );
+    }
+    for step in uninstalls {
+        info!(
+            "{} {} ({} installation): {}",
+            step.action, step.name, step.installation, step.reason
+        );
+    }
+
+    Ok(())
+}
+
+async fn init_for_github(sink: &InitSink<'_, '_>, args: InitArgs) -> Result<(), zbus::Error> {
+    let mut config = match &args.instance_url {
+        Some(instance_url) => {
+            let Some(client_id) = &args.client_id else {
+                error!("Please register FlatSync as a GitHub App with the \"Gists\" permission on your GitHub Enterprise Server and pass its client ID via `--client-id`.");
+                return Err(zbus::Error::Failure("No client ID given".into()));
+            };
+            GitHubConfig::for_instance(instance_url, client_id)
+        }
+        None => GitHubConfig::default(),
+    };
+    if let Some(client_id) = args.client_id {
+        config.client_id = client_id;
+    }
+    if let Some(api_url) = args.api_url {
+        config.api_url = api_url;
+    }
+    if let Some(device_auth_url) = args.device_auth_url {
+        config.device_auth_url = device_auth_url;
+    }
+    if let Some(token_url) = args.token_url {
+        config.token_url = token_url;
+    }
+
     // Initialize OAuth Device Flow
-    let github = GitHubProvider::new();
+    let github =
+        GitHubProvider::with_config(&config).map_err(|e| zbus::Error::Failure(e.to_string()))?;
 
     let device_auth_res = github.device_code().await.unwrap();
 
-    error!("Please install FlatSync as a GitHub app to your account **first** by following this link: {:?}.\nThis is required because GitHub's API doesn't allow us to interact with Gists without additional permission via a GitHub App installation.", GH_APP_INSTALLATION_URL);
+    if config == GitHubConfig::default() {
+        error!("Please install FlatSync as a GitHub app to your account **first** by following this link: {:?}.\nThis is required because GitHub's API doesn't allow us to interact with Gists without additional permission via a GitHub App installation.", GH_APP_INSTALLATION_URL);
+    } else {
+        error!("Please install your FlatSync GitHub App to your account **first**, as GitHub's API doesn't allow us to interact with Gists without it.");
+    }
 
     error!(
         "Afterwards, please visit {:?} and enter the following code: {:?}",
@@ -71,15 +825,18 @@ async fn init_for_github(
         &device_auth_res.user_code().secret().to_string()
     );
 
-    let token_pair =
-        serde_json::to_string(&github.register_device(device_auth_res).await.unwrap()).unwrap();
+    // The endpoints are stored along with the tokens, as they belong to the account
+    let account = GitHubAccount {
+        tokens: github.register_device(device_auth_res).await.unwrap(),
+        config,
+    };
 
-    proxy.set_gist_secret(token_pair.as_str()).await?;
+    sink.set_secret(serde_json::to_string(&account).unwrap().as_str())
+        .await?;
 
-    if let Some(id) = gist_id {
-        proxy.set_gist_id(id.as_ref()).await?;
-    } else {
-        let id = proxy.create_gist().await?;
+    if let Some(id) = args.gist_id {
+        sink.join(id.as_ref(), args.join).await?;
+    } else if let Some(id) = sink.create().await? {
         info!(
             "Successfully created a Flatsync list with GitHub Gist ID: {:?}",
             id
@@ -89,6 +846,285 @@ async fn init_for_github(
     Ok(())
 }
 
+async fn init_for_gitlab(
+    sink: &InitSink<'_, '_>,
+    instance_url: String,
+    client_id: Option<String>,
+    snippet_id: Option<String>,
+    join: JoinStrategy,
+) -> Result<(), zbus::Error> {
+    let Some(client_id) = client_id else {
+        error!("Please register FlatSync as an OAuth application with the `api` scope on your GitLab instance and pass its application ID via `--client-id`.");
+        return Err(zbus::Error::Failure("No client ID given".into()));
+    };
+
+    let settings = gio::Settings::new(APP_ID);
+    settings
+        .set_string("gitlab-instance-url", &instance_url)
+        .and_then(|_| settings.set_string("gitlab-client-id", &client_id))
+        .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+
+    // Initialize OAuth Device Flow
+    let gitlab = GitLabProvider::new(&instance_url, &client_id)
+        .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+
+    let device_auth_res = gitlab
+        .device_code()
+        .await
+        .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+
+    error!(
+        "Please visit {:?} and enter the following code: {:?}",
+        &device_auth_res.verification_uri().to_string(),
+        &device_auth_res.user_code().secret().to_string()
+    );
+
+    let token_pair = gitlab
+        .register_device(device_auth_res)
+        .await
+        .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+    let token_pair = serde_json::to_string(&token_pair).unwrap();
+
+    sink.set_secret(token_pair.as_str()).await?;
+
+    if let Some(id) = snippet_id {
+        sink.join(id.as_ref(), join).await?;
+    } else if let Some(id) = sink.create().await? {
+        info!(
+            "Successfully created a Flatsync list with GitLab Snippet ID: {:?}",
+            id
+        );
+    }
+
+    Ok(())
+}
+
+async fn init_for_webdav(
+    sink: &InitSink<'_, '_>,
+    url: Option<String>,
+    username: Option<String>,
+    join: JoinStrategy,
+) -> Result<(), zbus::Error> {
+    let (Some(url), Some(username)) = (url, username) else {
+        error!("Please pass the URL of the WebDAV directory via `--url` and your user name via `--username`.");
+        return Err(zbus::Error::Failure("No URL or user name given".into()));
+    };
+
+    let settings = gio::Settings::new(APP_ID);
+    settings
+        .set_string("webdav-url", &url)
+        .and_then(|_| settings.set_string("webdav-username", &username))
+        .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+
+    // Nextcloud and ownCloud users should use an app password here
+    error!("Please enter the (app) password for {:?}:", username);
+    let mut password = String::new();
+    std::io::stdin().read_line(&mut password)?;
+
+    sink.set_secret(password.trim_end()).await?;
+    // Joins the list if the directory already contains one
+    if let Some(id) = sink.create_or_join(join).await? {
+        info!("Successfully set up syncing with {:?}", id);
+    }
+
+    Ok(())
+}
+
+async fn init_for_s3(sink: &InitSink<'_, '_>, args: InitArgs) -> Result<(), zbus::Error> {
+    let (Some(endpoint), Some(bucket), Some(access_key_id)) =
+        (args.endpoint, args.bucket, args.access_key_id)
+    else {
+        error!("Please pass the endpoint URL via `--endpoint`, the bucket via `--bucket` and your access key ID via `--access-key-id`.");
+        return Err(zbus::Error::Failure(
+            "No endpoint, bucket or access key given".into(),
+        ));
+    };
+
+    let settings = gio::Settings::new(APP_ID);
+    let mut values = vec![("s3-endpoint", endpoint), ("s3-bucket", bucket)];
+    if let Some(prefix) = args.prefix {
+        values.push(("s3-prefix", prefix));
+    }
+    if let Some(region) = args.region {
+        values.push(("s3-region", region));
+    }
+    for (key, value) in values {
+        settings
+            .set_string(key, &value)
+            .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+    }
+
+    error!(
+        "Please enter the secret access key for {:?}:",
+        access_key_id
+    );
+    let mut secret_access_key = String::new();
+    std::io::stdin().read_line(&mut secret_access_key)?;
+
+    let credentials = serde_json::json!({
+        "access_key_id": access_key_id,
+        "secret_access_key": secret_access_key.trim_end(),
+    });
+    sink.set_secret(&credentials.to_string()).await?;
+    // Joins the list if the bucket already contains one
+    if let Some(id) = sink.create_or_join(args.join).await? {
+        info!("Successfully set up syncing with object {:?}", id);
+    }
+
+    Ok(())
+}
+
+async fn init_for_flatsync_server(
+    sink: &InitSink<'_, '_>,
+    url: Option<String>,
+    join: JoinStrategy,
+) -> Result<(), zbus::Error> {
+    let Some(url) = url else {
+        error!("Please pass the URL of the FlatSync server via `--url`.");
+        return Err(zbus::Error::Failure("No URL given".into()));
+    };
+
+    gio::Settings::new(APP_ID)
+        .set_string("flatsync-server-url", &url)
+        .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+
+    error!("Please enter the access token printed by `flatsync-server add-account`:");
+    let mut token = String::new();
+    std::io::stdin().read_line(&mut token)?;
+
+    sink.set_secret(token.trim()).await?;
+    if let Some(id) = sink.create_or_join(join).await? {
+        info!("Successfully registered this device as {:?}", id);
+    }
+
+    Ok(())
+}
+
+async fn init_for_forgejo(sink: &InitSink<'_, '_>, args: InitArgs) -> Result<(), zbus::Error> {
+    let Some(repository) = args.repository else {
+        error!(
+            "Please pass the repository to store the list in as `owner/name` via `--repository`."
+        );
+        return Err(zbus::Error::Failure("No repository given".into()));
+    };
+
+    let settings = gio::Settings::new(APP_ID);
+    let mut values = vec![("forgejo-repository", repository)];
+    if let Some(instance_url) = args.instance_url {
+        values.push(("forgejo-instance-url", instance_url));
+    }
+    if let Some(branch) = args.branch {
+        values.push(("forgejo-branch", branch));
+    }
+    if let Some(client_id) = args.client_id {
+        values.push(("forgejo-client-id", client_id));
+    }
+    for (key, value) in values {
+        settings
+            .set_string(key, &value)
+            .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+    }
+
+    // Either a plain access token or an OAuth token pair serialized as JSON
+    error!("Please enter an access token with the `write:repository` scope:");
+    let mut token = String::new();
+    std::io::stdin().read_line(&mut token)?;
+
+    sink.set_secret(token.trim()).await?;
+    // Joins the list if the repository already contains one
+    if let Some(id) = sink.create_or_join(args.join).await? {
+        info!("Successfully set up syncing with repository {:?}", id);
+    }
+
+    Ok(())
+}
+
+async fn init_for_lan(sink: &InitSink<'_, '_>, join: JoinStrategy) -> Result<(), zbus::Error> {
+    let id = match sink.create_or_join(join).await {
+        Ok(Some(id)) => id,
+        Ok(None) => return Ok(()),
+        // Re-running `init` keeps the device ID we are already paired under
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "app.drey.FlatSync.Daemon.Error.GistAlreadyInitialized" =>
+        {
+            info!("LAN sync is already set up");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+
+    info!("Successfully set up LAN sync with device ID {:?}", id);
+    info!("Run `flatsync-cli pair` here and `flatsync-cli pair --code <code>` on another device to pair them.");
+
+    Ok(())
+}
+
+async fn init_for_local_directory(
+    sink: &InitSink<'_, '_>,
+    directory: Option<PathBuf>,
+    join: JoinStrategy,
+) -> Result<(), zbus::Error> {
+    let Some(directory) = directory else {
+        error!("Please pass the directory to keep the FlatSync list in via `--directory`.");
+        return Err(zbus::Error::Failure("No directory given".into()));
+    };
+
+    let directory = std::env::current_dir()?.join(directory);
+    sink.join(directory.to_string_lossy().as_ref(), join)
+        .await?;
+
+    if directory.join("flatsync.json").exists() {
+        info!(
+            "Found an existing FlatSync list in {:?}, it will be synced shortly",
+            directory
+        );
+    } else if sink.create().await?.is_some() {
+        info!("Successfully created a FlatSync list in {:?}", directory);
+    }
+
+    Ok(())
+}
+
+async fn init_for_git(
+    sink: &InitSink<'_, '_>,
+    repository: Option<String>,
+    branch: Option<String>,
+    join: JoinStrategy,
+) -> Result<(), zbus::Error> {
+    let Some(repository) = repository else {
+        error!("Please pass the URL of the git repository to use via `--repository`.");
+        return Err(zbus::Error::Failure("No repository given".into()));
+    };
+
+    // The git sink takes the repository as its ID, which creating the list needs
+    let settings = gio::Settings::new(APP_ID);
+    settings
+        .set_string("git-repository-id", &repository)
+        .and_then(|_| match branch {
+            Some(branch) => settings.set_string("git-repository-branch", &branch),
+            None => Ok(()),
+        })
+        .map_err(|e| zbus::Error::Failure(e.to_string()))?;
+
+    // Joins the list if the repository already contains one
+    match sink.create_or_join(join).await {
+        Ok(Some(_)) => info!(
+            "Successfully set up syncing with git repository {:?}",
+            repository
+        ),
+        Ok(None) => {}
+        // Re-running `init` finds the list we already synced with in the checkout
+        Err(zbus::Error::MethodError(name, _, _))
+            if name.as_str() == "app.drey.FlatSync.Daemon.Error.GistAlreadyInitialized" =>
+        {
+            info!("Already syncing with git repository {:?}", repository);
+        }
+        Err(e) => return Err(e),
+    }
+
+    Ok(())
+}
+
 pub fn handle_daemon_error(error: zbus::Error) {
     error!("Something Went Wrong, is the Daemon running?\n {}", error);
     process::exit(1);
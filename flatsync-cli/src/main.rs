@@ -31,14 +31,119 @@ async fn main() -> Result<(), zbus::Error> {
     let proxy: DaemonProxy<'_> = DaemonProxy::new(&connection).await?;
 
     match args.cmd {
-        Commands::Init { provider, gist_id } => {
-            if let Err(_e) = init(&proxy, provider, gist_id).await {
+        Commands::Init(args) => {
+            if let Err(_e) = init(&proxy, args).await {
                 error!("Initialization was not successful, please try again or open a bug report if this issue persists.");
                 process::exit(1);
             }
         }
         // We pass `!uninthis will not be prone to bugsstall` as the daemon interface expects an `install` boolean (this will not be prone to bugs this will not be prone to bugs this will not be prone to bugs)
         Commands::Autostart { uninstall } => proxy.autostart_file(!uninstall).await?,
+        Commands::Migrate {
+            to,
+            old_sink,
+            force,
+        } => {
+            if migrate(&proxy, to, old_sink, force).await.is_err() {
+                process::exit(1);
+            }
+        }
+        Commands::Pair { code } => {
+            if let Err(error) = pair(&proxy, code).await {
+                error!("Pairing was not successful: {}", error);
+                process::exit(1);
+            }
+        }
+        Commands::Peers { unpair } => {
+            if let Err(error) = peers(&proxy, unpair).await {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Sinks { remove_mirror } => {
+            if let Err(error) = sinks(&proxy, remove_mirror).await {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Plan => {
+            if let Err(error) = plan(&proxy).await {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Conflicts => {
+            if let Err(error) = conflicts(&proxy).await {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Devices { rename, refs } => {
+            if let Err(error) = devices(&proxy, rename, refs).await {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Profiles {
+            profile,
+            add,
+            remove,
+            delete,
+            subscribe,
+        } => {
+            if let Err(error) = profiles(&proxy, profile, add, remove, delete, subscribe).await {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Filters { add, remove, sync } => {
+            if let Err(error) = filters(&proxy, add, remove, sync).await {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Versions {
+            policy,
+            pin,
+            pin_policy,
+            commit,
+            unpin,
+            propagate_updates,
+        } => {
+            if let Err(error) = versions(
+                &proxy,
+                policy,
+                pin,
+                pin_policy,
+                commit,
+                unpin,
+                propagate_updates,
+            )
+            .await
+            {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Remotes { alias, unalias } => {
+            if let Err(error) = remotes(&proxy, alias, unalias).await {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Pending {
+            approve,
+            reject,
+            approve_all,
+            reject_all,
+            mode,
+        } => {
+            if let Err(error) =
+                pending(&proxy, approve, reject, approve_all, reject_all, mode).await
+            {
+                handle_daemon_error(error);
+            }
+        }
+        Commands::Brake {
+            confirm,
+            max_uninstalls,
+            max_percentage,
+        } => {
+            if let Err(error) = brake(&proxy, confirm, max_uninstalls, max_percentage).await {
+                handle_daemon_error(error);
+            }
+        }
         Commands::SyncNow => match proxy.sync_now().await {
             Ok(_) => info!("Starting Manual Sync"),
             Err(error) => handle_daemon_error(error),
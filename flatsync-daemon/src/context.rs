@@ -1,16 +1,37 @@
-use crate::Error;
+use crate::{
+    device, filters,
+    merge::{self, Merge},
+    pending,
+    remotes::RemoteResolver,
+    safety_brake,
+    settings::Settings,
+    sync_plan::{InstallationPlan, PlannedChange, SyncPlan},
+    versions, Error,
+};
 use diff::Diff;
 use libflatpak::{gio, prelude::*};
 use libflatsync_common::{
-    FlatpakInstallationKind, FlatpakInstallationPayload, FlatpakRef, FlatpakRemote,
+    FlatpakInstallationKind, FlatpakInstallationPayload, FlatpakRef, FlatpakRemote, VersionPolicy,
 };
 use log::{debug, trace};
-use std::{collections::HashSet, path::PathBuf};
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+/// How long a remote is taken not to offer a ref for this device's architecture before asking it again.
+const NOT_OFFERED_RECHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
 
 /// ## `Context`
 /// Holds variables that are used throughout the daemon's lifetime.
 pub struct Context {
     local_installations: FlatpakInstallationPayload,
+    /// The local installations as of the last successful sync, which serve as the common ancestor when merging.
+    base: Option<FlatpakInstallationPayload>,
+    /// Refs their remote was found not to offer, by installation, remote and full ref, with the time of the lookup.
+    not_offered: RefCell<HashMap<(FlatpakInstallationKind, String, String), Instant>>,
 }
 
 impl Context {
@@ -25,8 +46,13 @@ impl Context {
                     .map_err(Error::FlatpakInstallationQueryFailure)?,
             };
 
+        // Missing after upgrading from a version without three-way merges, in which case the first merge won't remove anything
+        let base = FlatpakInstallationPayload::new_from_file(Self::get_base_file()).ok();
+
         Ok(Self {
             local_installations,
+            base,
+            not_offered: RefCell::default(),
         })
     }
 
@@ -84,20 +110,202 @@ impl Context {
         !diff.0.altered.is_empty() || !diff.0.removed.is_empty()
     }
 
-    pub fn local_altered_at(&self) -> chrono::DateTime<chrono::Utc> {
-        self.local_installations.altered_at
+    pub fn local_installations(&self) -> &FlatpakInstallationPayload {
+        &self.local_installations
+    }
+
+    /// ## `merge()`
+    /// Merges our local installations with the given remote payload, using the state of the last successful sync as their common ancestor.
+    ///
+    /// See `merge::three_way_merge()` for details.
+    pub fn merge(&self, remote: &FlatpakInstallationPayload) -> Merge {
+        // Refs excluded by the filter rules are treated as if they weren't installed here, so they neither spread to nor get removed from other devices
+        let base = self.base.as_ref().map(filters::filtered);
+        let local = filters::filtered(&self.local_installations);
+
+        merge::three_way_merge(base.as_ref(), &local, remote)
+    }
+
+    /// ## `mark_synced()`
+    /// Remembers the current local installations as the base of the next merge.
+    /// Only the refs the filter rules in effect allow are remembered, so that refs allowed by a later rule change are
+    /// taken for local additions rather than for refs the other devices removed.
+    ///
+    /// This is called once the local installations and the remote have been reconciled.
+    pub fn mark_synced(&mut self) -> Result<(), Error> {
+        let base = filters::filtered(&self.local_installations);
+        base.write_to_file(&Self::get_base_file())
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+        self.base = Some(base);
+        Ok(())
     }
 
-    pub fn sync_to_system(&mut self, remote: &FlatpakInstallationPayload) -> Result<(), Error> {
-        self.install_to_system(remote)?;
-        self.uninstall_from_system(remote)?;
+    /// ## `forget_base()`
+    /// Drops the state of the last sync, so that the next merge doesn't remove anything, like when syncing for the first time.
+    pub fn forget_base(&mut self) -> Result<(), Error> {
+        match std::fs::remove_file(Self::get_base_file()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        self.base = None;
+        Ok(())
+    }
 
-        let mut local = FlatpakInstallationPayload::new_from_system()
+    /// ## `plan()`
+    /// Computes the changes needed to bring the local installations in line with the given payload, without making them.
+    ///
+    /// Remotes of the payload are matched to local ones by name, collection ID or URL, see `RemoteResolver`. Refs are installed from the matching local remote.
+    /// Remotes of the payload missing locally are added and the ones configured differently are modified, its refs missing locally are installed
+    /// and the local refs and remotes it doesn't contain are uninstalled and removed. Remotes which refs are still installed from are kept.
+    /// Only the refs allowed by the filter rules are touched, and if this device subscribed to profiles, only the ones in any of them.
+    /// Refs are installed at the version their pin in the payload, or otherwise the `version-policy` setting, asks for.
+    /// If the `propagate-updates` setting is on, installed refs are updated to the commit of the payload, or the one they are pinned to.
+    ///
+    /// Refs are matched by their kind, ID and branch and installed for the architecture of this device. Refs the remote doesn't offer for it
+    /// are reported as skipped.
+    pub fn plan(&self, remote: &FlatpakInstallationPayload) -> Result<SyncPlan, Error> {
+        let system = FlatpakInstallationPayload::new_from_system()
+            .map_err(Error::FlatpakInstallationQueryFailure)?;
+
+        let rules = filters::active_rules();
+        let profiles: Vec<String> = Settings::instance().get("subscribed-profiles");
+        let in_scope = |kind: FlatpakInstallationKind, ref_: &FlatpakRef| {
+            rules.allows(kind, ref_) && remote.in_profiles(&profiles, ref_)
+        };
+
+        let propagate_updates = versions::propagate_updates();
+
+        let mut plan = SyncPlan::default();
+        for kind in [
+            FlatpakInstallationKind::System,
+            FlatpakInstallationKind::User,
+        ] {
+            plan.installations.insert(
+                kind,
+                self.plan_for_kind(remote, &system, kind, &in_scope, propagate_updates)?,
+            );
+        }
+
+        Ok(plan)
+    }
+
+    /// ## `sync_plan()`
+    /// Computes the changes a sync to the given payload makes, which are the ones of `plan()` the sync isn't held up by.
+    ///
+    /// Updates are deferred while the network is metered or Power Saver is enabled, as they are large downloads nobody asked for.
+    /// Changes waiting for the user's approval are held back, see `pending::hold_back()`, and plans uninstalling more refs than
+    /// the limits allow are stopped with `Error::UninstallLimitExceeded`, see `safety_brake::check()`.
+    /// Both only remember what they held back or stopped if `record` is set, which previews of the sync don't.
+    ///
+    /// # Returns
+    /// The plan, along with the number of changes newly held back for approval.
+    pub fn sync_plan(
+        &self,
+        remote: &FlatpakInstallationPayload,
+        record: bool,
+    ) -> Result<(SyncPlan, usize), Error> {
+        let mut plan = self.plan(remote)?;
+
+        if gio::NetworkMonitor::default().is_network_metered()
+            || gio::PowerProfileMonitor::get_default().is_power_saver_enabled()
+        {
+            plan.retain(|step| {
+                let deferred = step.action == "update";
+                if deferred {
+                    debug!("Deferring update of {} until the next sync", step.name);
+                }
+                !deferred
+            });
+        }
+
+        let newly_pending = pending::hold_back(&mut plan, record)?;
+
+        // a truncated or foreign payload would make us uninstall everything, so large removals need confirmation
+        let installed = filters::filtered(&self.local_installations)
+            .installations
+            .0
+            .values()
+            .map(|installation| installation.refs.len())
+            .sum();
+        safety_brake::check(&plan, installed, record)?;
+
+        Ok((plan, newly_pending))
+    }
+
+    /// ## `apply()`
+    /// Makes the changes of a plan computed by `plan()` and refreshes the local installations afterwards.
+    ///
+    /// Remotes are added and modified and refs installed in all installations before any ref is uninstalled or remote removed.
+    /// A failing change, e.g. of a ref which reached its end of life, is logged and skipped, so that it doesn't hold up the others.
+    /// As it isn't reflected by the refreshed local installations, the next sync retries it.
+    ///
+    /// # Returns
+    /// The errors of the changes which failed.
+    pub fn apply(&mut self, plan: &SyncPlan) -> Result<Vec<Error>, Error> {
+        let mut failures = vec![];
+        let mut attempt = |res: Result<(), Error>| {
+            if let Err(e) = res {
+                log::warn!("{}", e);
+                failures.push(e);
+            }
+        };
+
+        for (kind, changes) in &plan.installations {
+            let installation = Self::get_user_or_system_installation(*kind);
+
+            for change in &changes.add_remotes {
+                attempt(self.add_remote(&change.item, &installation));
+            }
+
+            for change in &changes.modify_remotes {
+                log::trace!("Modifying remote {} ({})", change.item.name, change.reason);
+                attempt(self.modify_remote(&change.item, &installation));
+            }
+
+            for remote in &changes.refresh_remotes {
+                if let Err(e) = installation.update_remote_sync(remote, gio::Cancellable::NONE) {
+                    log::warn!(
+                        "Couldn't refresh remote {}, installing from its cached metadata: {}",
+                        remote,
+                        e
+                    );
+                }
+            }
+
+            for change in &changes.install {
+                log::trace!("Installing ref {} ({})", change.item.ref_, change.reason);
+                attempt(self.install_ref(&installation, *kind, &change.item));
+            }
+
+            for change in &changes.update {
+                log::trace!(
+                    "Updating ref {} to {} ({})",
+                    change.item.ref_,
+                    change.item.commit,
+                    change.reason
+                );
+                attempt(self.update_ref(&installation, *kind, &change.item));
+            }
+        }
+
+        for (kind, changes) in &plan.installations {
+            let installation = Self::get_user_or_system_installation(*kind);
+
+            for change in &changes.uninstall {
+                log::trace!("Uninstalling ref {} ({})", change.item.ref_, change.reason);
+                attempt(self.uninstall_ref(&installation, *kind, &change.item));
+            }
+
+            for change in &changes.remove_remotes {
+                attempt(self.remove_remote(&change.item, &installation));
+            }
+        }
+
+        let local = FlatpakInstallationPayload::new_from_system()
             .map_err(Error::FlatpakInstallationQueryFailure)?;
-        local.altered_at = remote.altered_at;
         log::debug!("Done updating local state, refreshing cache");
         self.set_cache_and_file(local)?;
-        Ok(())
+        Ok(failures)
     }
 
     /// ## `init_local_installations_file()`
@@ -146,6 +354,13 @@ impl Context {
         flatsync_user_data_file
     }
 
+    fn get_base_file() -> PathBuf {
+        let mut base_file = Self::get_user_flatsync_dir();
+        base_file.push("base.json");
+
+        base_file
+    }
+
     fn get_user_flatsync_dir() -> PathBuf {
         let mut flatsync_user_data_dir = glib::user_data_dir();
         flatsync_user_data_dir.push("flatsync");
@@ -161,16 +376,6 @@ impl Context {
         Ok(())
     }
 
-    fn is_installed(&self, kind: FlatpakInstallationKind, id: &str) -> Result<bool, Error> {
-        let binding = FlatpakInstallationPayload::new_from_system().unwrap();
-        let apps = match binding.installations(kind) {
-            Some(v) => v,
-            None => return Err(Error::FlatpakNoSuchInstallation),
-        };
-
-        Ok(apps.refs.iter().any(|ref_| ref_.ref_ == id))
-    }
-
     fn run_in_transaction(
         &self,
         installation: &libflatpak::Installation,
@@ -205,6 +410,7 @@ impl Context {
             .map_err(|e| Error::FlatpakTransactionFailure(e.to_string()))
     }
 
+    /// Installs the ref at its `commit`, or at the latest one if it is empty.
     fn install_ref(
         &self,
         installation: &libflatpak::Installation,
@@ -220,6 +426,10 @@ impl Context {
                 ));
             }
 
+            if ref_.commit.is_empty() {
+                return Ok(());
+            }
+
             if let Err(e) = transaction.add_update(&ref_.ref_, &[], Some(&ref_.commit)) {
                 log::error!(
                     "Couldn't select the commit {}, falling back to latest: {}",
@@ -233,6 +443,27 @@ impl Context {
         })
     }
 
+    fn update_ref(
+        &self,
+        installation: &libflatpak::Installation,
+        kind: FlatpakInstallationKind,
+        ref_: &FlatpakRef,
+    ) -> Result<(), Error> {
+        self.run_in_transaction(installation, kind, ref_, |transaction| {
+            if let Err(e) = transaction.add_update(&ref_.ref_, &[], Some(&ref_.commit)) {
+                log::error!(
+                    "Couldn't update reference {} to commit {}: {}",
+                    ref_.ref_,
+                    ref_.commit,
+                    e
+                );
+                return Err(Error::FlatpakUpdateFailed(ref_.ref_.clone(), e.to_string()));
+            }
+
+            Ok(())
+        })
+    }
+
     fn uninstall_ref(
         &self,
         installation: &libflatpak::Installation,
@@ -258,7 +489,7 @@ impl Context {
         installation: &libflatpak::Installation,
     ) -> Result<(), Error> {
         // No need to install local repositories...
-        if let Some(true) = remote.url.as_ref().map(|u| u.starts_with("file://")) {
+        if Self::is_local_remote(remote) {
             return Ok(());
         }
 
@@ -278,6 +509,65 @@ impl Context {
         Ok(())
     }
 
+    fn modify_remote(
+        &self,
+        remote: &FlatpakRemote,
+        installation: &libflatpak::Installation,
+    ) -> Result<(), Error> {
+        log::debug!("Modifying remote {}", &remote.name);
+
+        let flatpak_remote = installation
+            .remote_by_name(&remote.name, gio::Cancellable::NONE)
+            .map_err(|e| Error::FlatpakRemoteModifyFailed(remote.name.clone(), e.to_string()))?;
+        // Only what is compared by `changed_remote_attributes()` is taken over, e.g. GPG verification stays as it is
+        if let Some(url) = &remote.url {
+            flatpak_remote.set_url(url);
+        }
+        if let Some(title) = &remote.title {
+            flatpak_remote.set_title(title);
+        }
+        flatpak_remote.set_collection_id(remote.collection_id.as_deref());
+        flatpak_remote.set_prio(remote.prio);
+
+        installation
+            .modify_remote(&flatpak_remote, gio::Cancellable::NONE)
+            .map_err(|e| Error::FlatpakRemoteModifyFailed(remote.name.clone(), e.to_string()))?;
+        installation
+            .update_remote_sync(&remote.name, gio::Cancellable::NONE)
+            .map_err(|e| Error::FlatpakRemoteRefreshFailed(remote.name.clone(), e.to_string()))?;
+
+        Ok(())
+    }
+
+    fn remove_remote(
+        &self,
+        remote: &FlatpakRemote,
+        installation: &libflatpak::Installation,
+    ) -> Result<(), Error> {
+        // The plan made sure no refs are left, but their uninstallation might have failed or been held back for approval
+        let in_use = installation
+            .list_installed_refs(gio::Cancellable::NONE)
+            .map(|refs| {
+                refs.iter().any(|ref_| {
+                    ref_.origin()
+                        .map_or(false, |origin| origin.as_str() == remote.name)
+                })
+            })
+            .unwrap_or(true);
+        if in_use {
+            log::warn!(
+                "Not removing remote {}, as refs are still installed from it",
+                &remote.name
+            );
+            return Ok(());
+        }
+
+        log::debug!("Removing remote {}", &remote.name);
+        installation
+            .remove_remote(&remote.name, gio::Cancellable::NONE)
+            .map_err(|e| Error::FlatpakRemoteRemoveFailed(remote.name.clone(), e.to_string()))
+    }
+
     fn get_user_install_path() -> PathBuf {
         let mut user_path = glib::home_dir();
         user_path.push(".local");
@@ -300,86 +590,312 @@ impl Context {
         }
     }
 
-    fn install_for_kind(
+    /// ## `plan_for_kind()`
+    /// Computes the changes to a single installation, see `plan()`.
+    fn plan_for_kind(
         &self,
         remote: &FlatpakInstallationPayload,
+        system: &FlatpakInstallationPayload,
         kind: FlatpakInstallationKind,
-    ) -> Result<(), Error> {
-        let remote_installations = match remote.installations(kind) {
-            Some(e) => Ok(e),
-            None => Err(Error::FlatpakNoSuchInstallation),
-        }?;
+        in_scope: &dyn Fn(FlatpakInstallationKind, &FlatpakRef) -> bool,
+        propagate_updates: bool,
+    ) -> Result<InstallationPlan, Error> {
+        let remote_installation = remote
+            .installations(kind)
+            .ok_or(Error::FlatpakNoSuchInstallation)?;
+        let local_installation = system
+            .installations(kind)
+            .ok_or(Error::FlatpakNoSuchInstallation)?;
 
+        let resolver =
+            RemoteResolver::new(&local_installation.remotes, &remote_installation.remotes);
+        // Compared by key only, a different commit or architecture on the remote doesn't mean it was removed there
+        let local_refs: HashSet<String> = local_installation
+            .refs
+            .iter()
+            .map(|ref_| ref_.sync_key())
+            .collect();
+        let remote_refs: HashSet<String> = remote_installation
+            .refs
+            .iter()
+            .map(|ref_| ref_.sync_key())
+            .collect();
+        let native_arch = device::native_arch();
         let installation = Self::get_user_or_system_installation(kind);
 
-        for remote in &remote_installations.remotes {
-            self.add_remote(remote, &installation)?;
+        let mut plan = InstallationPlan::default();
+
+        for flatpak_remote in &remote_installation.remotes {
+            // No need to add local repositories...
+            if Self::is_local_remote(flatpak_remote) {
+                continue;
+            }
+
+            match resolver.resolve(flatpak_remote) {
+                None => plan.add_remotes.push(PlannedChange {
+                    item: flatpak_remote.clone(),
+                    reason: "configured on another device".into(),
+                }),
+                Some(local_remote) if local_remote.name != flatpak_remote.name => {
+                    trace!(
+                        "Remote {} is configured as {} here, skipping",
+                        flatpak_remote.name,
+                        local_remote.name
+                    );
+                }
+                Some(local_remote) => {
+                    let changed = Self::changed_remote_attributes(local_remote, flatpak_remote);
+                    if !changed.is_empty() {
+                        plan.modify_remotes.push(PlannedChange {
+                            item: flatpak_remote.clone(),
+                            reason: format!("{} changed on another device", changed.join(", ")),
+                        });
+                    }
+                }
+            }
         }
 
-        for ref_ in &remote_installations.refs {
-            if self.is_installed(kind, &ref_.ref_)? {
+        for ref_ in &remote_installation.refs {
+            if !in_scope(kind, ref_) {
+                trace!("Ref {} is filtered out, skipping", ref_.ref_);
+                continue;
+            }
+            if local_refs.contains(&ref_.sync_key()) {
                 trace!("Ref {} is already installed, skipping", ref_.ref_);
                 continue;
             }
-            log::trace!("Installing ref {}", ref_.ref_);
-            self.install_ref(&installation, kind, ref_)?;
+
+            // Install the ref for our own architecture, which commits of the payload and pins don't apply to
+            let cross_arch = ref_.arch != native_arch;
+            let mut item = if cross_arch {
+                ref_.for_arch(&native_arch)
+            } else {
+                ref_.clone()
+            };
+            item.origin = resolver.resolve_origin(&ref_.origin);
+
+            if cross_arch {
+                if !local_installation
+                    .remotes
+                    .iter()
+                    .any(|r| r.name == item.origin)
+                {
+                    debug!(
+                        "Remote {} isn't configured yet, looking for {} on it next time",
+                        item.origin, item.ref_
+                    );
+                    continue;
+                }
+
+                match self.remote_offers(&installation, kind, &item) {
+                    Some(true) => {}
+                    Some(false) => {
+                        plan.skipped.push(PlannedChange {
+                            reason: format!(
+                                "{} doesn't offer it for {}, only installed for {} elsewhere",
+                                item.origin, native_arch, ref_.arch
+                            ),
+                            item,
+                        });
+                        continue;
+                    }
+                    None => continue,
+                }
+            }
+
+            // The commit of the payload is merely the one installed on another device, which is often an older one
+            match versions::policy_for(remote, ref_) {
+                (VersionPolicy::ExactCommit, Some(pinned)) if !cross_arch => {
+                    item.commit = pinned.into()
+                }
+                (VersionPolicy::ExactCommit, _) => {}
+                (VersionPolicy::Latest, _) => item.commit.clear(),
+                (VersionPolicy::NotOlderThanRemote, _) => {
+                    item.commit.clear();
+                    plan.refresh_remotes.insert(item.origin.clone());
+                }
+            }
+            plan.install.push(PlannedChange {
+                item,
+                reason: Self::install_reason(remote, kind, ref_),
+            });
         }
 
-        Ok(())
-    }
+        for ref_ in &local_installation.refs {
+            if in_scope(kind, ref_) && !remote_refs.contains(&ref_.sync_key()) {
+                plan.uninstall.push(PlannedChange {
+                    item: ref_.clone(),
+                    reason: "removed on another device".into(),
+                });
+            }
+        }
 
-    fn uninstall_for_kind(
-        &self,
-        remote: &FlatpakInstallationPayload,
-        kind: FlatpakInstallationKind,
-    ) -> Result<(), Error> {
-        let remote_installations_for_kind = match remote.installations(kind) {
-            Some(k) => k,
-            None => return Err(Error::FlatpakNoSuchInstallation),
-        };
+        if propagate_updates {
+            // Only refs of the same architecture, the commits of others don't exist here
+            let installed: HashMap<&str, &FlatpakRef> = local_installation
+                .refs
+                .iter()
+                .map(|ref_| (ref_.ref_.as_str(), ref_))
+                .collect();
+
+            for ref_ in &remote_installation.refs {
+                let Some(installed) = installed.get(ref_.ref_.as_str()) else {
+                    continue;
+                };
+                if !in_scope(kind, ref_) {
+                    continue;
+                }
 
-        let local_installations_for_kind = self
-            .local_installations
-            .installations(kind)
-            .ok_or(Error::FlatpakNoSuchInstallation)?;
+                let (commit, reason) = match versions::policy_for(remote, ref_) {
+                    (VersionPolicy::ExactCommit, Some(pinned)) => {
+                        (pinned.to_string(), format!("pinned to commit {}", pinned))
+                    }
+                    _ => (
+                        ref_.commit.clone(),
+                        "another device has a different version installed".into(),
+                    ),
+                };
+                if commit.is_empty() || commit == installed.commit {
+                    continue;
+                }
 
-        let remote_refs_temp: HashSet<FlatpakRef> = remote_installations_for_kind
-            .refs
-            .clone()
-            .into_iter()
-            .collect();
+                // Keep our origin, the ref might be installed from a remote called differently here
+                let mut item = (*installed).clone();
+                item.commit = commit;
+                plan.update.push(PlannedChange { item, reason });
+            }
+        }
 
-        let to_uninstall = local_installations_for_kind
-            .refs
+        let uninstalled: HashSet<&str> = plan
+            .uninstall
             .iter()
-            .filter(|ref_| !remote_refs_temp.contains(ref_))
-            .collect::<Vec<_>>();
-
-        let installation = Self::get_user_or_system_installation(kind);
+            .map(|change| change.item.ref_.as_str())
+            .collect();
+        for local_remote in &local_installation.remotes {
+            if Self::is_local_remote(local_remote) || resolver.is_shared(local_remote) {
+                continue;
+            }
 
-        for ref_ in to_uninstall {
-            if !self.is_installed(kind, &ref_.ref_)? {
-                log::trace!("Ref {} is already uninstalled, skipping", ref_.ref_);
+            // Flatpak refuses to remove remotes which refs are installed from, e.g. ones that aren't synced
+            let in_use = local_installation.refs.iter().any(|ref_| {
+                ref_.origin == local_remote.name && !uninstalled.contains(ref_.ref_.as_str())
+            });
+            if in_use {
+                debug!(
+                    "Keeping remote {}, refs are still installed from it",
+                    local_remote.name
+                );
                 continue;
             }
-            log::trace!("Uninstalling ref {}", ref_.ref_);
-            self.uninstall_ref(&installation, kind, ref_)?;
+
+            plan.remove_remotes.push(PlannedChange {
+                item: local_remote.clone(),
+                reason: "removed on another device".into(),
+            });
         }
 
-        Ok(())
+        Ok(plan)
     }
 
-    fn install_to_system(&mut self, remote: &FlatpakInstallationPayload) -> Result<(), Error> {
-        self.install_for_kind(remote, FlatpakInstallationKind::System)?;
-        self.install_for_kind(remote, FlatpakInstallationKind::User)?;
+    /// Whether the ref's origin offers it, looked up on the remote itself.
+    /// Refs it doesn't offer aren't looked up again for a day, so that every sync doesn't ask the remote anew.
+    ///
+    /// Returns `None` if we couldn't tell, e.g. because the remote isn't reachable.
+    fn remote_offers(
+        &self,
+        installation: &libflatpak::Installation,
+        kind: FlatpakInstallationKind,
+        ref_: &FlatpakRef,
+    ) -> Option<bool> {
+        let key = (kind, ref_.origin.clone(), ref_.ref_.clone());
+        if self
+            .not_offered
+            .borrow()
+            .get(&key)
+            .is_some_and(|looked_up| looked_up.elapsed() < NOT_OFFERED_RECHECK_INTERVAL)
+        {
+            return Some(false);
+        }
 
-        Ok(())
+        match installation.fetch_remote_ref_sync(
+            &ref_.origin,
+            ref_.kind.into(),
+            &ref_.id,
+            Some(&ref_.arch),
+            Some(&ref_.branch),
+            gio::Cancellable::NONE,
+        ) {
+            Ok(_) => {
+                self.not_offered.borrow_mut().remove(&key);
+                Some(true)
+            }
+            Err(e) if e.matches(libflatpak::Error::RefNotFound) => {
+                self.not_offered.borrow_mut().insert(key, Instant::now());
+                Some(false)
+            }
+            Err(e) => {
+                debug!(
+                    "Couldn't look up {} on remote {}, trying again next time: {}",
+                    ref_.ref_, ref_.origin, e
+                );
+                None
+            }
+        }
     }
 
-    fn uninstall_from_system(&self, remote: &FlatpakInstallationPayload) -> Result<(), Error> {
-        self.uninstall_for_kind(remote, FlatpakInstallationKind::System)?;
-        self.uninstall_for_kind(remote, FlatpakInstallationKind::User)?;
+    fn is_local_remote(remote: &FlatpakRemote) -> bool {
+        remote
+            .url
+            .as_ref()
+            .map_or(false, |u| u.starts_with("file://"))
+    }
 
-        Ok(())
+    /// Names the synced attributes in which the local version of a remote differs from the shared one.
+    ///
+    /// Attributes the shared version doesn't have are left alone, so that we don't try to unset them over and over.
+    fn changed_remote_attributes(
+        local: &FlatpakRemote,
+        shared: &FlatpakRemote,
+    ) -> Vec<&'static str> {
+        let mut changed = vec![];
+        if shared.url.is_some() && local.url != shared.url {
+            changed.push("URL");
+        }
+        if shared.title.is_some() && local.title != shared.title {
+            changed.push("title");
+        }
+        if local.prio != shared.prio {
+            changed.push("priority");
+        }
+        if local.collection_id != shared.collection_id {
+            changed.push("collection ID");
+        }
+        changed
+    }
+
+    /// Names the devices a ref is installed on, as far as the device registry of the payload knows.
+    fn install_reason(
+        remote: &FlatpakInstallationPayload,
+        kind: FlatpakInstallationKind,
+        ref_: &FlatpakRef,
+    ) -> String {
+        let key = ref_.sync_key();
+        let devices = remote
+            .devices
+            .values()
+            .filter(|device| {
+                device.refs.get(&kind).map_or(false, |refs| {
+                    refs.iter()
+                        .any(|r| FlatpakRef::sync_key_of(r).is_some_and(|k| k == key))
+                })
+            })
+            .map(|device| device.name.as_str())
+            .collect::<Vec<_>>();
+
+        if devices.is_empty() {
+            "installed on another device".into()
+        } else {
+            format!("installed on {}", devices.join(", "))
+        }
     }
 }
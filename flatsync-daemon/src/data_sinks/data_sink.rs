@@ -1,9 +1,10 @@
 use std::collections::HashMap;
 
-use crate::{settings::Settings, Error};
+use crate::{settings::Settings, Error, MessageType};
 use async_trait::async_trait;
 use libflatsync_common::FlatpakInstallationPayload;
 use log::debug;
+use tokio::sync::mpsc::Sender;
 
 pub static FILE_NAME: &str = "flatsync.json";
 
@@ -18,6 +19,8 @@ pub trait DataSink {
     async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error>;
     /// Update the data sink with the given payload.
     async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error>;
+    /// Delete the payload from the data sink, e.g. after migrating to another one.
+    async fn delete(&self) -> Result<(), Error>;
 
     fn is_initialised(&self) -> bool {
         !self.sink_id().is_empty()
@@ -65,6 +68,14 @@ pub trait DataSink {
         Ok(())
     }
 
+    /// Start watching the data sink for changes made by other devices, requesting a poll through `sender` once one arrives.
+    ///
+    /// This is called regularly, so implementations have to make sure not to set up a second watch for the same location.
+    /// Sinks which can't be watched simply rely on the autosync timer.
+    fn watch(&self, _sender: Sender<MessageType>) -> Result<(), Error> {
+        Ok(())
+    }
+
     async fn keyring(&self) -> oo7::Keyring {
         oo7::Keyring::new().await.unwrap()
     }
@@ -4,8 +4,6 @@ use libflatsync_common::providers::oauth_client::TokenPair;
 use std::collections::HashMap;
 
 pub enum SecretType {
-    // `Simple` never gets constructed, but we should keep it here to allow for a reference and future, less-specific implementations
-    #[allow(dead_code)]
     Simple(String),
     OAuth(TokenPair),
 }
@@ -30,6 +28,22 @@ pub trait DataSinkClient {
         Ok(std::str::from_utf8(&secret)?.to_string())
     }
 
+    /// Replaces the stored secret, e.g. after refreshing an OAuth token pair.
+    async fn set_secret_raw(&self, secret: &str) -> Result<(), Error> {
+        let keyring = self.keyring().await;
+        keyring.unlock().await?;
+        let name = self.sink_name();
+        keyring
+            .create_item(
+                &format!("{} token", name),
+                HashMap::from([("purpose", format!("{}-secret", name).as_ref())]),
+                secret,
+                true,
+            )
+            .await?;
+        Ok(())
+    }
+
     async fn secret(&self) -> Result<SecretType, Error> {
         Ok(SecretType::Simple(self.secret_raw().await?))
     }
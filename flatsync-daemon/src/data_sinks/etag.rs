@@ -0,0 +1,64 @@
+use crate::Error;
+use log::debug;
+use reqwest::header::{self, HeaderName};
+use std::sync::Mutex;
+
+/// What we know about the ETag of a payload stored as a single HTTP resource.
+#[derive(Debug, Default)]
+enum State {
+    /// We never fetched the payload, or found it missing.
+    #[default]
+    Unknown,
+    Known(String),
+    /// The server answered a fetch without an ETag, so we can't write safely.
+    Unsupported,
+}
+
+/// The ETag of a payload stored as a single HTTP resource, e.g. on a WebDAV server, in an S3 bucket or as the latest payload
+/// of a FlatSync server account, as of our latest fetch or write.
+///
+/// It makes sure we never overwrite changes we haven't fetched yet: writes carry an `If-Match` header with the ETag, or
+/// `If-None-Match: *` while we don't know it, which only lets them through if there is no payload yet. The sinks report
+/// failed preconditions as `Error::SinkConflict`, so that the caller fetches and merges before writing again.
+#[derive(Debug, Default)]
+pub struct KnownEtag(Mutex<State>);
+
+impl KnownEtag {
+    fn etag(resp: &reqwest::Response) -> Option<String> {
+        let etag = resp
+            .headers()
+            .get(header::ETAG)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        debug!("ETag of {} is now {:?}", resp.url(), etag);
+        etag
+    }
+
+    /// Remembers the ETag of a successful fetch.
+    pub fn fetched(&self, resp: &reqwest::Response) {
+        *self.0.lock().unwrap() = Self::etag(resp).map_or(State::Unsupported, State::Known);
+    }
+
+    /// Remembers the ETag of a successful write. Servers don't have to send one, in which case the next write has to fetch first.
+    pub fn written(&self, resp: &reqwest::Response) {
+        *self.0.lock().unwrap() = Self::etag(resp).map_or(State::Unknown, State::Known);
+    }
+
+    /// Forgets the ETag, e.g. because the payload turned out to be missing or was deleted.
+    pub fn forget(&self) {
+        *self.0.lock().unwrap() = State::Unknown;
+    }
+
+    /// The precondition for the next write.
+    ///
+    /// A failed precondition leaves the ETag as it is, so that we don't write again before the caller fetched.
+    pub fn precondition(&self) -> Result<(HeaderName, String), Error> {
+        match &*self.0.lock().unwrap() {
+            State::Known(etag) => Ok((header::IF_MATCH, etag.clone())),
+            State::Unknown => Ok((header::IF_NONE_MATCH, "*".into())),
+            State::Unsupported => Err(Error::InvalidSinkConfiguration(
+                "the server doesn't send ETags".into(),
+            )),
+        }
+    }
+}
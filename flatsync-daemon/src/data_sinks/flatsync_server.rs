@@ -0,0 +1,175 @@
+use self::client::FlatSyncServerClient;
+use async_trait::async_trait;
+use libflatsync_common::FlatpakInstallationPayload;
+use log::debug;
+use reqwest::{header, Method, StatusCode};
+
+use super::{data_sink::DataSink, etag::KnownEtag, rest_client::RestClient};
+use crate::{device, Error};
+
+static SERVER_SINK_NAME: &str = "flatsync-server";
+
+pub mod client {
+    use crate::data_sinks::data_sink_client::DataSinkClient;
+    use crate::data_sinks::rest_client::RestClient;
+    use crate::settings::Settings;
+    use crate::Error;
+    use reqwest::{Method, RequestBuilder};
+
+    static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+    /// Client for a `flatsync-server` instance, authenticating with the access token stored in the keyring.
+    pub struct FlatSyncServerClient {
+        request: RequestBuilder,
+    }
+
+    impl DataSinkClient for FlatSyncServerClient {
+        fn sink_name(&self) -> &'static str {
+            super::SERVER_SINK_NAME
+        }
+    }
+
+    impl FlatSyncServerClient {
+        /// Creates a request for `path` on the configured server, e.g. `/api/v1/payload`.
+        pub async fn new(method: Method, path: &str) -> Result<FlatSyncServerClient, Error> {
+            let server_url: String = Settings::instance().get("flatsync-server-url");
+
+            Ok(FlatSyncServerClient {
+                request: reqwest::Client::builder()
+                    .user_agent(APP_USER_AGENT)
+                    .build()
+                    .unwrap()
+                    .request(
+                        method,
+                        format!("{}{}", server_url.trim_end_matches('/'), path),
+                    ),
+            })
+        }
+
+        pub async fn send(self) -> Result<reqwest::Response, Error> {
+            let token = self.secret_raw().await?;
+
+            self.request
+                .header("Content-Type", "application/json")
+                .bearer_auth(token)
+                .send()
+                .await
+                .map_err(Error::HttpFailure)
+        }
+    }
+
+    impl RestClient for FlatSyncServerClient {
+        fn builder(&mut self) -> RequestBuilder {
+            self.request.try_clone().unwrap()
+        }
+
+        fn set_builder(&mut self, builder: RequestBuilder) {
+            self.request = builder;
+        }
+    }
+}
+
+/// A data sink talking to a self-hosted `flatsync-server`, which keeps the payload of every device of an account.
+///
+/// The sink ID is the name this device stores its payload under, its device ID, while fetching returns the most recently altered payload of any device.
+/// The server hands out the account's revision as the ETag of that payload and refuses writes based on an outdated one, so that
+/// no device overwrites changes of another one it hasn't fetched yet, see `KnownEtag`.
+pub struct FlatSyncServerDataSink {
+    etag: KnownEtag,
+}
+
+impl FlatSyncServerDataSink {
+    pub async fn new() -> Result<Self, Error> {
+        Ok(Self {
+            etag: KnownEtag::default(),
+        })
+    }
+
+    async fn put(
+        &self,
+        device: &str,
+        payload: &FlatpakInstallationPayload,
+        precondition: (header::HeaderName, String),
+    ) -> Result<reqwest::Response, Error> {
+        let mut client =
+            FlatSyncServerClient::new(Method::PUT, &format!("/api/v1/devices/{}/payload", device))
+                .await?;
+        client.body(payload);
+        let builder = client.builder().header(precondition.0, precondition.1);
+        client.set_builder(builder);
+
+        client.send().await
+    }
+}
+
+#[async_trait]
+impl DataSink for FlatSyncServerDataSink {
+    async fn create(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        // Unlike the hostname, the device ID is unique and only consists of characters the server accepts in device names
+        let device = device::device_id();
+        debug!("Registering device {} with FlatSync server", device);
+        let resp = self
+            .put(&device, &payload, (header::IF_NONE_MATCH, "*".into()))
+            .await?;
+
+        if resp.status() == StatusCode::CONFLICT {
+            debug!("Other devices of the account already stored their payloads, joining them");
+        } else {
+            let resp = resp.error_for_status()?;
+            self.etag.written(&resp);
+        }
+
+        self.set_sink_id(&device);
+        Ok(())
+    }
+
+    async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error> {
+        let resp = FlatSyncServerClient::new(Method::GET, "/api/v1/payload")
+            .await?
+            .send()
+            .await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            self.etag.forget();
+            return Err(Error::MissingGistFiles);
+        }
+
+        let resp = resp.error_for_status()?;
+        self.etag.fetched(&resp);
+        Ok(resp.json().await?)
+    }
+
+    async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        let precondition = self.etag.precondition()?;
+        let resp = self.put(&self.sink_id(), &payload, precondition).await?;
+
+        if resp.status() == StatusCode::CONFLICT {
+            // Another device stored its payload since we last fetched
+            return Err(Error::SinkConflict);
+        }
+
+        let resp = resp.error_for_status()?;
+        self.etag.written(&resp);
+        Ok(())
+    }
+
+    /// Removes this device from the account, the payloads of the other devices are left untouched.
+    async fn delete(&self) -> Result<(), Error> {
+        let resp = FlatSyncServerClient::new(
+            Method::DELETE,
+            &format!("/api/v1/devices/{}", self.sink_id()),
+        )
+        .await?
+        .send()
+        .await?;
+        if resp.status() != StatusCode::NOT_FOUND {
+            resp.error_for_status()?;
+        }
+
+        Ok(())
+    }
+
+    fn sink_name(&self) -> &'static str {
+        SERVER_SINK_NAME
+    }
+}
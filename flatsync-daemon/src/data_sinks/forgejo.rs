@@ -0,0 +1,325 @@
+use self::client::ForgejoClient;
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use libflatsync_common::FlatpakInstallationPayload;
+use log::debug;
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Mutex;
+
+use super::{data_sink::DataSink, rest_client::RestClient};
+use crate::{data_sinks::data_sink::FILE_NAME, device, settings::Settings, Error};
+
+static FORGEJO_SINK_NAME: &str = "forgejo";
+
+/// The base URL of the Forgejo or Gitea instance the user configured, without a trailing slash.
+fn instance_url() -> String {
+    let url: String = Settings::instance().get("forgejo-instance-url");
+    url.trim_end_matches('/').to_string()
+}
+
+pub mod client {
+    use crate::data_sinks::data_sink_client::DataSinkClient;
+    use crate::data_sinks::data_sink_client::SecretType;
+    use crate::data_sinks::oauth_client::OauthClient;
+    use crate::data_sinks::rest_client::RestClient;
+    use crate::settings::Settings;
+    use crate::Error;
+    use async_trait::async_trait;
+    use libflatsync_common::providers::oauth_client::{AccessTokenData, TokenPair};
+    use oauth2::basic::BasicClient;
+    use oauth2::reqwest::async_http_client;
+    use oauth2::{AuthUrl, ClientId, TokenResponse, TokenUrl};
+    use reqwest::{IntoUrl, Method, RequestBuilder};
+
+    static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+    /// Client for the Forgejo/Gitea API, authenticating either with an access token or an OAuth token pair.
+    pub struct ForgejoClient {
+        request: RequestBuilder,
+    }
+
+    impl DataSinkClient for ForgejoClient {
+        fn sink_name(&self) -> &'static str {
+            super::FORGEJO_SINK_NAME
+        }
+    }
+
+    impl ForgejoClient {
+        pub async fn new<U: IntoUrl>(method: Method, url: U) -> Result<ForgejoClient, Error> {
+            Ok(ForgejoClient {
+                request: reqwest::Client::builder()
+                    .user_agent(APP_USER_AGENT)
+                    .build()
+                    .unwrap()
+                    .request(method, url),
+            })
+        }
+
+        /// Stored OAuth token pairs are JSON, anything else is treated as a plain access token.
+        async fn secret(&self) -> Result<SecretType, Error> {
+            let secret = self.secret_raw().await?;
+
+            Ok(match serde_json::from_str::<TokenPair>(&secret) {
+                Ok(token_pair) => SecretType::OAuth(token_pair),
+                Err(_) => SecretType::Simple(secret),
+            })
+        }
+
+        pub async fn send(self) -> Result<reqwest::Response, Error> {
+            let authorization = match self.secret().await? {
+                SecretType::Simple(token) => format!("token {}", token),
+                SecretType::OAuth(token_pair) => {
+                    let token_pair = self.check_tokens(&token_pair).await?;
+                    format!("Bearer {}", token_pair.access_token_data.token.secret())
+                }
+            };
+
+            self.request
+                .header("Content-Type", "application/json")
+                .header("Authorization", authorization)
+                .send()
+                .await
+                .map_err(Error::HttpFailure)
+        }
+    }
+
+    #[async_trait]
+    impl OauthClient for ForgejoClient {
+        fn oauth2_scopes(&self) -> Vec<String> {
+            vec!["write:repository".into()]
+        }
+
+        async fn check_tokens(&self, tokens: &TokenPair) -> Result<TokenPair, Error> {
+            match tokens.access_token_data.expires_in {
+                Some(expires_in) if expires_in <= chrono::Utc::now() => {}
+                _ => return Ok(tokens.clone()),
+            }
+
+            let refresh_token = tokens.refresh_token.as_ref().ok_or_else(|| {
+                Error::OAuthTokenRefreshFailure("No refresh token available".into())
+            })?;
+
+            let instance_url = super::instance_url();
+            let invalid_url = |e: oauth2::url::ParseError| {
+                Error::InvalidSinkConfiguration(format!("Invalid instance URL: {}", e))
+            };
+            let client = BasicClient::new(
+                ClientId::new(Settings::instance().get("forgejo-client-id")),
+                None,
+                AuthUrl::new(format!("{}/login/oauth/authorize", instance_url))
+                    .map_err(invalid_url)?,
+                Some(
+                    TokenUrl::new(format!("{}/login/oauth/access_token", instance_url))
+                        .map_err(invalid_url)?,
+                ),
+            );
+
+            let token = client
+                .exchange_refresh_token(refresh_token)
+                .request_async(async_http_client)
+                .await
+                .map_err(|e| Error::OAuthTokenRefreshFailure(e.to_string()))?;
+
+            let token_pair = TokenPair {
+                access_token_data: AccessTokenData {
+                    token: token.access_token().clone(),
+                    expires_in: token.expires_in().map(|e| {
+                        chrono::Utc::now() + chrono::Duration::seconds(e.as_secs() as i64)
+                    }),
+                },
+                refresh_token: token.refresh_token().cloned(),
+            };
+
+            self.set_secret_raw(&serde_json::to_string(&token_pair).unwrap())
+                .await?;
+
+            Ok(token_pair)
+        }
+    }
+
+    impl RestClient for ForgejoClient {
+        fn builder(&mut self) -> RequestBuilder {
+            self.request.try_clone().unwrap()
+        }
+
+        fn set_builder(&mut self, builder: RequestBuilder) {
+            self.request = builder;
+        }
+    }
+}
+
+// See https://codeberg.org/api/swagger#/repository/repoGetContents
+#[derive(Deserialize)]
+struct ContentsResponse {
+    sha: String,
+    content: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FileResponse {
+    content: ContentsResponse,
+}
+
+/// A data sink storing the payload as a file in a Forgejo or Gitea repository (e.g. on Codeberg) through the repository contents API.
+///
+/// The repository (`owner/name`) is configured through the `forgejo-repository` setting and becomes the sink ID once the file exists.
+/// Updates carry the blob SHA we last saw, so that the server rejects them if another device changed the file in the meantime.
+/// Without one, e.g. in an instance that never fetched, and after such a conflict, updates fail with `Error::SinkConflict` until the
+/// file was fetched again, so that the caller merges the other device's changes first.
+pub struct ForgejoDataSink {
+    sha: Mutex<Option<String>>,
+}
+
+impl ForgejoDataSink {
+    pub async fn new() -> Result<Self, Error> {
+        Ok(Self {
+            sha: Mutex::new(None),
+        })
+    }
+
+    fn branch() -> String {
+        Settings::instance().get("forgejo-branch")
+    }
+
+    fn contents_url(repository: &str) -> String {
+        format!(
+            "{}/api/v1/repos/{}/contents/{}",
+            instance_url(),
+            repository.trim_matches('/'),
+            FILE_NAME
+        )
+    }
+
+    fn commit_message() -> String {
+        format!("Update FlatSync list from {}", device::device_name())
+    }
+
+    /// Fetches the file, returning `None` if it doesn't exist yet.
+    async fn get(&self, repository: &str) -> Result<Option<ContentsResponse>, Error> {
+        let resp = ForgejoClient::new(
+            Method::GET,
+            format!("{}?ref={}", Self::contents_url(repository), Self::branch()),
+        )
+        .await?
+        .send()
+        .await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+
+        let contents: ContentsResponse = resp.error_for_status()?.json().await?;
+        *self.sha.lock().unwrap() = Some(contents.sha.clone());
+        Ok(Some(contents))
+    }
+
+    fn encode(payload: &FlatpakInstallationPayload) -> Result<String, Error> {
+        let contents = serde_json::to_vec_pretty(payload)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+        Ok(BASE64.encode(contents))
+    }
+}
+
+#[async_trait]
+impl DataSink for ForgejoDataSink {
+    /// Creates the file, unless the repository already contains a FlatSync list.
+    /// In that case we simply join it and leave it to the next sync to reconcile both.
+    async fn create(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        let repository: String = Settings::instance().get("forgejo-repository");
+
+        if self.get(&repository).await?.is_some() {
+            debug!(
+                "{} already contains a FlatSync list, joining it",
+                repository
+            );
+        } else {
+            let mut client =
+                ForgejoClient::new(Method::POST, Self::contents_url(&repository)).await?;
+            client.body(json!({
+                "content": Self::encode(&payload)?,
+                "branch": Self::branch(),
+                "message": Self::commit_message(),
+            }));
+
+            let resp: FileResponse = client.send().await?.error_for_status()?.json().await?;
+            *self.sha.lock().unwrap() = Some(resp.content.sha);
+        }
+
+        self.set_sink_id(&repository);
+        Ok(())
+    }
+
+    async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error> {
+        let content = self
+            .get(&self.sink_id())
+            .await?
+            .and_then(|contents| contents.content)
+            .ok_or(Error::MissingGistFiles)?;
+
+        // The API wraps the base64 encoded content in lines
+        let content: String = content.split_whitespace().collect();
+        let content = BASE64
+            .decode(content)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+
+        serde_json::from_slice(&content)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))
+    }
+
+    async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        let repository = self.sink_id();
+
+        // Taking the current SHA instead would overwrite changes we haven't seen
+        let sha = self.sha.lock().unwrap().clone();
+        let Some(sha) = sha else {
+            return Err(Error::SinkConflict);
+        };
+
+        let mut client = ForgejoClient::new(Method::PUT, Self::contents_url(&repository)).await?;
+        client.body(json!({
+            "content": Self::encode(&payload)?,
+            "sha": sha,
+            "branch": Self::branch(),
+            "message": Self::commit_message(),
+        }));
+
+        let resp = client.send().await?;
+        if matches!(
+            resp.status(),
+            StatusCode::CONFLICT | StatusCode::UNPROCESSABLE_ENTITY
+        ) {
+            // Somebody else updated the file since we last fetched it. We keep the outdated SHA, as taking the current one
+            // would overwrite their commit with a payload that doesn't contain their changes.
+            return Err(Error::SinkConflict);
+        }
+
+        let resp: FileResponse = resp.error_for_status()?.json().await?;
+        *self.sha.lock().unwrap() = Some(resp.content.sha);
+        Ok(())
+    }
+
+    async fn delete(&self) -> Result<(), Error> {
+        let repository = self.sink_id();
+        let Some(contents) = self.get(&repository).await? else {
+            return Ok(());
+        };
+
+        let mut client =
+            ForgejoClient::new(Method::DELETE, Self::contents_url(&repository)).await?;
+        client.body(json!({
+            "sha": contents.sha,
+            "branch": Self::branch(),
+            "message": format!("Remove FlatSync list from {}", device::device_name()),
+        }));
+        client.send().await?.error_for_status()?;
+
+        *self.sha.lock().unwrap() = None;
+        Ok(())
+    }
+
+    fn sink_name(&self) -> &'static str {
+        FORGEJO_SINK_NAME
+    }
+}
@@ -0,0 +1,387 @@
+use super::data_sink::{DataSink, FILE_NAME};
+use crate::{device, settings::Settings, Error};
+use async_trait::async_trait;
+use libflatsync_common::FlatpakInstallationPayload;
+use log::{debug, trace, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+use tokio::{fs, process::Command};
+
+static GIT_SINK_NAME: &str = "git-repository";
+/// How often we retry pushing when another device pushed in the meantime.
+const PUSH_ATTEMPTS: usize = 3;
+
+/// A data sink storing the payload in a git repository, with every update becoming its own commit.
+///
+/// The sink ID is the URL of the repository, which can be anything `git` itself understands, e.g. a local path, a `file://` URL or an SSH remote.
+/// We keep a working copy of the repository in FlatSync's data directory and shell out to `git` for all operations, so that the user's SSH setup is honored.
+pub struct GitDataSink {
+    /// The directory holding the working copies, one per repository.
+    checkouts: PathBuf,
+}
+
+impl GitDataSink {
+    pub async fn new() -> Result<Self, Error> {
+        let mut checkouts = glib::user_data_dir();
+        checkouts.push("flatsync");
+        checkouts.push("git");
+
+        Ok(Self { checkouts })
+    }
+
+    fn branch(&self) -> String {
+        Settings::instance().get("git-repository-branch")
+    }
+
+    /// The directory holding our working copy of the repository with the given URL.
+    ///
+    /// It is named after a hash of the URL, so that URLs which only differ in special characters don't share one.
+    fn checkout_dir(&self, url: &str) -> PathBuf {
+        let digest = Sha256::digest(url.as_bytes());
+        self.checkouts.join(hex::encode(&digest[..16]))
+    }
+
+    async fn git(dir: Option<&Path>, args: &[&str]) -> Result<String, Error> {
+        let mut command = Command::new("git");
+        if let Some(dir) = dir {
+            command.arg("-C").arg(dir);
+        }
+        command.args(args);
+        // We run in the background, so there's nobody to answer prompts
+        command.env("GIT_TERMINAL_PROMPT", "0");
+        if std::env::var_os("GIT_SSH_COMMAND").is_none() {
+            command.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+        }
+
+        trace!("Running git {:?}", args);
+        let output = command.output().await?;
+
+        if !output.status.success() {
+            return Err(Error::GitFailure(
+                args.join(" "),
+                String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            ));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    /// Makes sure we have a working copy of the repository with the given URL which is up to date with the remote branch.
+    ///
+    /// # Returns
+    /// The path of the working copy.
+    async fn sync_checkout(&self, url: &str, branch: &str) -> Result<PathBuf, Error> {
+        let dir = self.checkout_dir(url);
+
+        if !dir.join(".git").exists() {
+            debug!("Cloning {} into {:?}", url, dir);
+            fs::create_dir_all(&self.checkouts).await?;
+            Self::git(None, &["clone", url, &dir.to_string_lossy()]).await?;
+        }
+
+        Self::git(Some(&dir), &["fetch", "origin"]).await?;
+
+        // A freshly created remote repository doesn't have our branch yet
+        let remote_ref = format!("origin/{}", branch);
+        if Self::git(
+            Some(&dir),
+            &["rev-parse", "--verify", "--quiet", &remote_ref],
+        )
+        .await
+        .is_ok()
+        {
+            Self::git(Some(&dir), &["checkout", "-B", branch, &remote_ref]).await?;
+            Self::git(Some(&dir), &["reset", "--hard", &remote_ref]).await?;
+        } else if Self::git(Some(&dir), &["symbolic-ref", "--short", "HEAD"])
+            .await
+            .is_ok_and(|head| head.trim() == branch)
+        {
+            trace!("Already on branch {}, which isn't pushed yet", branch);
+        } else if Self::git(
+            Some(&dir),
+            &[
+                "rev-parse",
+                "--verify",
+                "--quiet",
+                &format!("refs/heads/{}", branch),
+            ],
+        )
+        .await
+        .is_ok()
+        {
+            Self::git(Some(&dir), &["checkout", branch]).await?;
+        } else {
+            Self::git(Some(&dir), &["checkout", "--orphan", branch]).await?;
+        }
+
+        Ok(dir)
+    }
+
+    async fn read_head(dir: &Path) -> Result<Option<FlatpakInstallationPayload>, Error> {
+        let Ok(contents) = Self::git(Some(dir), &["show", &format!("HEAD:{}", FILE_NAME)]).await
+        else {
+            return Ok(None);
+        };
+
+        serde_json::from_str(&contents)
+            .map(Some)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))
+    }
+
+    /// Builds the commit message for the change from `previous` to `payload` made by the given device.
+    fn commit_message(
+        device: &str,
+        previous: Option<&FlatpakInstallationPayload>,
+        payload: &FlatpakInstallationPayload,
+    ) -> String {
+        let refs = |payload: &FlatpakInstallationPayload| {
+            payload
+                .installations
+                .0
+                .iter()
+                .flat_map(|(kind, installation)| {
+                    installation
+                        .refs
+                        .iter()
+                        .map(move |ref_| format!("{} ({})", ref_.ref_, kind))
+                })
+                .collect::<BTreeSet<_>>()
+        };
+
+        let new_refs = refs(payload);
+        let old_refs = previous.map(refs).unwrap_or_default();
+        let added = new_refs.difference(&old_refs).collect::<Vec<_>>();
+        let removed = old_refs.difference(&new_refs).collect::<Vec<_>>();
+
+        let mut message = format!(
+            "Sync from {}: {} added, {} removed\n",
+            device,
+            added.len(),
+            removed.len()
+        );
+
+        for (title, refs) in [("Added", added), ("Removed", removed)] {
+            if refs.is_empty() {
+                continue;
+            }
+            message.push_str(&format!("\n{}:\n", title));
+            for ref_ in refs {
+                message.push_str(&format!("  {}\n", ref_));
+            }
+        }
+
+        message
+    }
+
+    async fn commit(dir: &Path, device: &str, message: &str) -> Result<(), Error> {
+        let author = format!("user.name=FlatSync ({})", device);
+        Self::git(
+            Some(dir),
+            &[
+                "-c",
+                &author,
+                "-c",
+                "user.email=flatsync@localhost",
+                "commit",
+                "--quiet",
+                "-m",
+                message,
+            ],
+        )
+        .await?;
+
+        Ok(())
+    }
+
+    async fn push(dir: &Path, branch: &str) -> Result<(), Error> {
+        let refspec = format!("HEAD:refs/heads/{}", branch);
+        Self::git(Some(dir), &["push", "origin", &refspec]).await?;
+        Ok(())
+    }
+
+    async fn commit_and_push(
+        &self,
+        device: &str,
+        url: &str,
+        branch: &str,
+        payload: &FlatpakInstallationPayload,
+    ) -> Result<(), Error> {
+        let contents = serde_json::to_string_pretty(payload)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+
+        for attempt in 1..=PUSH_ATTEMPTS {
+            let dir = self.sync_checkout(url, branch).await?;
+            let previous = Self::read_head(&dir).await?;
+
+            fs::write(dir.join(FILE_NAME), &contents).await?;
+            Self::git(Some(&dir), &["add", FILE_NAME]).await?;
+
+            if Self::git(Some(&dir), &["diff", "--cached", "--quiet"])
+                .await
+                .is_ok()
+            {
+                debug!("Repository is already up to date, nothing to commit");
+                return Ok(());
+            }
+
+            let message = Self::commit_message(device, previous.as_ref(), payload);
+            Self::commit(&dir, device, &message).await?;
+
+            match Self::push(&dir, branch).await {
+                Ok(_) => return Ok(()),
+                Err(e) if attempt < PUSH_ATTEMPTS => {
+                    warn!("Pushing to git repository failed, retrying: {}", e);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DataSink for GitDataSink {
+    /// Pushes the payload as the first commit, unless the repository already contains a FlatSync list.
+    /// In that case we simply join it and leave it to the next sync to reconcile both.
+    async fn create(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        let (url, branch) = (self.sink_id(), self.branch());
+        let dir = self.sync_checkout(&url, &branch).await?;
+        if Self::read_head(&dir).await?.is_some() {
+            debug!("Repository already contains a FlatSync list, joining it");
+            return Ok(());
+        }
+
+        self.commit_and_push(&device::device_name(), &url, &branch, &payload)
+            .await
+    }
+
+    async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error> {
+        let dir = self.sync_checkout(&self.sink_id(), &self.branch()).await?;
+        Self::read_head(&dir).await?.ok_or(Error::MissingGistFiles)
+    }
+
+    async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        self.commit_and_push(
+            &device::device_name(),
+            &self.sink_id(),
+            &self.branch(),
+            &payload,
+        )
+        .await
+    }
+
+    /// Commits the removal of the FlatSync list, its history is kept in the repository.
+    async fn delete(&self) -> Result<(), Error> {
+        let branch = self.branch();
+        let dir = self.sync_checkout(&self.sink_id(), &branch).await?;
+        if Self::read_head(&dir).await?.is_none() {
+            return Ok(());
+        }
+
+        Self::git(Some(&dir), &["rm", "--quiet", FILE_NAME]).await?;
+        let device = device::device_name();
+        Self::commit(
+            &dir,
+            &device,
+            &format!("Remove FlatSync list from {}", device),
+        )
+        .await?;
+        Self::push(&dir, &branch).await
+    }
+
+    /// The sink counts as initialised once our working copy contains a FlatSync list.
+    fn is_initialised(&self) -> bool {
+        let url = self.sink_id();
+        !url.is_empty() && self.checkout_dir(&url).join(FILE_NAME).exists()
+    }
+
+    fn sink_name(&self) -> &'static str {
+        GIT_SINK_NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sinks::stand_in::payload;
+
+    async fn commit_count(dir: &Path) -> usize {
+        GitDataSink::git(Some(dir), &["rev-list", "--count", "HEAD"])
+            .await
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn syncs_two_devices_through_a_bare_repository() {
+        let root = std::env::temp_dir().join(format!("flatsync-git-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let remote = root.join("remote.git");
+        GitDataSink::git(
+            None,
+            &["init", "--bare", "--quiet", &remote.to_string_lossy()],
+        )
+        .await
+        .unwrap();
+        let url = remote.to_string_lossy().to_string();
+
+        let first = GitDataSink {
+            checkouts: root.join("first"),
+        };
+        let second = GitDataSink {
+            checkouts: root.join("second"),
+        };
+
+        // The first device pushes to the empty repository, creating the branch
+        first
+            .commit_and_push("first", &url, "main", &payload(1))
+            .await
+            .unwrap();
+
+        // The second one picks the list up and commits on top of it
+        let dir = second.sync_checkout(&url, "main").await.unwrap();
+        let fetched = GitDataSink::read_head(&dir).await.unwrap().unwrap();
+        assert_eq!(fetched.altered_at, payload(1).altered_at);
+        second
+            .commit_and_push("second", &url, "main", &payload(2))
+            .await
+            .unwrap();
+
+        // Pushing an unchanged list doesn't add an empty commit
+        second
+            .commit_and_push("second", &url, "main", &payload(2))
+            .await
+            .unwrap();
+
+        let dir = first.sync_checkout(&url, "main").await.unwrap();
+        let fetched = GitDataSink::read_head(&dir).await.unwrap().unwrap();
+        assert_eq!(fetched.altered_at, payload(2).altered_at);
+        assert_eq!(commit_count(&dir).await, 2);
+
+        // Commits name the device that made them
+        let subject = GitDataSink::git(Some(&dir), &["log", "-1", "--format=%s"])
+            .await
+            .unwrap();
+        assert_eq!(subject.trim(), "Sync from second: 0 added, 0 removed");
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[test]
+    fn checkout_dirs_differ_for_similar_urls() {
+        let sink = GitDataSink {
+            checkouts: PathBuf::from("/tmp"),
+        };
+
+        assert_ne!(
+            sink.checkout_dir("git@host:user/repo"),
+            sink.checkout_dir("git@host:user_repo")
+        );
+    }
+}
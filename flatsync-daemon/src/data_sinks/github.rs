@@ -16,7 +16,6 @@ use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
 static GH_SINK_NAME: &str = "github-gists";
-static GH_API_URL: &str = "https://api.github.com/gists";
 
 pub mod client {
     use crate::data_sinks::data_sink_client::DataSinkClient;
@@ -25,19 +24,29 @@ pub mod client {
     use crate::data_sinks::rest_client::RestClient;
     use crate::Error;
     use async_trait::async_trait;
-    use libflatsync_common::providers::github::get_github_basic_client;
+    use libflatsync_common::providers::github::{get_github_basic_client, GitHubAccount};
     use libflatsync_common::providers::oauth_client::{AccessTokenData, TokenPair};
     use oauth2::basic::BasicClient;
     use oauth2::reqwest::async_http_client;
     use oauth2::TokenResponse;
     // '{"description":"Example of a gist","public":false,"files":{"README.md":{"content":"Hello World"}}}'
-    use reqwest::{IntoUrl, Method, RequestBuilder};
+    use reqwest::{Method, RequestBuilder};
 
     static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
 
     pub struct GitHubClient {
         request: RequestBuilder,
         client: BasicClient,
+        tokens: TokenPair,
+    }
+
+    /// Looks the account up in the keyring.
+    struct Keyring;
+
+    impl DataSinkClient for Keyring {
+        fn sink_name(&self) -> &'static str {
+            super::GH_SINK_NAME
+        }
     }
 
     impl DataSinkClient for GitHubClient {
@@ -47,21 +56,30 @@ pub mod client {
     }
 
     impl GitHubClient {
-        pub async fn new<U: IntoUrl>(method: Method, url: U) -> Result<GitHubClient, Error> {
+        /// Prepares a request to `path` below the Gists API of the instance the stored account belongs to, e.g. `/<gist id>`.
+        pub async fn new(method: Method, path: &str) -> Result<GitHubClient, Error> {
+            let account = serde_json::from_str::<GitHubAccount>(&Keyring.secret_raw().await?)
+                .map_err(|e| Error::InvalidSinkConfiguration(e.to_string()))?;
+            let url = format!(
+                "{}/gists{}",
+                account.config.api_url.trim_end_matches('/'),
+                path
+            );
+
             Ok(GitHubClient {
                 request: reqwest::Client::builder()
                     .user_agent(APP_USER_AGENT)
                     .build()
                     .unwrap()
                     .request(method, url),
-                client: get_github_basic_client(),
+                client: get_github_basic_client(&account.config)
+                    .map_err(Error::ProviderSetupFailure)?,
+                tokens: account.tokens,
             })
         }
 
         async fn secret(&self) -> Result<SecretType, Error> {
-            let token_pair = serde_json::from_str::<TokenPair>(&self.secret_raw().await?).unwrap();
-
-            Ok(SecretType::OAuth(token_pair))
+            Ok(SecretType::OAuth(self.tokens.clone()))
         }
 
         pub async fn send(self) -> Result<reqwest::Response, Error> {
@@ -180,7 +198,7 @@ impl DataSink for GitHubGistDataSink {
             pub id: String,
         }
 
-        let mut client = GitHubClient::new(Method::POST, GH_API_URL).await?;
+        let mut client = GitHubClient::new(Method::POST, "").await?;
 
         client.body(json!({
             "description": "Installed Flatpaks and its remote repositories",
@@ -206,7 +224,7 @@ impl DataSink for GitHubGistDataSink {
         }
 
         let mut resp: GetGistResponse =
-            GitHubClient::new(Method::GET, format!("{}/{}", GH_API_URL, self.sink_id()))
+            GitHubClient::new(Method::GET, &format!("/{}", self.sink_id()))
                 .await?
                 .send()
                 .await?
@@ -221,8 +239,7 @@ impl DataSink for GitHubGistDataSink {
     }
 
     async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
-        let mut client =
-            GitHubClient::new(Method::POST, format!("{}/{}", GH_API_URL, self.sink_id())).await?;
+        let mut client = GitHubClient::new(Method::POST, &format!("/{}", self.sink_id())).await?;
         client.body(json!({
             "files": {
                 FILE_NAME: GistFile { content: payload }
@@ -233,6 +250,17 @@ impl DataSink for GitHubGistDataSink {
         Ok(())
     }
 
+    async fn delete(&self) -> Result<(), Error> {
+        // See https://docs.github.com/en/rest/gists/gists?apiVersion=2022-11-28#delete-a-gist
+        GitHubClient::new(Method::DELETE, &format!("/{}", self.sink_id()))
+            .await?
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
     fn sink_name(&self) -> &'static str {
         GH_SINK_NAME
     }
@@ -0,0 +1,239 @@
+use self::client::GitLabClient;
+use async_trait::async_trait;
+use libflatsync_common::FlatpakInstallationPayload;
+use log::debug;
+use serde_json::json;
+
+use super::{data_sink::DataSink, rest_client::RestClient};
+use crate::{data_sinks::data_sink::FILE_NAME, settings::Settings, Error};
+use reqwest::Method;
+use serde::Deserialize;
+
+static GITLAB_SINK_NAME: &str = "gitlab-snippets";
+
+/// The base URL of the GitLab instance the user configured, without a trailing slash.
+fn instance_url() -> String {
+    let url: String = Settings::instance().get("gitlab-instance-url");
+    url.trim_end_matches('/').to_string()
+}
+
+fn snippets_api_url() -> String {
+    format!("{}/api/v4/snippets", instance_url())
+}
+
+pub mod client {
+    use crate::data_sinks::data_sink_client::DataSinkClient;
+    use crate::data_sinks::data_sink_client::SecretType;
+    use crate::data_sinks::oauth_client::OauthClient;
+    use crate::data_sinks::rest_client::RestClient;
+    use crate::settings::Settings;
+    use crate::Error;
+    use async_trait::async_trait;
+    use libflatsync_common::providers::gitlab::get_gitlab_basic_client;
+    use libflatsync_common::providers::oauth_client::{AccessTokenData, TokenPair};
+    use oauth2::basic::BasicClient;
+    use oauth2::reqwest::async_http_client;
+    use oauth2::TokenResponse;
+    use reqwest::{IntoUrl, Method, RequestBuilder};
+
+    static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+    pub struct GitLabClient {
+        request: RequestBuilder,
+        client: BasicClient,
+    }
+
+    impl DataSinkClient for GitLabClient {
+        fn sink_name(&self) -> &'static str {
+            super::GITLAB_SINK_NAME
+        }
+    }
+
+    impl GitLabClient {
+        pub async fn new<U: IntoUrl>(method: Method, url: U) -> Result<GitLabClient, Error> {
+            let client_id: String = Settings::instance().get("gitlab-client-id");
+
+            Ok(GitLabClient {
+                request: reqwest::Client::builder()
+                    .user_agent(APP_USER_AGENT)
+                    .build()
+                    .unwrap()
+                    .request(method, url),
+                client: get_gitlab_basic_client(&super::instance_url(), &client_id)
+                    .map_err(Error::ProviderSetupFailure)?,
+            })
+        }
+
+        async fn secret(&self) -> Result<SecretType, Error> {
+            let token_pair = serde_json::from_str::<TokenPair>(&self.secret_raw().await?)
+                .map_err(|_| Error::KeychainEntryNotFound)?;
+
+            Ok(SecretType::OAuth(token_pair))
+        }
+
+        pub async fn send(self) -> Result<reqwest::Response, Error> {
+            let SecretType::OAuth(token_pair) = self.secret().await? else {
+                unreachable!();
+            };
+
+            let token_pair = self.check_tokens(&token_pair).await?;
+
+            self.request
+                .header("Content-Type", "application/json")
+                .header(
+                    "Authorization",
+                    format!("Bearer {}", token_pair.access_token_data.token.secret()),
+                )
+                .send()
+                .await
+                .and_then(|resp| resp.error_for_status())
+                .map_err(Error::HttpFailure)
+        }
+    }
+
+    #[async_trait]
+    impl OauthClient for GitLabClient {
+        fn oauth2_scopes(&self) -> Vec<String> {
+            vec!["api".into()]
+        }
+
+        async fn check_tokens(&self, tokens: &TokenPair) -> Result<TokenPair, Error> {
+            let token_data = &tokens.access_token_data;
+
+            match token_data.expires_in {
+                Some(expires_in) if expires_in <= chrono::Utc::now() => {}
+                _ => return Ok(tokens.clone()),
+            }
+
+            let refresh_token = tokens.refresh_token.as_ref().ok_or_else(|| {
+                Error::OAuthTokenRefreshFailure("No refresh token available".into())
+            })?;
+
+            let token = self
+                .client
+                .exchange_refresh_token(refresh_token)
+                .request_async(async_http_client)
+                .await
+                .map_err(|e| Error::OAuthTokenRefreshFailure(e.to_string()))?;
+
+            let token_pair = TokenPair {
+                access_token_data: AccessTokenData {
+                    token: token.access_token().clone(),
+                    expires_in: token.expires_in().map(|e| {
+                        chrono::Utc::now() + chrono::Duration::seconds(e.as_secs() as i64)
+                    }),
+                },
+                // GitLab rotates refresh tokens, the old one is invalid from now on
+                refresh_token: token.refresh_token().cloned(),
+            };
+
+            self.set_secret_raw(&serde_json::to_string(&token_pair).unwrap())
+                .await?;
+
+            Ok(token_pair)
+        }
+    }
+
+    impl RestClient for GitLabClient {
+        fn builder(&mut self) -> RequestBuilder {
+            self.request.try_clone().unwrap()
+        }
+
+        fn set_builder(&mut self, builder: RequestBuilder) {
+            self.request = builder;
+        }
+    }
+}
+
+pub struct GitLabSnippetDataSink {}
+
+impl GitLabSnippetDataSink {
+    pub async fn new() -> Result<Self, Error> {
+        Ok(Self {})
+    }
+}
+
+#[async_trait]
+impl DataSink for GitLabSnippetDataSink {
+    async fn create(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        // See https://docs.gitlab.com/ee/api/snippets.html#create-new-snippet
+        #[derive(Deserialize, Debug)]
+        pub struct CreateSnippetResponse {
+            pub id: u64,
+        }
+
+        let mut client = GitLabClient::new(Method::POST, snippets_api_url()).await?;
+
+        client.body(json!({
+            "title": "Installed Flatpaks and its remote repositories",
+            "visibility": "private",
+            "files": [{
+                "file_path": FILE_NAME,
+                "content": serde_json::to_string_pretty(&payload).unwrap(),
+            }],
+        }));
+
+        let resp = client.send().await?;
+        debug!("Snippet creation response: {:?}", resp);
+        let data: CreateSnippetResponse = resp.json().await?;
+        debug!("Snippet creation response data: {:?}", data);
+        self.set_sink_id(&data.id.to_string());
+        Ok(())
+    }
+
+    async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error> {
+        // See https://docs.gitlab.com/ee/api/snippets.html#snippet-repository-file-content
+        let content = GitLabClient::new(
+            Method::GET,
+            format!(
+                "{}/{}/files/main/{}/raw",
+                snippets_api_url(),
+                self.sink_id(),
+                FILE_NAME
+            ),
+        )
+        .await?
+        .send()
+        .await?
+        .text()
+        .await?;
+
+        serde_json::from_str(&content).map_err(|_| Error::MissingGistFiles)
+    }
+
+    async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        // See https://docs.gitlab.com/ee/api/snippets.html#update-snippet
+        let mut client = GitLabClient::new(
+            Method::PUT,
+            format!("{}/{}", snippets_api_url(), self.sink_id()),
+        )
+        .await?;
+        client.body(json!({
+            "files": [{
+                "action": "update",
+                "file_path": FILE_NAME,
+                "content": serde_json::to_string_pretty(&payload).unwrap(),
+            }],
+        }));
+        client.send().await?;
+
+        Ok(())
+    }
+
+    async fn delete(&self) -> Result<(), Error> {
+        // See https://docs.gitlab.com/ee/api/snippets.html#delete-snippet
+        GitLabClient::new(
+            Method::DELETE,
+            format!("{}/{}", snippets_api_url(), self.sink_id()),
+        )
+        .await?
+        .send()
+        .await?;
+
+        Ok(())
+    }
+
+    fn sink_name(&self) -> &'static str {
+        GITLAB_SINK_NAME
+    }
+}
@@ -0,0 +1,43 @@
+use crate::Error;
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Outcome of the latest operations on a single data sink.
+#[derive(Debug, Clone, Default)]
+pub struct SinkHealth {
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+impl SinkHealth {
+    pub fn is_healthy(&self) -> bool {
+        self.last_error.is_none()
+    }
+}
+
+// Shared between the D-Bus interface and the main loop, which each hold their own set of sinks
+static HEALTH: Mutex<BTreeMap<String, SinkHealth>> = Mutex::new(BTreeMap::new());
+
+/// Records the outcome of an operation on the sink of the given provider.
+pub fn record<T>(provider: &str, result: &Result<T, Error>) {
+    let mut health = HEALTH.lock().unwrap();
+    let entry = health.entry(provider.to_string()).or_default();
+    match result {
+        Ok(_) => {
+            entry.last_success = Some(Utc::now());
+            entry.last_error = None;
+        }
+        Err(e) => entry.last_error = Some(e.to_string()),
+    }
+}
+
+/// The health of the sink of the given provider, which is healthy as long as nothing failed yet.
+pub fn get(provider: &str) -> SinkHealth {
+    HEALTH
+        .lock()
+        .unwrap()
+        .get(provider)
+        .cloned()
+        .unwrap_or_default()
+}
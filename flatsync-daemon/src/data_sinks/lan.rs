@@ -0,0 +1,611 @@
+use self::{
+    discovery::DiscoveredPeer,
+    protocol::{
+        ClientHello, HelloReply, Request, Response, SecureChannel, ServerHello, PAIRING_IDENTITY,
+        PROTOCOL_VERSION,
+    },
+};
+use super::{data_sink::DataSink, data_sink_client::DataSinkClient};
+use crate::{settings::Settings, Error, MessageType};
+use async_trait::async_trait;
+use libflatsync_common::FlatpakInstallationPayload;
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Mutex,
+    time::Duration,
+};
+use tokio::{fs, net::TcpStream, sync::mpsc::Sender, task::JoinHandle};
+
+mod discovery;
+mod protocol;
+mod server;
+
+static LAN_SINK_NAME: &str = "lan";
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A device we paired with, together with the key we agreed on while pairing.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Peer {
+    name: String,
+    key: String,
+}
+
+/// Looks the paired devices up in the keyring.
+struct Keyring;
+
+impl DataSinkClient for Keyring {
+    fn sink_name(&self) -> &'static str {
+        LAN_SINK_NAME
+    }
+}
+
+/// The paired devices by their ID.
+#[derive(Clone)]
+enum PeerStore {
+    /// Kept in the keyring, as they include the keys
+    Keyring,
+    #[cfg(test)]
+    Memory(std::sync::Arc<Mutex<BTreeMap<String, Peer>>>),
+}
+
+impl PeerStore {
+    async fn load(&self) -> Result<BTreeMap<String, Peer>, Error> {
+        match self {
+            Self::Keyring => match Keyring.secret_raw().await {
+                Ok(secret) => {
+                    serde_json::from_str(&secret).map_err(|e| Error::LanFailure(e.to_string()))
+                }
+                Err(Error::KeychainEntryNotFound) => Ok(BTreeMap::new()),
+                Err(e) => Err(e),
+            },
+            #[cfg(test)]
+            Self::Memory(peers) => Ok(peers.lock().unwrap().clone()),
+        }
+    }
+
+    async fn save(&self, peers: &BTreeMap<String, Peer>) -> Result<(), Error> {
+        match self {
+            Self::Keyring => {
+                Keyring
+                    .set_secret_raw(&serde_json::to_string(peers).unwrap())
+                    .await
+            }
+            #[cfg(test)]
+            Self::Memory(stored) => {
+                *stored.lock().unwrap() = peers.clone();
+                Ok(())
+            }
+        }
+    }
+
+    async fn add(&self, device_id: &str, peer: Peer) -> Result<(), Error> {
+        let mut peers = self.load().await?;
+        peers.insert(device_id.to_string(), peer);
+        self.save(&peers).await
+    }
+}
+
+/// The short-lived window in which another device may pair with us using a code we showed to the user.
+mod pairing {
+    use std::{
+        sync::Mutex,
+        time::{Duration, Instant},
+    };
+
+    const PAIRING_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+    /// Every attempt lets an attacker check a single guess of the code, so we only allow a few.
+    const PAIRING_ATTEMPTS: u8 = 3;
+
+    struct PairingWindow {
+        code: String,
+        expires_at: Instant,
+        attempts_left: u8,
+    }
+
+    // Opened through the D-Bus interface, but used by the server of the main loop's sink
+    static PAIRING: Mutex<Option<PairingWindow>> = Mutex::new(None);
+
+    pub fn start() -> String {
+        let code = format!("{:06}", rand::random::<u32>() % 1_000_000);
+        *PAIRING.lock().unwrap() = Some(PairingWindow {
+            code: code.clone(),
+            expires_at: Instant::now() + PAIRING_TIMEOUT,
+            attempts_left: PAIRING_ATTEMPTS,
+        });
+        code
+    }
+
+    /// The code of the currently open pairing window, if any.
+    pub fn code() -> Option<String> {
+        let mut pairing = PAIRING.lock().unwrap();
+        if pairing
+            .as_ref()
+            .is_some_and(|window| window.expires_at < Instant::now())
+        {
+            *pairing = None;
+        }
+        pairing.as_ref().map(|window| window.code.clone())
+    }
+
+    pub fn failed_attempt() {
+        let mut pairing = PAIRING.lock().unwrap();
+        if let Some(window) = pairing.as_mut() {
+            window.attempts_left -= 1;
+            if window.attempts_left == 0 {
+                *pairing = None;
+            }
+        }
+    }
+
+    pub fn finish() {
+        *PAIRING.lock().unwrap() = None;
+    }
+}
+
+/// The payload we serve to other devices.
+fn own_payload_path() -> PathBuf {
+    let mut path = glib::user_data_dir();
+    path.push("flatsync");
+    path.push("lan");
+    path.push(super::data_sink::FILE_NAME);
+    path
+}
+
+/// A daemon taking part in LAN sync: the identity it announces, the payload it serves and the devices it is paired with.
+#[derive(Clone)]
+struct Node {
+    device_id: String,
+    name: String,
+    payload_path: PathBuf,
+    peers: PeerStore,
+}
+
+impl Node {
+    /// This device, as set up through the settings.
+    fn own() -> Result<Self, Error> {
+        let device_id: String = Settings::instance().get("lan-id");
+        if device_id.is_empty() {
+            return Err(Error::InvalidSinkConfiguration(
+                "LAN sync hasn't been set up yet".into(),
+            ));
+        }
+
+        Ok(Self {
+            device_id,
+            name: glib::host_name().to_string(),
+            payload_path: own_payload_path(),
+            peers: PeerStore::Keyring,
+        })
+    }
+
+    async fn read_payload(&self) -> Result<Option<FlatpakInstallationPayload>, Error> {
+        if !self.payload_path.exists() {
+            return Ok(None);
+        }
+
+        let contents = fs::read(&self.payload_path).await?;
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))
+    }
+
+    async fn write_payload(&self, payload: &FlatpakInstallationPayload) -> Result<(), Error> {
+        if let Some(parent) = self.payload_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let contents = serde_json::to_vec_pretty(payload)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+        fs::write(&self.payload_path, contents).await?;
+        Ok(())
+    }
+}
+
+async fn connect_stream(address: SocketAddr) -> Result<(TcpStream, ServerHello), Error> {
+    let mut stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+        .await
+        .map_err(|_| Error::LanFailure(format!("Connecting to {} timed out", address)))??;
+
+    let hello: ServerHello = protocol::recv(&mut stream).await?;
+    if hello.version != PROTOCOL_VERSION {
+        return Err(Error::LanFailure(format!(
+            "{} speaks protocol version {}, we speak {}",
+            hello.name, hello.version, PROTOCOL_VERSION
+        )));
+    }
+
+    Ok((stream, hello))
+}
+
+/// Connects `node` to the paired device at `address` and sets up an encrypted session with it.
+///
+/// # Returns
+/// The ID of the device and the session.
+async fn connect(
+    address: SocketAddr,
+    node: &Node,
+    peers: &BTreeMap<String, Peer>,
+) -> Result<(String, SecureChannel), Error> {
+    let (mut stream, hello) = connect_stream(address).await?;
+    let peer = peers
+        .get(&hello.device_id)
+        .ok_or_else(|| Error::LanFailure(format!("{} isn't paired with us", hello.name)))?;
+
+    let nonce = protocol::new_nonce();
+    protocol::send(
+        &mut stream,
+        &ClientHello::Session {
+            device_id: node.device_id.clone(),
+            nonce: nonce.clone(),
+        },
+    )
+    .await?;
+
+    match protocol::recv::<HelloReply>(&mut stream).await? {
+        HelloReply::Accepted => {}
+        HelloReply::Rejected(reason) => return Err(Error::LanFailure(reason)),
+        HelloReply::Pair { .. } => return Err(Error::LanFailure("Unexpected reply".into())),
+    }
+
+    let key = hex::decode(&peer.key).map_err(|e| Error::LanFailure(e.to_string()))?;
+    Ok((
+        hello.device_id,
+        SecureChannel::new(stream, &key, &nonce, &hello.nonce, true),
+    ))
+}
+
+/// Connects to every paired device we can reach, once each.
+async fn connect_to_peers(node: &Node) -> Result<Vec<(String, SecureChannel)>, Error> {
+    let peers = node.peers.load().await?;
+    if peers.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut seen = BTreeSet::new();
+    let mut channels = vec![];
+
+    for DiscoveredPeer { device_id, address } in discovery::discover().await {
+        if device_id
+            .as_ref()
+            .is_some_and(|id| *id == node.device_id || !peers.contains_key(id) || seen.contains(id))
+        {
+            continue;
+        }
+
+        match connect(address, node, &peers).await {
+            Ok((id, channel)) => {
+                if seen.insert(id.clone()) {
+                    channels.push((id, channel));
+                }
+            }
+            Err(e) => debug!("Couldn't connect to {}: {}", address, e),
+        }
+    }
+
+    Ok(channels)
+}
+
+/// Tells every reachable paired device that our payload changed.
+async fn notify_peers(node: &Node) -> Result<(), Error> {
+    for (id, mut channel) in connect_to_peers(node).await? {
+        let res = async {
+            channel.send(&Request::PayloadChanged).await?;
+            channel.recv::<Response>().await
+        }
+        .await;
+
+        if let Err(e) = res {
+            debug!("Failed to notify {} about our changes: {}", id, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// ## `start_pairing()`
+/// Lets another device pair with us for a few minutes, using the returned code.
+pub fn start_pairing() -> Result<String, Error> {
+    Node::own()?;
+    info!("Waiting for another device to pair with us");
+    Ok(pairing::start())
+}
+
+/// ## `pair()`
+/// Pairs with the device on the network which is currently showing the given code.
+///
+/// # Returns
+/// The name of the device we paired with.
+pub async fn pair(code: &str) -> Result<String, Error> {
+    let node = Node::own()?;
+    let paired = node.peers.load().await?;
+
+    for DiscoveredPeer { device_id, address } in discovery::discover().await {
+        if device_id
+            .as_ref()
+            .is_some_and(|id| *id == node.device_id || paired.contains_key(id))
+        {
+            continue;
+        }
+
+        match pair_with(address, code, &node).await {
+            Ok(name) => {
+                info!("Paired with {}", name);
+                return Ok(name);
+            }
+            Err(e) => debug!("Pairing with {} failed: {}", address, e),
+        }
+    }
+
+    Err(Error::LanFailure(
+        "Found no device waiting for pairing with this code".into(),
+    ))
+}
+
+async fn pair_with(address: SocketAddr, code: &str, node: &Node) -> Result<String, Error> {
+    let (mut stream, hello) = connect_stream(address).await?;
+    if hello.device_id == node.device_id {
+        return Err(Error::LanFailure("Refusing to pair with ourselves".into()));
+    }
+
+    let (state, msg) = Spake2::<Ed25519Group>::start_symmetric(
+        &Password::new(code.trim().as_bytes()),
+        &Identity::new(PAIRING_IDENTITY),
+    );
+    let nonce = protocol::new_nonce();
+    protocol::send(
+        &mut stream,
+        &ClientHello::Pair {
+            device_id: node.device_id.clone(),
+            name: node.name.clone(),
+            nonce: nonce.clone(),
+            spake: hex::encode(msg),
+        },
+    )
+    .await?;
+
+    let spake = match protocol::recv::<HelloReply>(&mut stream).await? {
+        HelloReply::Pair { spake } => spake,
+        HelloReply::Rejected(reason) => return Err(Error::LanFailure(reason)),
+        HelloReply::Accepted => return Err(Error::LanFailure("Unexpected reply".into())),
+    };
+    let spake = hex::decode(spake).map_err(|e| Error::LanFailure(e.to_string()))?;
+    let key = state
+        .finish(&spake)
+        .map_err(|e| Error::LanFailure(format!("{:?}", e)))?;
+
+    // The server only answers if it derived the same key, i.e. if we entered the right code
+    let mut channel = SecureChannel::new(stream, &key, &nonce, &hello.nonce, true);
+    channel.send(&Request::ConfirmPairing).await?;
+    channel.recv::<Response>().await?;
+
+    node.peers
+        .add(
+            &hello.device_id,
+            Peer {
+                name: hello.name.clone(),
+                key: hex::encode(key),
+            },
+        )
+        .await?;
+    Ok(hello.name)
+}
+
+/// ## `peers()`
+/// The names of the devices we are paired with.
+pub async fn peers() -> Result<Vec<String>, Error> {
+    Ok(PeerStore::Keyring
+        .load()
+        .await?
+        .into_values()
+        .map(|peer| peer.name)
+        .collect())
+}
+
+/// ## `unpair()`
+/// Forgets the paired device with the given name, so that it can't fetch our payload anymore.
+pub async fn unpair(name: &str) -> Result<(), Error> {
+    let mut peers = PeerStore::Keyring.load().await?;
+    let count = peers.len();
+    peers.retain(|_, peer| peer.name != name);
+    if peers.len() == count {
+        return Err(Error::LanFailure(format!("Not paired with {}", name)));
+    }
+
+    PeerStore::Keyring.save(&peers).await
+}
+
+/// A data sink without any server in between: daemons on the same network find each other through mDNS/DNS-SD
+/// and exchange their payloads directly, over a channel encrypted with a key agreed on once while pairing.
+///
+/// The sink ID is the random ID of this device. Fetching returns the most recently altered payload of all
+/// reachable paired devices, including our own, while updating notifies them that they should poll us.
+pub struct LanDataSink {
+    server: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl LanDataSink {
+    pub async fn new() -> Result<Self, Error> {
+        Ok(Self {
+            server: Mutex::new(None),
+        })
+    }
+}
+
+impl Drop for LanDataSink {
+    fn drop(&mut self) {
+        if let Some(server) = self.server.lock().unwrap().take() {
+            server.abort();
+        }
+    }
+}
+
+#[async_trait]
+impl DataSink for LanDataSink {
+    async fn create(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        if self.sink_id().is_empty() {
+            self.set_sink_id(&hex::encode(rand::random::<[u8; 16]>()));
+        }
+
+        Node::own()?.write_payload(&payload).await
+    }
+
+    async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error> {
+        let node = Node::own()?;
+        let mut payloads = node.read_payload().await?.into_iter().collect::<Vec<_>>();
+
+        for (id, mut channel) in connect_to_peers(&node).await? {
+            let res = async {
+                channel.send(&Request::GetPayload).await?;
+                channel.recv::<Response>().await
+            }
+            .await;
+
+            match res {
+                Ok(Response::Payload(Some(payload))) => payloads.push(payload),
+                Ok(_) => debug!("{} doesn't have a payload yet", id),
+                Err(e) => debug!("Failed to fetch payload from {}: {}", id, e),
+            }
+        }
+
+        payloads
+            .into_iter()
+            .max_by_key(|payload| payload.altered_at)
+            .ok_or(Error::MissingGistFiles)
+    }
+
+    async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        let node = Node::own()?;
+        node.write_payload(&payload).await?;
+
+        // Discovery takes a moment, there's no need to hold up the sync for that
+        tokio::spawn(async move {
+            if let Err(e) = notify_peers(&node).await {
+                warn!("Failed to notify paired devices about our changes: {}", e);
+            }
+        });
+
+        Ok(())
+    }
+
+    async fn delete(&self) -> Result<(), Error> {
+        let path = own_payload_path();
+        if path.exists() {
+            fs::remove_file(&path).await?;
+        }
+        Ok(())
+    }
+
+    /// Starts serving our payload to paired devices and announcing ourselves on the network.
+    /// Paired devices reporting changes make us poll.
+    fn watch(&self, sender: Sender<MessageType>) -> Result<(), Error> {
+        if !self.is_initialised() {
+            return Ok(());
+        }
+
+        let mut server = self.server.lock().unwrap();
+        if server.as_ref().is_some_and(|server| !server.is_finished()) {
+            return Ok(());
+        }
+
+        let port: u16 = Settings::instance().get("lan-port");
+        let listener = std::net::TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        let port = listener.local_addr()?.port();
+        let listener = tokio::net::TcpListener::from_std(listener)?;
+        let node = Node::own()?;
+
+        info!("Listening for paired devices on port {}", port);
+        *server = Some(tokio::spawn(async move {
+            let _advertisement = discovery::advertise(&node.device_id, &node.name, port)
+                .map_err(|e| warn!("Failed to announce ourselves on the network: {}", e))
+                .ok();
+            server::run(listener, sender, node).await;
+        }));
+
+        Ok(())
+    }
+
+    fn sink_name(&self) -> &'static str {
+        LAN_SINK_NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sinks::{data_sink::FILE_NAME, stand_in::payload};
+    use std::{path::Path, sync::Arc};
+    use tokio::{net::TcpListener, sync::mpsc::Receiver};
+
+    fn node(name: &str, root: &Path) -> Node {
+        Node {
+            device_id: format!("{}-id", name),
+            name: name.into(),
+            payload_path: root.join(name).join(FILE_NAME),
+            peers: PeerStore::Memory(Arc::default()),
+        }
+    }
+
+    /// Serves `node` on a loopback port, like the sink does on the network once it is watched.
+    async fn serve(node: &Node) -> (SocketAddr, Receiver<MessageType>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        let (sender, receiver) = tokio::sync::mpsc::channel(4);
+        tokio::spawn(server::run(listener, sender, node.clone()));
+        (address, receiver)
+    }
+
+    #[tokio::test]
+    async fn pairs_and_syncs_two_daemons_on_loopback() {
+        let root = std::env::temp_dir().join(format!("flatsync-lan-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let (first, second) = (node("first", &root), node("second", &root));
+        first.write_payload(&payload(1)).await.unwrap();
+        let (address, mut polls) = serve(&first).await;
+
+        // Pairing needs an open pairing window and the code it shows
+        assert!(pair_with(address, "123456", &second).await.is_err());
+        let code = pairing::start();
+        assert!(pair_with(address, "wrong", &second).await.is_err());
+        assert_eq!(pair_with(address, &code, &second).await.unwrap(), "first");
+        assert!(first.peers.load().await.unwrap().contains_key("second-id"));
+
+        // The second device fetches the payload of the first one over the encrypted channel
+        let peers = second.peers.load().await.unwrap();
+        let (id, mut channel) = connect(address, &second, &peers).await.unwrap();
+        assert_eq!(id, "first-id");
+        channel.send(&Request::GetPayload).await.unwrap();
+        match channel.recv::<Response>().await.unwrap() {
+            Response::Payload(Some(fetched)) => {
+                assert_eq!(fetched.altered_at, payload(1).altered_at)
+            }
+            _ => panic!("The first device didn't serve its payload"),
+        }
+
+        // Reporting changes makes the first device poll
+        channel.send(&Request::PayloadChanged).await.unwrap();
+        channel.recv::<Response>().await.unwrap();
+        assert!(matches!(
+            polls.recv().await,
+            Some(MessageType::TimeToPoll(None))
+        ));
+
+        // Devices the first one isn't paired with are turned away, even if they claim to know its key
+        let third = node("third", &root);
+        let claimed = BTreeMap::from([(
+            "first-id".to_string(),
+            Peer {
+                name: "first".into(),
+                key: hex::encode([0u8; 32]),
+            },
+        )]);
+        assert!(connect(address, &third, &claimed).await.is_err());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,108 @@
+//! Finding other FlatSync daemons on the network through mDNS/DNS-SD.
+
+use crate::{settings::Settings, Error};
+use log::{debug, warn};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+
+pub const SERVICE_TYPE: &str = "_flatsync._tcp.local.";
+/// How long we listen for announcements when looking for peers.
+const BROWSE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A FlatSync daemon we might be able to talk to.
+#[derive(Debug, Clone)]
+pub struct DiscoveredPeer {
+    /// The ID the daemon announced, unknown for the peers configured in `lan-static-peers`.
+    pub device_id: Option<String>,
+    pub address: SocketAddr,
+}
+
+/// Keeps announcing our daemon on the network until it is dropped.
+pub struct Advertisement(ServiceDaemon);
+
+impl Drop for Advertisement {
+    fn drop(&mut self) {
+        self.0.shutdown().ok();
+    }
+}
+
+pub fn advertise(device_id: &str, name: &str, port: u16) -> Result<Advertisement, Error> {
+    let daemon = ServiceDaemon::new().map_err(|e| Error::LanFailure(e.to_string()))?;
+    let host_name = format!("{}.local.", glib::host_name());
+    let properties = HashMap::from([
+        ("id".to_string(), device_id.to_string()),
+        ("name".to_string(), name.to_string()),
+    ]);
+
+    let service = ServiceInfo::new(SERVICE_TYPE, device_id, &host_name, "", port, properties)
+        .map_err(|e| Error::LanFailure(e.to_string()))?
+        .enable_addr_auto();
+    daemon
+        .register(service)
+        .map_err(|e| Error::LanFailure(e.to_string()))?;
+
+    debug!("Announcing FlatSync on port {}", port);
+    Ok(Advertisement(daemon))
+}
+
+/// The peers configured in the `lan-static-peers` setting, e.g. for networks without multicast or testing on loopback.
+fn static_peers() -> Vec<DiscoveredPeer> {
+    Settings::instance()
+        .get::<Vec<String>>("lan-static-peers")
+        .iter()
+        .filter_map(|peer| match peer.to_socket_addrs() {
+            Ok(mut addresses) => addresses.next(),
+            Err(e) => {
+                warn!("Ignoring invalid static peer {:?}: {}", peer, e);
+                None
+            }
+        })
+        .map(|address| DiscoveredPeer {
+            device_id: None,
+            address,
+        })
+        .collect()
+}
+
+/// Looks for other FlatSync daemons, starting with the statically configured ones.
+pub async fn discover() -> Vec<DiscoveredPeer> {
+    let mut peers = static_peers();
+
+    let daemon = match ServiceDaemon::new() {
+        Ok(daemon) => daemon,
+        Err(e) => {
+            warn!("Failed to start mDNS discovery: {}", e);
+            return peers;
+        }
+    };
+    let receiver = match daemon.browse(SERVICE_TYPE) {
+        Ok(receiver) => receiver,
+        Err(e) => {
+            warn!("Failed to browse for FlatSync daemons: {}", e);
+            return peers;
+        }
+    };
+
+    let deadline = tokio::time::Instant::now() + BROWSE_TIMEOUT;
+    while let Ok(Ok(event)) = tokio::time::timeout_at(deadline, receiver.recv_async()).await {
+        let ServiceEvent::ServiceResolved(service) = event else {
+            continue;
+        };
+
+        let device_id = service.get_property_val_str("id").map(String::from);
+        debug!("Discovered FlatSync daemon {:?}", device_id);
+        for address in service.get_addresses() {
+            peers.push(DiscoveredPeer {
+                device_id: device_id.clone(),
+                address: SocketAddr::new(IpAddr::from(*address), service.get_port()),
+            });
+        }
+    }
+
+    daemon.shutdown().ok();
+    peers
+}
@@ -0,0 +1,220 @@
+//! The wire protocol spoken between FlatSync daemons on the same network.
+//!
+//! Every message is a length-prefixed JSON document. After the unencrypted hello exchange, both sides derive
+//! a key per direction from the key agreed on while pairing and the nonces of the hello messages, and all
+//! further messages are encrypted with ChaCha20-Poly1305. A peer which doesn't know the pairing key can
+//! neither read nor forge them.
+
+use crate::Error;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Nonce,
+};
+use hkdf::Hkdf;
+use libflatsync_common::FlatpakInstallationPayload;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use sha2::Sha256;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    net::TcpStream,
+};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Identity both sides use for SPAKE2, as the pairing code is all they share.
+pub const PAIRING_IDENTITY: &[u8] = b"app.drey.FlatSync.Pairing";
+const SESSION_INFO: &[u8] = b"app.drey.FlatSync.Session";
+// Payloads are a few hundred KiB at most, anything bigger is garbage
+const MAX_FRAME_LEN: u32 = 16 * 1024 * 1024;
+/// The hello messages are exchanged before the other side proved anything, so they have to stay small.
+const MAX_HANDSHAKE_FRAME_LEN: u32 = 16 * 1024;
+
+/// Sent by the server as soon as a client connects.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ServerHello {
+    pub version: u32,
+    pub device_id: String,
+    pub name: String,
+    pub nonce: String,
+}
+
+/// The client's answer to [`ServerHello`], either starting a pairing or a session with a paired device.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum ClientHello {
+    Pair {
+        device_id: String,
+        name: String,
+        nonce: String,
+        spake: String,
+    },
+    Session {
+        device_id: String,
+        nonce: String,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum HelloReply {
+    Pair { spake: String },
+    Accepted,
+    Rejected(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Request {
+    /// Proves to the server that both sides derived the same key from the pairing code.
+    ConfirmPairing,
+    GetPayload,
+    /// Tells the server that our payload changed, so that it polls us.
+    PayloadChanged,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Response {
+    Ok,
+    Payload(Option<FlatpakInstallationPayload>),
+}
+
+/// A fresh random nonce for the hello messages, hex encoded.
+pub fn new_nonce() -> String {
+    hex::encode(rand::random::<[u8; 32]>())
+}
+
+/// Sends an unencrypted handshake message.
+pub async fn send<T: Serialize>(
+    stream: &mut (impl AsyncWrite + Unpin),
+    msg: &T,
+) -> Result<(), Error> {
+    let data = serde_json::to_vec(msg).map_err(|e| Error::LanFailure(e.to_string()))?;
+    write_frame(stream, &data).await
+}
+
+/// Receives an unencrypted handshake message.
+pub async fn recv<T: DeserializeOwned>(stream: &mut (impl AsyncRead + Unpin)) -> Result<T, Error> {
+    let data = read_frame(stream, MAX_HANDSHAKE_FRAME_LEN).await?;
+    serde_json::from_slice(&data).map_err(|e| Error::LanFailure(e.to_string()))
+}
+
+async fn write_frame(stream: &mut (impl AsyncWrite + Unpin), data: &[u8]) -> Result<(), Error> {
+    stream.write_u32(data.len() as u32).await?;
+    stream.write_all(data).await?;
+    stream.flush().await?;
+    Ok(())
+}
+
+async fn read_frame(stream: &mut (impl AsyncRead + Unpin), max_len: u32) -> Result<Vec<u8>, Error> {
+    let len = stream.read_u32().await?;
+    if len > max_len {
+        return Err(Error::LanFailure(format!(
+            "Frame of {} bytes is too big",
+            len
+        )));
+    }
+
+    // The buffer grows with the data actually received, announcing a big frame alone doesn't allocate it
+    let mut data = Vec::new();
+    (&mut *stream)
+        .take(len.into())
+        .read_to_end(&mut data)
+        .await?;
+    if data.len() != len as usize {
+        return Err(Error::LanFailure(
+            "Connection closed in the middle of a frame".into(),
+        ));
+    }
+    Ok(data)
+}
+
+/// An encrypted and authenticated connection to a paired device.
+pub struct SecureChannel {
+    stream: TcpStream,
+    send_cipher: ChaCha20Poly1305,
+    recv_cipher: ChaCha20Poly1305,
+    send_counter: u64,
+    recv_counter: u64,
+}
+
+impl SecureChannel {
+    /// Derives the session keys from the pairing `key` and the nonces both sides sent in their hello messages.
+    pub fn new(
+        stream: TcpStream,
+        key: &[u8],
+        client_nonce: &str,
+        server_nonce: &str,
+        is_client: bool,
+    ) -> Self {
+        let salt = [client_nonce.as_bytes(), server_nonce.as_bytes()].concat();
+        let mut okm = [0u8; 64];
+        Hkdf::<Sha256>::new(Some(&salt), key)
+            .expand(SESSION_INFO, &mut okm)
+            .unwrap();
+
+        let (client_key, server_key) = okm.split_at(32);
+        let (send_key, recv_key) = if is_client {
+            (client_key, server_key)
+        } else {
+            (server_key, client_key)
+        };
+
+        Self {
+            stream,
+            send_cipher: ChaCha20Poly1305::new_from_slice(send_key).unwrap(),
+            recv_cipher: ChaCha20Poly1305::new_from_slice(recv_key).unwrap(),
+            send_counter: 0,
+            recv_counter: 0,
+        }
+    }
+
+    /// Every message uses the next value of its direction's counter as nonce, so they can't be replayed or reordered.
+    fn nonce(counter: u64) -> Nonce {
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        *Nonce::from_slice(&nonce)
+    }
+
+    pub async fn send<T: Serialize>(&mut self, msg: &T) -> Result<(), Error> {
+        let plaintext = serde_json::to_vec(msg).map_err(|e| Error::LanFailure(e.to_string()))?;
+        let ciphertext = self
+            .send_cipher
+            .encrypt(&Self::nonce(self.send_counter), plaintext.as_slice())
+            .map_err(|_| Error::LanFailure("Failed to encrypt message".into()))?;
+        self.send_counter += 1;
+
+        write_frame(&mut self.stream, &ciphertext).await
+    }
+
+    pub async fn recv<T: DeserializeOwned>(&mut self) -> Result<T, Error> {
+        let ciphertext = read_frame(&mut self.stream, MAX_FRAME_LEN).await?;
+        let plaintext = self
+            .recv_cipher
+            .decrypt(&Self::nonce(self.recv_counter), ciphertext.as_slice())
+            .map_err(|_| Error::LanAuthenticationFailure)?;
+        self.recv_counter += 1;
+
+        serde_json::from_slice(&plaintext).map_err(|e| Error::LanFailure(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn rejects_big_frames_during_the_handshake() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_u32(MAX_FRAME_LEN).await.unwrap();
+
+        let res = recv::<ClientHello>(&mut server).await;
+        assert!(matches!(res, Err(Error::LanFailure(_))));
+    }
+
+    #[tokio::test]
+    async fn rejects_truncated_frames() {
+        let (mut client, mut server) = tokio::io::duplex(64);
+        client.write_u32(32).await.unwrap();
+        client.write_all(b"{}").await.unwrap();
+        drop(client);
+
+        let res = recv::<ClientHello>(&mut server).await;
+        assert!(matches!(res, Err(Error::LanFailure(_))));
+    }
+}
@@ -0,0 +1,142 @@
+//! Serving our payload to paired devices and handling pairing requests.
+
+use super::{
+    pairing,
+    protocol::{
+        self, ClientHello, HelloReply, Request, Response, SecureChannel, ServerHello,
+        PAIRING_IDENTITY, PROTOCOL_VERSION,
+    },
+    Node, Peer,
+};
+use crate::{Error, MessageType};
+use log::{debug, info, warn};
+use spake2::{Ed25519Group, Identity, Password, Spake2};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc::Sender,
+};
+
+/// Accepts connections from other daemons to `node` until the task is aborted.
+pub async fn run(listener: TcpListener, sender: Sender<MessageType>, node: Node) {
+    loop {
+        let (stream, address) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Failed to accept LAN connection: {}", e);
+                continue;
+            }
+        };
+
+        let sender = sender.clone();
+        let node = node.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_connection(stream, sender, &node).await {
+                debug!("LAN connection from {} failed: {}", address, e);
+            }
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    sender: Sender<MessageType>,
+    node: &Node,
+) -> Result<(), Error> {
+    let server_nonce = protocol::new_nonce();
+    protocol::send(
+        &mut stream,
+        &ServerHello {
+            version: PROTOCOL_VERSION,
+            device_id: node.device_id.clone(),
+            name: node.name.clone(),
+            nonce: server_nonce.clone(),
+        },
+    )
+    .await?;
+
+    match protocol::recv::<ClientHello>(&mut stream).await? {
+        ClientHello::Pair {
+            device_id,
+            name,
+            nonce,
+            spake,
+        } => {
+            let Some(code) = pairing::code() else {
+                protocol::send(
+                    &mut stream,
+                    &HelloReply::Rejected("Not waiting for pairing".into()),
+                )
+                .await?;
+                return Ok(());
+            };
+
+            let (state, msg) = Spake2::<Ed25519Group>::start_symmetric(
+                &Password::new(code.as_bytes()),
+                &Identity::new(PAIRING_IDENTITY),
+            );
+            protocol::send(
+                &mut stream,
+                &HelloReply::Pair {
+                    spake: hex::encode(msg),
+                },
+            )
+            .await?;
+
+            let spake = hex::decode(spake).map_err(|e| Error::LanFailure(e.to_string()))?;
+            let key = state
+                .finish(&spake)
+                .map_err(|e| Error::LanFailure(format!("{:?}", e)))?;
+
+            let mut channel = SecureChannel::new(stream, &key, &nonce, &server_nonce, false);
+            match channel.recv::<Request>().await {
+                Ok(Request::ConfirmPairing) => {}
+                // Most likely the other side entered a different code
+                _ => {
+                    pairing::failed_attempt();
+                    return Err(Error::LanAuthenticationFailure);
+                }
+            }
+
+            node.peers
+                .add(
+                    &device_id,
+                    Peer {
+                        name: name.clone(),
+                        key: hex::encode(key),
+                    },
+                )
+                .await?;
+            pairing::finish();
+            channel.send(&Response::Ok).await?;
+            info!("Paired with {}", name);
+        }
+        ClientHello::Session { device_id, nonce } => {
+            let Some(peer) = node.peers.load().await?.remove(&device_id) else {
+                protocol::send(&mut stream, &HelloReply::Rejected("Unknown device".into())).await?;
+                return Ok(());
+            };
+            protocol::send(&mut stream, &HelloReply::Accepted).await?;
+
+            let key = hex::decode(&peer.key).map_err(|e| Error::LanFailure(e.to_string()))?;
+            let mut channel = SecureChannel::new(stream, &key, &nonce, &server_nonce, false);
+            // The connection is closed by the client once it's done
+            while let Ok(request) = channel.recv::<Request>().await {
+                match request {
+                    Request::GetPayload => {
+                        channel
+                            .send(&Response::Payload(node.read_payload().await?))
+                            .await?
+                    }
+                    Request::PayloadChanged => {
+                        debug!("{} reported changes, requesting a poll", peer.name);
+                        sender.send(MessageType::TimeToPoll(None)).await.ok();
+                        channel.send(&Response::Ok).await?;
+                    }
+                    Request::ConfirmPairing => channel.send(&Response::Ok).await?,
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
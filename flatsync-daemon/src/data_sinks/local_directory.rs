@@ -0,0 +1,322 @@
+use super::data_sink::{DataSink, FILE_NAME};
+use crate::{Error, MessageType};
+use async_trait::async_trait;
+use gio::prelude::*;
+use libflatsync_common::FlatpakInstallationPayload;
+use log::{debug, error, trace};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::SystemTime,
+};
+use tokio::{fs, io::AsyncWriteExt, sync::mpsc::Sender};
+
+static LOCAL_DIRECTORY_SINK_NAME: &str = "local-directory";
+
+// The states of the sync files we wrote, by path. Shared between the D-Bus interface and the main loop, which each hold
+// their own sink and watch, so that neither takes the other's writes for changes made by other devices
+static KNOWN_STATES: Mutex<BTreeMap<PathBuf, FileState>> = Mutex::new(BTreeMap::new());
+
+/// Snapshot of the sync file, used to tell changes made by other devices apart from our own writes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FileState {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl FileState {
+    fn from_contents(modified: Option<SystemTime>, contents: &[u8]) -> Self {
+        let mut hasher = DefaultHasher::new();
+        contents.hash(&mut hasher);
+
+        Self {
+            modified,
+            len: contents.len() as u64,
+            hash: hasher.finish(),
+        }
+    }
+
+    /// Reads the current state of the file at `path`, returning `None` if it doesn't exist (anymore).
+    fn read(path: &Path) -> Option<Self> {
+        let modified = std::fs::metadata(path).ok()?.modified().ok();
+        let contents = std::fs::read(path).ok()?;
+        Some(Self::from_contents(modified, &contents))
+    }
+
+    fn remember(path: &Path, state: FileState) {
+        KNOWN_STATES
+            .lock()
+            .unwrap()
+            .insert(path.to_path_buf(), state);
+    }
+
+    fn forget(path: &Path) {
+        KNOWN_STATES.lock().unwrap().remove(path);
+    }
+
+    /// Whether the file at `path` changed since we last wrote it or noticed it changing, remembering its current state if so.
+    fn changed_on_disk(path: &Path) -> bool {
+        let Some(current) = Self::read(path) else {
+            return false;
+        };
+
+        let mut known = KNOWN_STATES.lock().unwrap();
+        if known
+            .get(path)
+            .is_some_and(|last| !current.differs_from(last))
+        {
+            return false;
+        }
+        known.insert(path.to_path_buf(), current);
+        true
+    }
+
+    /// Whether `other` differs from this state.
+    ///
+    /// Tools like Syncthing preserve the modification time of the originating device, so we only rely on it
+    /// to skip hashing the file when nothing changed at all, and fall back to the content hash otherwise.
+    fn differs_from(&self, other: &FileState) -> bool {
+        if self.modified.is_some() && self.modified == other.modified && self.len == other.len {
+            return false;
+        }
+
+        self.hash != other.hash
+    }
+}
+
+/// A data sink storing the payload in a directory which is kept in sync between devices by an external tool,
+/// e.g. Syncthing or the Nextcloud desktop client.
+///
+/// The sink ID is the path of that directory.
+pub struct LocalDirectoryDataSink {
+    watch: Mutex<Option<(PathBuf, gio::Cancellable)>>,
+}
+
+impl LocalDirectoryDataSink {
+    pub async fn new() -> Result<Self, Error> {
+        Ok(Self {
+            watch: Mutex::new(None),
+        })
+    }
+
+    fn directory(&self) -> PathBuf {
+        PathBuf::from(self.sink_id())
+    }
+
+    fn file_path(&self) -> PathBuf {
+        self.directory().join(FILE_NAME)
+    }
+
+    /// Writes `contents` to `path` by writing a temporary file next to it and renaming it over the original,
+    /// so that neither the sync tool nor other devices ever pick up a half-written file.
+    async fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Error> {
+        let tmp_path = path.with_file_name(format!(".{}.{}.tmp", FILE_NAME, std::process::id()));
+
+        let mut file = fs::File::create(&tmp_path).await?;
+        file.write_all(contents).await?;
+        file.sync_all().await?;
+        drop(file);
+
+        if let Err(e) = fs::rename(&tmp_path, path).await {
+            let _ = fs::remove_file(&tmp_path).await;
+            return Err(e.into());
+        }
+
+        Ok(())
+    }
+
+    async fn write_payload(path: &Path, payload: &FlatpakInstallationPayload) -> Result<(), Error> {
+        if let Some(directory) = path.parent() {
+            fs::create_dir_all(directory).await?;
+        }
+
+        let contents = serde_json::to_vec_pretty(payload)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+        Self::write_atomically(path, &contents).await?;
+
+        let modified = fs::metadata(path).await?.modified().ok();
+        FileState::remember(path, FileState::from_contents(modified, &contents));
+
+        Ok(())
+    }
+
+    async fn read_payload(path: &Path) -> Result<FlatpakInstallationPayload, Error> {
+        let contents = fs::read(path).await?;
+        serde_json::from_slice(&contents)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))
+    }
+}
+
+/// Stops the watch, so that a sink replaced after changing the sink provider or reloading it doesn't keep requesting polls.
+impl Drop for LocalDirectoryDataSink {
+    fn drop(&mut self) {
+        if let Some((directory, cancellable)) = self.watch.lock().unwrap().take() {
+            trace!("No longer watching {:?}", directory);
+            cancellable.cancel();
+        }
+    }
+}
+
+#[async_trait]
+impl DataSink for LocalDirectoryDataSink {
+    async fn create(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        debug!("Creating FlatSync file in {:?}", self.directory());
+        Self::write_payload(&self.file_path(), &payload).await
+    }
+
+    // Fetching doesn't mark the file as known, as a fetch by one of the sinks could otherwise hide a change from the watch
+    // of the other, which would then never request a poll for it
+    async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error> {
+        Self::read_payload(&self.file_path()).await
+    }
+
+    async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        Self::write_payload(&self.file_path(), &payload).await
+    }
+
+    async fn delete(&self) -> Result<(), Error> {
+        let path = self.file_path();
+        if path.exists() {
+            fs::remove_file(&path).await?;
+        }
+        FileState::forget(&path);
+        Ok(())
+    }
+
+    /// The sink counts as initialised once the directory contains a FlatSync file, so that `create` can be
+    /// used for the first device while the others simply point the sink at the same directory.
+    fn is_initialised(&self) -> bool {
+        !self.sink_id().is_empty() && self.file_path().exists()
+    }
+
+    fn watch(&self, sender: Sender<MessageType>) -> Result<(), Error> {
+        let directory = self.directory();
+        if directory.as_os_str().is_empty() || !directory.is_dir() {
+            return Ok(());
+        }
+
+        let mut watch = self.watch.lock().unwrap();
+        if let Some((watched, cancellable)) = watch.as_ref() {
+            if *watched == directory {
+                return Ok(());
+            }
+            cancellable.cancel();
+        }
+
+        let cancellable = gio::Cancellable::new();
+        *watch = Some((directory.clone(), cancellable.clone()));
+        drop(watch);
+
+        let file_path = directory.join(FILE_NAME);
+
+        debug!("Watching {:?} for changes", directory);
+
+        // `gio::FileMonitor` isn't `Send`, so it lives on its own thread with its own main context until the
+        // watch is cancelled, e.g. because the sink was pointed at another directory.
+        std::thread::spawn(move || {
+            let context = glib::MainContext::new();
+            let main_loop = glib::MainLoop::new(Some(&context), false);
+
+            let res = context.with_thread_default(|| {
+                let monitor = gio::File::for_path(&directory)
+                    .monitor_directory(gio::FileMonitorFlags::WATCH_MOVES, Some(&cancellable))?;
+
+                monitor.connect_changed(move |_, file, other_file, event| {
+                    let concerns_sync_file = |f: Option<&gio::File>| {
+                        f.and_then(|f| f.basename())
+                            .is_some_and(|name| name.as_os_str() == FILE_NAME)
+                    };
+
+                    if !matches!(
+                        event,
+                        gio::FileMonitorEvent::ChangesDoneHint
+                            | gio::FileMonitorEvent::Created
+                            | gio::FileMonitorEvent::MovedIn
+                            | gio::FileMonitorEvent::Renamed
+                    ) || !(concerns_sync_file(Some(file)) || concerns_sync_file(other_file))
+                    {
+                        return;
+                    }
+
+                    if !FileState::changed_on_disk(&file_path) {
+                        trace!("Ignoring event {:?}, file is unchanged", event);
+                        return;
+                    }
+
+                    debug!("FlatSync file changed on disk, requesting a poll");
+                    if sender.blocking_send(MessageType::TimeToPoll(None)).is_err() {
+                        error!("Failed to request a poll after the FlatSync file changed");
+                    }
+                });
+
+                let quit_loop = main_loop.clone();
+                cancellable.connect_cancelled(move |_| quit_loop.quit());
+                if !cancellable.is_cancelled() {
+                    main_loop.run();
+                }
+
+                Ok::<_, glib::Error>(())
+            });
+
+            match res {
+                Ok(Err(e)) => error!("Failed to watch {:?}: {}", directory, e),
+                Err(e) => error!(
+                    "Failed to acquire main context for watching {:?}: {}",
+                    directory, e
+                ),
+                Ok(Ok(())) => {}
+            }
+        });
+
+        Ok(())
+    }
+
+    fn sink_name(&self) -> &'static str {
+        LOCAL_DIRECTORY_SINK_NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sinks::stand_in::payload;
+
+    #[tokio::test]
+    async fn tells_changes_of_other_devices_from_own_writes() {
+        let dir = std::env::temp_dir().join(format!(
+            "flatsync-local-directory-test-{}",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join(FILE_NAME);
+
+        // Writes by any of our sinks, e.g. the one of the D-Bus interface, aren't taken for changes by the watch
+        LocalDirectoryDataSink::write_payload(&path, &payload(1))
+            .await
+            .unwrap();
+        assert!(!FileState::changed_on_disk(&path));
+        let fetched = LocalDirectoryDataSink::read_payload(&path).await.unwrap();
+        assert_eq!(fetched.altered_at, payload(1).altered_at);
+
+        // The file is replaced rather than written in place, without leaving the temporary file behind
+        let files = std::fs::read_dir(&dir).unwrap().count();
+        assert_eq!(files, 1);
+
+        // The sync tool brings in the list of another device, which is only reported once
+        std::fs::write(&path, serde_json::to_vec(&payload(2)).unwrap()).unwrap();
+        assert!(FileState::changed_on_disk(&path));
+        assert!(!FileState::changed_on_disk(&path));
+
+        LocalDirectoryDataSink::write_payload(&path, &payload(3))
+            .await
+            .unwrap();
+        assert!(!FileState::changed_on_disk(&path));
+        let fetched = LocalDirectoryDataSink::read_payload(&path).await.unwrap();
+        assert_eq!(fetched.altered_at, payload(3).altered_at);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -1,7 +1,29 @@
 pub mod data_sink;
 pub mod data_sink_client;
+mod etag;
+pub mod flatsync_server;
+pub mod forgejo;
+pub mod git;
 pub mod github;
+pub mod gitlab;
+pub mod health;
+pub mod lan;
+pub mod local_directory;
 mod oauth_client;
+pub mod registry;
 mod rest_client;
+pub mod s3;
+#[cfg(test)]
+mod stand_in;
+pub mod webdav;
 
+pub use flatsync_server::FlatSyncServerDataSink;
+pub use forgejo::ForgejoDataSink;
+pub use git::GitDataSink;
 pub use github::GitHubGistDataSink;
+pub use gitlab::GitLabSnippetDataSink;
+pub use lan::LanDataSink;
+pub use local_directory::LocalDirectoryDataSink;
+pub use registry::BoxedDataSink;
+pub use s3::S3DataSink;
+pub use webdav::WebDavDataSink;
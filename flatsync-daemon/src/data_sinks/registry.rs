@@ -0,0 +1,66 @@
+use super::{
+    data_sink::DataSink, FlatSyncServerDataSink, ForgejoDataSink, GitDataSink, GitHubGistDataSink,
+    GitLabSnippetDataSink, LanDataSink, LocalDirectoryDataSink, S3DataSink, WebDavDataSink,
+};
+use crate::{settings::Settings, Error};
+use libflatsync_common::providers::providers_list::Providers;
+
+pub type BoxedDataSink = Box<dyn DataSink + 'static + Send + Sync>;
+
+/// Constructs the `DataSink` implementation backing the given provider.
+pub async fn new_sink(provider: Providers) -> Result<BoxedDataSink, Error> {
+    Ok(match provider {
+        Providers::Github => Box::new(GitHubGistDataSink::new().await?),
+        Providers::LocalDirectory => Box::new(LocalDirectoryDataSink::new().await?),
+        Providers::Git => Box::new(GitDataSink::new().await?),
+        Providers::Gitlab => Box::new(GitLabSnippetDataSink::new().await?),
+        Providers::Webdav => Box::new(WebDavDataSink::new().await?),
+        Providers::S3 => Box::new(S3DataSink::new().await?),
+        Providers::FlatsyncServer => Box::new(FlatSyncServerDataSink::new().await?),
+        Providers::Forgejo => Box::new(ForgejoDataSink::new().await?),
+        Providers::Lan => Box::new(LanDataSink::new().await?),
+    })
+}
+
+/// Constructs the `DataSink` implementation for a provider name, e.g. `github`.
+pub async fn new_sink_by_name(name: &str) -> Result<BoxedDataSink, Error> {
+    let provider =
+        Providers::from_name(name).ok_or_else(|| Error::UnknownSinkProvider(name.into()))?;
+    new_sink(provider).await
+}
+
+/// The name of the provider currently selected in the `sink-provider` setting.
+pub fn configured_provider() -> String {
+    Settings::instance().get("sink-provider")
+}
+
+/// Persists the given provider name in the `sink-provider` setting.
+pub fn set_configured_provider(name: &str) {
+    Settings::instance().set("sink-provider", name).unwrap();
+}
+
+/// Makes the given provider the primary one and drops it from the mirrors, in a single settings change.
+pub fn switch_provider(name: &str) {
+    let mirrors = mirror_providers()
+        .into_iter()
+        .filter(|m| m != name)
+        .collect::<Vec<_>>();
+
+    let settings = Settings::instance();
+    settings.delay();
+    set_configured_provider(name);
+    set_mirror_providers(&mirrors);
+    settings.apply();
+}
+
+/// The names of the providers selected in the `mirror-sink-providers` setting.
+pub fn mirror_providers() -> Vec<String> {
+    Settings::instance().get("mirror-sink-providers")
+}
+
+/// Persists the given provider names in the `mirror-sink-providers` setting.
+pub fn set_mirror_providers(names: &[String]) {
+    Settings::instance()
+        .set("mirror-sink-providers", names.to_vec())
+        .unwrap();
+}
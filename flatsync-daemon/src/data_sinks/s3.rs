@@ -0,0 +1,409 @@
+use self::client::{Bucket, S3Client};
+use async_trait::async_trait;
+use libflatsync_common::FlatpakInstallationPayload;
+use log::debug;
+use reqwest::{header, Method, StatusCode};
+
+use super::{data_sink::DataSink, etag::KnownEtag};
+use crate::{data_sinks::data_sink::FILE_NAME, settings::Settings, Error};
+
+static S3_SINK_NAME: &str = "s3";
+
+pub mod client {
+    use crate::data_sinks::data_sink_client::DataSinkClient;
+    use crate::settings::Settings;
+    use crate::Error;
+    use hmac::{Hmac, Mac};
+    use reqwest::{Method, Url};
+    use sha2::{Digest, Sha256};
+
+    static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+    /// The access key pair stored in the keyring.
+    #[derive(serde::Serialize, serde::Deserialize)]
+    pub struct S3Credentials {
+        pub access_key_id: String,
+        pub secret_access_key: String,
+    }
+
+    /// The bucket to store the payload in, together with the keys to access it with.
+    pub struct Bucket {
+        pub endpoint: String,
+        pub name: String,
+        pub region: String,
+        pub credentials: S3Credentials,
+    }
+
+    /// Looks the access key pair up in the keyring.
+    struct Keyring;
+
+    impl DataSinkClient for Keyring {
+        fn sink_name(&self) -> &'static str {
+            super::S3_SINK_NAME
+        }
+    }
+
+    impl Bucket {
+        /// The bucket configured in the settings, with the access key pair from the keyring.
+        pub async fn load() -> Result<Self, Error> {
+            let settings = Settings::instance();
+            let credentials = serde_json::from_str::<S3Credentials>(&Keyring.secret_raw().await?)
+                .map_err(|_| Error::KeychainEntryNotFound)?;
+
+            Ok(Self {
+                endpoint: settings.get("s3-endpoint"),
+                name: settings.get("s3-bucket"),
+                region: settings.get("s3-region"),
+                credentials,
+            })
+        }
+    }
+
+    /// Minimal client for S3-compatible object storage, signing its requests with AWS Signature Version 4.
+    ///
+    /// Objects are addressed path-style (`<endpoint>/<bucket>/<key>`), which is what MinIO and most other S3-compatible servers expect.
+    pub struct S3Client {
+        method: Method,
+        url: Url,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    /// Percent-encodes everything but unreserved characters (and `/` if `keep_slash` is set), as required for canonical requests.
+    fn uri_encode(input: &str, keep_slash: bool) -> String {
+        input
+            .bytes()
+            .map(|b| match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    (b as char).to_string()
+                }
+                b'/' if keep_slash => "/".to_string(),
+                _ => format!("%{:02X}", b),
+            })
+            .collect()
+    }
+
+    fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    impl S3Client {
+        pub fn new(method: Method, bucket: &Bucket, key: &str) -> Result<S3Client, Error> {
+            let url = Url::parse(&format!(
+                "{}/{}/{}",
+                bucket.endpoint.trim_end_matches('/'),
+                uri_encode(&bucket.name, false),
+                uri_encode(key, true)
+            ))
+            .map_err(|e| Error::InvalidSinkConfiguration(e.to_string()))?;
+
+            Ok(S3Client {
+                method,
+                url,
+                headers: vec![],
+                body: vec![],
+            })
+        }
+
+        pub fn header(mut self, key: &str, value: &str) -> Self {
+            self.headers.push((key.to_lowercase(), value.to_string()));
+            self
+        }
+
+        pub fn body(mut self, body: Vec<u8>) -> Self {
+            self.body = body;
+            self
+        }
+
+        /// Computes the `Authorization` header for the request, signing the host, date and payload hash.
+        fn authorization(
+            &self,
+            bucket: &Bucket,
+            amz_date: &str,
+            payload_hash: &str,
+            host: &str,
+        ) -> String {
+            let credentials = &bucket.credentials;
+            let date = &amz_date[..8];
+            let scope = format!("{}/{}/s3/aws4_request", date, bucket.region);
+
+            let mut query = self
+                .url
+                .query_pairs()
+                .map(|(k, v)| format!("{}={}", uri_encode(&k, false), uri_encode(&v, false)))
+                .collect::<Vec<_>>();
+            query.sort();
+
+            let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+            let canonical_request = format!(
+                "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+                self.method,
+                self.url.path(),
+                query.join("&"),
+                host,
+                payload_hash,
+                amz_date,
+                signed_headers,
+                payload_hash
+            );
+
+            let string_to_sign = format!(
+                "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+                amz_date,
+                scope,
+                hex::encode(Sha256::digest(canonical_request.as_bytes()))
+            );
+
+            let signing_key = ["s3", "aws4_request"].iter().fold(
+                hmac_sha256(
+                    hmac_sha256(
+                        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+                        date,
+                    )
+                    .as_slice(),
+                    &bucket.region,
+                ),
+                |key, part| hmac_sha256(&key, part),
+            );
+
+            format!(
+                "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+                credentials.access_key_id,
+                scope,
+                signed_headers,
+                hex::encode(hmac_sha256(&signing_key, &string_to_sign))
+            )
+        }
+
+        pub async fn send(self, bucket: &Bucket) -> Result<reqwest::Response, Error> {
+            let amz_date = chrono::Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+            let payload_hash = hex::encode(Sha256::digest(&self.body));
+            let host = match self.url.port() {
+                Some(port) => format!("{}:{}", self.url.host_str().unwrap_or_default(), port),
+                None => self.url.host_str().unwrap_or_default().to_string(),
+            };
+            let authorization = self.authorization(bucket, &amz_date, &payload_hash, &host);
+
+            let mut request = reqwest::Client::builder()
+                .user_agent(APP_USER_AGENT)
+                .build()
+                .unwrap()
+                .request(self.method, self.url)
+                .header("host", host)
+                .header("x-amz-date", amz_date)
+                .header("x-amz-content-sha256", payload_hash)
+                .header("authorization", authorization);
+            for (key, value) in self.headers {
+                request = request.header(key, value);
+            }
+
+            request
+                .body(self.body)
+                .send()
+                .await
+                .map_err(Error::HttpFailure)
+        }
+    }
+}
+
+/// A data sink storing the payload as an object in an S3-compatible bucket, e.g. on MinIO.
+///
+/// The endpoint, bucket and key prefix are configured through the settings, while the sink ID is the key of the object.
+/// Objects are only ever written with conditional PUTs, so we never overwrite changes we haven't fetched, see `KnownEtag`.
+pub struct S3DataSink {
+    etag: KnownEtag,
+}
+
+impl S3DataSink {
+    pub async fn new() -> Result<Self, Error> {
+        Ok(Self {
+            etag: KnownEtag::default(),
+        })
+    }
+
+    fn object_key() -> String {
+        let prefix: String = Settings::instance().get("s3-prefix");
+        let prefix = prefix.trim_matches('/');
+        if prefix.is_empty() {
+            FILE_NAME.to_string()
+        } else {
+            format!("{}/{}", prefix, FILE_NAME)
+        }
+    }
+
+    async fn put(
+        bucket: &Bucket,
+        key: &str,
+        payload: &FlatpakInstallationPayload,
+        precondition: (header::HeaderName, String),
+    ) -> Result<reqwest::Response, Error> {
+        let body = serde_json::to_vec_pretty(payload)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+
+        S3Client::new(Method::PUT, bucket, key)?
+            .header("content-type", "application/json")
+            .header(precondition.0.as_str(), &precondition.1)
+            .body(body)
+            .send(bucket)
+            .await
+    }
+
+    async fn fetch_from(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+    ) -> Result<FlatpakInstallationPayload, Error> {
+        let resp = S3Client::new(Method::GET, bucket, key)?
+            .send(bucket)
+            .await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            self.etag.forget();
+            return Err(Error::MissingGistFiles);
+        }
+
+        let resp = resp.error_for_status()?;
+        self.etag.fetched(&resp);
+        Ok(resp.json().await?)
+    }
+
+    async fn update_at(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        payload: &FlatpakInstallationPayload,
+    ) -> Result<(), Error> {
+        let precondition = self.etag.precondition()?;
+        let resp = Self::put(bucket, key, payload, precondition).await?;
+
+        if resp.status() == StatusCode::PRECONDITION_FAILED {
+            // Somebody else updated the object since we last fetched it, or we never did
+            return Err(Error::SinkConflict);
+        }
+
+        let resp = resp.error_for_status()?;
+        self.etag.written(&resp);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DataSink for S3DataSink {
+    /// Uploads the payload, unless the bucket already holds a FlatSync list under the configured prefix.
+    /// In that case we simply join it and leave it to the next sync to reconcile both.
+    async fn create(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        let key = Self::object_key();
+        let bucket = Bucket::load().await?;
+        let resp = Self::put(&bucket, &key, &payload, (header::IF_NONE_MATCH, "*".into())).await?;
+
+        if resp.status() == StatusCode::PRECONDITION_FAILED {
+            debug!("Object {} already exists, joining it", key);
+        } else {
+            let resp = resp.error_for_status()?;
+            self.etag.written(&resp);
+        }
+
+        self.set_sink_id(&key);
+        Ok(())
+    }
+
+    async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error> {
+        self.fetch_from(&Bucket::load().await?, &self.sink_id())
+            .await
+    }
+
+    async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        self.update_at(&Bucket::load().await?, &self.sink_id(), &payload)
+            .await
+    }
+
+    async fn delete(&self) -> Result<(), Error> {
+        let bucket = Bucket::load().await?;
+        S3Client::new(Method::DELETE, &bucket, &self.sink_id())?
+            .send(&bucket)
+            .await?
+            .error_for_status()?;
+
+        self.etag.forget();
+        Ok(())
+    }
+
+    fn sink_name(&self) -> &'static str {
+        S3_SINK_NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client::S3Credentials;
+    use super::*;
+    use crate::data_sinks::stand_in;
+
+    /// Like a fresh MinIO instance, which accepts any signed request to its bucket.
+    async fn bucket() -> Bucket {
+        Bucket {
+            endpoint: stand_in::serve().await,
+            name: "flatsync".into(),
+            region: "us-east-1".into(),
+            credentials: S3Credentials {
+                access_key_id: "minioadmin".into(),
+                secret_access_key: "minioadmin".into(),
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn only_writes_with_conditional_puts() {
+        let bucket = bucket().await;
+        let key = format!("devices/{}", FILE_NAME);
+        let first = S3DataSink::new().await.unwrap();
+        let second = S3DataSink::new().await.unwrap();
+
+        // The first write creates the object, a second attempt to create it fails
+        let resp = S3DataSink::put(
+            &bucket,
+            &key,
+            &stand_in::payload(1),
+            (header::IF_NONE_MATCH, "*".into()),
+        )
+        .await
+        .unwrap();
+        assert!(resp.status().is_success());
+        let resp = S3DataSink::put(
+            &bucket,
+            &key,
+            &stand_in::payload(2),
+            (header::IF_NONE_MATCH, "*".into()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+
+        // An instance that never fetched may not write over the object until it did
+        assert!(matches!(
+            second.update_at(&bucket, &key, &stand_in::payload(2)).await,
+            Err(Error::SinkConflict)
+        ));
+
+        first.fetch_from(&bucket, &key).await.unwrap();
+        second.fetch_from(&bucket, &key).await.unwrap();
+        second
+            .update_at(&bucket, &key, &stand_in::payload(2))
+            .await
+            .unwrap();
+
+        // The first instance's ETag is outdated, so it has to fetch before it may write again
+        assert!(matches!(
+            first.update_at(&bucket, &key, &stand_in::payload(3)).await,
+            Err(Error::SinkConflict)
+        ));
+        let fetched = first.fetch_from(&bucket, &key).await.unwrap();
+        assert_eq!(fetched.altered_at, stand_in::payload(2).altered_at);
+        first
+            .update_at(&bucket, &key, &stand_in::payload(3))
+            .await
+            .unwrap();
+    }
+}
@@ -0,0 +1,99 @@
+//! An in-memory stand-in for WebDAV servers and S3-compatible object storage, so that the sinks can be tested without either.
+//!
+//! It stores whatever is PUT, hands out a new ETag for every write and honors the `If-Match` and `If-None-Match` preconditions
+//! the sinks rely on, like Nextcloud or MinIO do.
+
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{header, HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Router,
+};
+use libflatsync_common::{FlatpakInstallationMap, FlatpakInstallationPayload};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex},
+};
+
+#[derive(Default)]
+struct Files {
+    last_etag: u64,
+    /// The ETag and contents of the files, keyed by their path.
+    files: HashMap<String, (String, Bytes)>,
+}
+
+type SharedFiles = Arc<Mutex<Files>>;
+
+async fn handle(
+    State(files): State<SharedFiles>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let path = uri.path().to_string();
+    let value = |name: header::HeaderName| headers.get(name).and_then(|v| v.to_str().ok());
+    let mut files = files.lock().unwrap();
+    let current = files.files.get(&path).map(|(etag, _)| etag.clone());
+
+    match method.as_str() {
+        "GET" => match files.files.get(&path) {
+            Some((etag, contents)) => {
+                ([(header::ETAG, etag.clone())], contents.clone()).into_response()
+            }
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        "PUT" => {
+            let precondition_met = match (value(header::IF_MATCH), value(header::IF_NONE_MATCH)) {
+                (Some(etag), _) => current.as_deref() == Some(etag),
+                (None, Some("*")) => current.is_none(),
+                _ => true,
+            };
+            if !precondition_met {
+                return StatusCode::PRECONDITION_FAILED.into_response();
+            }
+
+            files.last_etag += 1;
+            let etag = format!("\"{}\"", files.last_etag);
+            files.files.insert(path, (etag.clone(), body));
+            (StatusCode::CREATED, [(header::ETAG, etag)]).into_response()
+        }
+        "DELETE" => match files.files.remove(&path) {
+            Some(_) => StatusCode::NO_CONTENT.into_response(),
+            None => StatusCode::NOT_FOUND.into_response(),
+        },
+        // Directories exist implicitly
+        "MKCOL" => StatusCode::CREATED.into_response(),
+        _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+    }
+}
+
+/// ## `serve()`
+/// Serves an empty stand-in on a free port of the loopback interface for the rest of the test.
+///
+/// # Returns
+/// Its base URL.
+pub async fn serve() -> String {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let app = Router::new()
+        .fallback(handle)
+        .with_state(SharedFiles::default());
+
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    format!("http://{}", address)
+}
+
+/// ## `payload()`
+/// An empty payload, told apart from others by the time it was altered at.
+pub fn payload(altered_at: i64) -> FlatpakInstallationPayload {
+    FlatpakInstallationPayload {
+        installations: FlatpakInstallationMap(BTreeMap::new()),
+        altered_at: chrono::DateTime::from_timestamp(altered_at, 0).unwrap(),
+        devices: BTreeMap::new(),
+        profiles: BTreeMap::new(),
+        filter_rules: None,
+        pins: BTreeMap::new(),
+    }
+}
@@ -0,0 +1,285 @@
+use self::client::{Credentials, WebDavClient};
+use async_trait::async_trait;
+use libflatsync_common::FlatpakInstallationPayload;
+use log::debug;
+use reqwest::{header, Method, StatusCode};
+
+use super::{data_sink::DataSink, etag::KnownEtag, rest_client::RestClient};
+use crate::{data_sinks::data_sink::FILE_NAME, settings::Settings, Error};
+
+static WEBDAV_SINK_NAME: &str = "webdav";
+
+pub mod client {
+    use crate::data_sinks::data_sink_client::DataSinkClient;
+    use crate::data_sinks::rest_client::RestClient;
+    use crate::settings::Settings;
+    use crate::Error;
+    use reqwest::{IntoUrl, Method, RequestBuilder};
+
+    static APP_USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+    /// The account to authenticate with at the WebDAV server.
+    pub struct Credentials {
+        pub username: String,
+        pub password: String,
+    }
+
+    /// Looks the password up in the keyring.
+    struct Keyring;
+
+    impl DataSinkClient for Keyring {
+        fn sink_name(&self) -> &'static str {
+            super::WEBDAV_SINK_NAME
+        }
+    }
+
+    impl Credentials {
+        /// The user name from the settings and the password (or app password) from the keyring.
+        pub async fn load() -> Result<Self, Error> {
+            Ok(Self {
+                username: Settings::instance().get("webdav-username"),
+                password: Keyring.secret_raw().await?,
+            })
+        }
+    }
+
+    /// Client for a WebDAV server, authenticating with Basic auth.
+    pub struct WebDavClient {
+        request: RequestBuilder,
+    }
+
+    impl WebDavClient {
+        pub async fn new<U: IntoUrl>(method: Method, url: U) -> Result<WebDavClient, Error> {
+            Ok(WebDavClient {
+                request: reqwest::Client::builder()
+                    .user_agent(APP_USER_AGENT)
+                    .build()
+                    .unwrap()
+                    .request(method, url),
+            })
+        }
+
+        pub fn header(mut self, key: reqwest::header::HeaderName, value: &str) -> Self {
+            self.request = self.request.header(key, value);
+            self
+        }
+
+        pub async fn send(self, credentials: &Credentials) -> Result<reqwest::Response, Error> {
+            self.request
+                .basic_auth(&credentials.username, Some(&credentials.password))
+                .send()
+                .await
+                .map_err(Error::HttpFailure)
+        }
+    }
+
+    impl RestClient for WebDavClient {
+        fn builder(&mut self) -> RequestBuilder {
+            self.request.try_clone().unwrap()
+        }
+
+        fn set_builder(&mut self, builder: RequestBuilder) {
+            self.request = builder;
+        }
+    }
+}
+
+/// A data sink storing the payload on a WebDAV server, e.g. Nextcloud or ownCloud.
+///
+/// The directory the payload is stored in is configured through the `webdav-url` setting, while the sink ID is the URL of the file itself.
+/// ETags are used to make sure we never overwrite changes we haven't fetched yet, see `KnownEtag`.
+pub struct WebDavDataSink {
+    etag: KnownEtag,
+}
+
+impl WebDavDataSink {
+    pub async fn new() -> Result<Self, Error> {
+        Ok(Self {
+            etag: KnownEtag::default(),
+        })
+    }
+
+    fn collection_url() -> String {
+        let url: String = Settings::instance().get("webdav-url");
+        format!("{}/", url.trim_end_matches('/'))
+    }
+
+    /// Uploads the payload to `url`, only succeeding if the file is in the state described by `precondition`.
+    async fn put(
+        url: &str,
+        credentials: &Credentials,
+        payload: &FlatpakInstallationPayload,
+        precondition: (header::HeaderName, String),
+    ) -> Result<reqwest::Response, Error> {
+        let mut client = WebDavClient::new(Method::PUT, url)
+            .await?
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(precondition.0, &precondition.1);
+        client.body(payload);
+
+        client.send(credentials).await
+    }
+
+    async fn fetch_from(
+        &self,
+        url: &str,
+        credentials: &Credentials,
+    ) -> Result<FlatpakInstallationPayload, Error> {
+        let resp = WebDavClient::new(Method::GET, url)
+            .await?
+            .send(credentials)
+            .await?;
+
+        if resp.status() == StatusCode::NOT_FOUND {
+            self.etag.forget();
+            return Err(Error::MissingGistFiles);
+        }
+
+        let resp = resp.error_for_status()?;
+        self.etag.fetched(&resp);
+        Ok(resp.json().await?)
+    }
+
+    async fn update_at(
+        &self,
+        url: &str,
+        credentials: &Credentials,
+        payload: &FlatpakInstallationPayload,
+    ) -> Result<(), Error> {
+        let precondition = self.etag.precondition()?;
+        let resp = Self::put(url, credentials, payload, precondition).await?;
+
+        if resp.status() == StatusCode::PRECONDITION_FAILED {
+            // Somebody else updated the file since we last fetched it, or we never did
+            return Err(Error::SinkConflict);
+        }
+
+        let resp = resp.error_for_status()?;
+        self.etag.written(&resp);
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl DataSink for WebDavDataSink {
+    /// Uploads the payload, unless the directory already holds a FlatSync list.
+    /// In that case we simply join it and leave it to the next sync to reconcile both.
+    async fn create(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        let collection_url = Self::collection_url();
+        let credentials = Credentials::load().await?;
+
+        // Make sure the directory exists, servers answer with 405 if it already does
+        let resp = WebDavClient::new(Method::from_bytes(b"MKCOL").unwrap(), &collection_url)
+            .await?
+            .send(&credentials)
+            .await?;
+        if !resp.status().is_success() && resp.status() != StatusCode::METHOD_NOT_ALLOWED {
+            resp.error_for_status()?;
+        }
+
+        let file_url = format!("{}{}", collection_url, FILE_NAME);
+        let resp = Self::put(
+            &file_url,
+            &credentials,
+            &payload,
+            (header::IF_NONE_MATCH, "*".into()),
+        )
+        .await?;
+
+        if resp.status() == StatusCode::PRECONDITION_FAILED {
+            debug!("{} already exists, joining it", file_url);
+        } else {
+            let resp = resp.error_for_status()?;
+            self.etag.written(&resp);
+        }
+
+        self.set_sink_id(&file_url);
+        Ok(())
+    }
+
+    async fn fetch(&self) -> Result<FlatpakInstallationPayload, Error> {
+        self.fetch_from(&self.sink_id(), &Credentials::load().await?)
+            .await
+    }
+
+    async fn update(&self, payload: FlatpakInstallationPayload) -> Result<(), Error> {
+        self.update_at(&self.sink_id(), &Credentials::load().await?, &payload)
+            .await
+    }
+
+    async fn delete(&self) -> Result<(), Error> {
+        let resp = WebDavClient::new(Method::DELETE, self.sink_id())
+            .await?
+            .send(&Credentials::load().await?)
+            .await?;
+        if resp.status() != StatusCode::NOT_FOUND {
+            resp.error_for_status()?;
+        }
+
+        self.etag.forget();
+        Ok(())
+    }
+
+    fn sink_name(&self) -> &'static str {
+        WEBDAV_SINK_NAME
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_sinks::stand_in;
+
+    fn credentials() -> Credentials {
+        Credentials {
+            username: "user".into(),
+            password: "app-password".into(),
+        }
+    }
+
+    #[tokio::test]
+    async fn never_overwrites_changes_it_has_not_fetched() {
+        let url = format!("{}/flatsync/{}", stand_in::serve().await, FILE_NAME);
+        let first = WebDavDataSink::new().await.unwrap();
+        let second = WebDavDataSink::new().await.unwrap();
+
+        // Without a file, the first write creates it
+        first
+            .update_at(&url, &credentials(), &stand_in::payload(1))
+            .await
+            .unwrap();
+
+        // An instance that never fetched may not write over the file until it did
+        assert!(matches!(
+            second
+                .update_at(&url, &credentials(), &stand_in::payload(2))
+                .await,
+            Err(Error::SinkConflict)
+        ));
+        let fetched = second.fetch_from(&url, &credentials()).await.unwrap();
+        assert_eq!(fetched.altered_at, stand_in::payload(1).altered_at);
+        second
+            .update_at(&url, &credentials(), &stand_in::payload(2))
+            .await
+            .unwrap();
+
+        // The first one's ETag is outdated now, and stays so until it fetched again
+        for _ in 0..2 {
+            assert!(matches!(
+                first
+                    .update_at(&url, &credentials(), &stand_in::payload(3))
+                    .await,
+                Err(Error::SinkConflict)
+            ));
+        }
+        let fetched = first.fetch_from(&url, &credentials()).await.unwrap();
+        assert_eq!(fetched.altered_at, stand_in::payload(2).altered_at);
+
+        first
+            .update_at(&url, &credentials(), &stand_in::payload(3))
+            .await
+            .unwrap();
+        let fetched = second.fetch_from(&url, &credentials()).await.unwrap();
+        assert_eq!(fetched.altered_at, stand_in::payload(3).altered_at);
+    }
+}
@@ -1,4 +1,9 @@
-use crate::{imp::Impl, DBusError};
+use crate::{imp::Impl, DBusError, Error};
+use libflatsync_common::dbus::{
+    DeviceInfo, JoinStrategy, OldSinkAction, PendingChange, ProfileInfo, SinkStatus, SyncConflict,
+    SyncMode, SyncStep,
+};
+use libflatsync_common::{FilterRules, FlatpakInstallationPayload, VersionPin, VersionPolicy};
 use log::{debug, info};
 use zbus::dbus_interface;
 
@@ -15,6 +20,35 @@ impl Daemon {
         let imp = Impl::new().await?;
         Ok(Self { imp, sender })
     }
+
+    /// ## `edit_payload()`
+    /// Has the main loop apply the given change to the latest payload and push it.
+    async fn edit_payload(
+        &self,
+        edit: impl FnOnce(&mut FlatpakInstallationPayload) + Send + 'static,
+    ) -> Result<(), DBusError> {
+        let (reply, res) = tokio::sync::oneshot::channel();
+        self.sender
+            .send(MessageType::EditPayload(Box::new(edit), reply))
+            .await
+            .map_err(|_| DBusError::SendError)?;
+        res.await
+            .map_err(|_| DBusError::SendError)?
+            .map_err(|e| DBusError::GistUpdateFailure(e.to_string()))
+    }
+
+    /// ## `decide_pending()`
+    /// Approves or rejects the given pending changes, or all of them if `ids` is `None`, and syncs to apply approved ones.
+    async fn decide_pending(&self, ids: Option<&[u32]>, approve: bool) -> Result<u32, DBusError> {
+        let decided = self
+            .imp
+            .decide_pending(ids, approve)
+            .map_err(|e| DBusError::GistApplyFailure(e.to_string()))?;
+        if approve {
+            self.sync_now().await?;
+        }
+        Ok(decided as u32)
+    }
 }
 
 #[dbus_interface(name = "app.drey.FlatSync.Daemon0")]
@@ -36,7 +70,10 @@ impl Daemon {
     async fn create_gist(&mut self) -> Result<String, DBusError> {
         self.imp.create_gist().await.map_err(|e| {
             debug!("Error creating gist: {:?}", e);
-            DBusError::GistCreateFailure(e.to_string())
+            match e {
+                Error::GistAlreadyInitialized(id) => DBusError::GistAlreadyInitialized(id),
+                e => DBusError::GistCreateFailure(e.to_string()),
+            }
         })
     }
 
@@ -51,11 +88,470 @@ impl Daemon {
         Ok(())
     }
 
+    /// ## `SetGistId(...)`
+    /// Start syncing with the given gist, or the location of the current data sink provider's list, merging it with the local installations
     async fn set_gist_id(&self, id: &str) -> Result<(), DBusError> {
+        self.join_gist(id, JoinStrategy::Merge.name()).await
+    }
+
+    /// ## `JoinGist(...)`
+    /// Start syncing with the given gist, or the location of the current data sink provider's list.
+    /// `strategy` is one of `adopt-remote`, `merge` or `overwrite-remote` and decides how an existing list and the local installations are reconciled
+    async fn join_gist(&self, id: &str, strategy: &str) -> Result<(), DBusError> {
+        let strategy = JoinStrategy::from_name(strategy).ok_or_else(|| {
+            DBusError::InvalidJoinStrategy(format!("Unknown join strategy: {}", strategy))
+        })?;
+
         self.imp.set_gist_id(id);
+        info!("Joining FlatSync list");
+        self.sender
+            .send(MessageType::Join(strategy))
+            .await
+            .map_err(|_| DBusError::SendError)
+    }
+
+    /// ## `SinkProvider()`
+    /// Get the name of the data sink provider currently in use
+    async fn sink_provider(&self) -> Result<String, DBusError> {
+        Ok(self.imp.sink_provider())
+    }
+
+    /// ## `SetSinkProvider(...)`
+    /// Switch the data sink to the provider with the given name, e.g. `github`
+    async fn set_sink_provider(&mut self, provider: &str) -> Result<(), DBusError> {
+        self.imp.set_sink_provider(provider).await.map_err(|e| {
+            debug!("Error switching sink provider: {:?}", e);
+            DBusError::InvalidSinkProvider(e.to_string())
+        })
+    }
+
+    /// ## `MigrateSink(...)`
+    /// Move the synced list to the data sink of the given provider and make it the active one.
+    /// `old_sink` is one of `keep`, `archive` or `delete` and decides what happens to the previously active sink.
+    /// A different list stored on the target is only replaced if `force` is set
+    async fn migrate_sink(
+        &mut self,
+        provider: &str,
+        old_sink: &str,
+        force: bool,
+    ) -> Result<String, DBusError> {
+        let old_sink = OldSinkAction::from_name(old_sink).ok_or_else(|| {
+            DBusError::MigrationFailure(format!("Unknown action for the old sink: {}", old_sink))
+        })?;
+
+        self.imp
+            .migrate_sink(provider, old_sink, force)
+            .await
+            .map_err(|e| {
+                debug!("Error migrating sink: {:?}", e);
+                DBusError::MigrationFailure(e.to_string())
+            })
+    }
+
+    /// ## `MirrorSinkProviders()`
+    /// Get the names of the data sink providers every change is replicated to besides the primary one
+    async fn mirror_sink_providers(&self) -> Result<Vec<String>, DBusError> {
+        Ok(self.imp.mirror_sink_providers())
+    }
+
+    /// ## `AddMirrorSink(...)`
+    /// Start replicating to the data sink of the given provider as well, e.g. `local-directory`
+    async fn add_mirror_sink(&mut self, provider: &str) -> Result<(), DBusError> {
+        self.imp.add_mirror_sink(provider).await.map_err(|e| {
+            debug!("Error adding mirror sink: {:?}", e);
+            DBusError::InvalidSinkProvider(e.to_string())
+        })
+    }
+
+    /// ## `SetMirrorSecret(...)`
+    /// Store the secret of the data sink of the given provider without making it the active one, e.g. to set it up as a mirror
+    async fn set_mirror_secret(&self, provider: &str, secret: &str) -> Result<(), DBusError> {
+        if secret.is_empty() {
+            return Err(DBusError::InvalidSecret);
+        }
+        self.imp
+            .set_mirror_secret(provider, secret)
+            .await
+            .map_err(|e| {
+                debug!("Error storing mirror secret: {:?}", e);
+                DBusError::InvalidSecret
+            })
+    }
+
+    /// ## `SetMirrorId(...)`
+    /// Point the data sink of the given provider to the given gist or location without making it the active one.
+    /// Its contents are replaced with the synced list on the next push
+    async fn set_mirror_id(&self, provider: &str, id: &str) -> Result<(), DBusError> {
+        self.imp.set_mirror_id(provider, id).await.map_err(|e| {
+            debug!("Error setting mirror sink ID: {:?}", e);
+            DBusError::InvalidSinkProvider(e.to_string())
+        })
+    }
+
+    /// ## `RemoveMirrorSink(...)`
+    /// Stop replicating to the data sink of the given provider
+    async fn remove_mirror_sink(&mut self, provider: &str) -> Result<(), DBusError> {
+        self.imp.remove_mirror_sink(provider).await.map_err(|e| {
+            debug!("Error removing mirror sink: {:?}", e);
+            DBusError::InvalidSinkProvider(e.to_string())
+        })
+    }
+
+    /// ## `SinkHealth()`
+    /// Get the health of the primary data sink and its mirrors, based on the outcome of their latest operations
+    async fn sink_health(&self) -> Result<Vec<SinkStatus>, DBusError> {
+        Ok(self.imp.sink_health())
+    }
+
+    /// ## `StartLanPairing()`
+    /// Let another device on the network pair with this one for a few minutes and get the code to enter there
+    async fn start_lan_pairing(&self) -> Result<String, DBusError> {
+        self.imp
+            .start_lan_pairing()
+            .map_err(|e| DBusError::PairingFailure(e.to_string()))
+    }
+
+    /// ## `PairLanDevice(...)`
+    /// Pair with the device on the network showing the given code and get its name
+    async fn pair_lan_device(&self, code: &str) -> Result<String, DBusError> {
+        self.imp.pair_lan_device(code).await.map_err(|e| {
+            debug!("Error pairing: {:?}", e);
+            DBusError::PairingFailure(e.to_string())
+        })
+    }
+
+    /// ## `LanPeers()`
+    /// Get the names of the devices this one is paired with
+    async fn lan_peers(&self) -> Result<Vec<String>, DBusError> {
+        self.imp
+            .lan_peers()
+            .await
+            .map_err(|e| DBusError::PairingFailure(e.to_string()))
+    }
+
+    /// ## `UnpairLanDevice(...)`
+    /// Forget the paired device with the given name
+    async fn unpair_lan_device(&self, name: &str) -> Result<(), DBusError> {
+        self.imp
+            .unpair_lan_device(name)
+            .await
+            .map_err(|e| DBusError::PairingFailure(e.to_string()))
+    }
+
+    /// ## `GetSyncPlan()`
+    /// Get the remotes the next sync would add and the refs it would install or uninstall, without changing anything.
+    /// Like the sync, this leaves out deferred updates and the changes waiting for approval, and fails if the safety brake would stop it
+    async fn get_sync_plan(&self) -> Result<Vec<SyncStep>, DBusError> {
+        self.imp
+            .sync_plan()
+            .await
+            .map(|plan| plan.steps())
+            .map_err(|e| {
+                debug!("Error computing sync plan: {:?}", e);
+                DBusError::GistSyncFailure(e.to_string())
+            })
+    }
+
+    /// ## `SyncConflicts()`
+    /// Get the changes from other devices that contradicted local ones during the latest sync, for which the local version was kept
+    async fn sync_conflicts(&self) -> Result<Vec<SyncConflict>, DBusError> {
+        Ok(self.imp.sync_conflicts())
+    }
+
+    /// ## `Devices()`
+    /// Get the devices syncing through the same payload and the refs installed on each of them
+    async fn devices(&self) -> Result<Vec<DeviceInfo>, DBusError> {
+        self.imp
+            .devices()
+            .await
+            .map_err(|e| DBusError::GistSyncFailure(e.to_string()))
+    }
+
+    /// ## `DeviceName()`
+    /// Get the name other devices see this one under
+    async fn device_name(&self) -> Result<String, DBusError> {
+        Ok(self.imp.device_name())
+    }
+
+    /// ## `SetDeviceName(...)`
+    /// Rename this device, an empty name resets it to the hostname. Other devices see the new name after the next sync
+    async fn set_device_name(&self, name: &str) -> Result<(), DBusError> {
+        self.imp.set_device_name(name);
         self.sync_now().await
     }
 
+    /// ## `Profiles()`
+    /// Get the profiles stored in the payload and whether this device subscribed to them
+    async fn profiles(&self) -> Result<Vec<ProfileInfo>, DBusError> {
+        self.imp
+            .profiles()
+            .await
+            .map_err(|e| DBusError::GistSyncFailure(e.to_string()))
+    }
+
+    /// ## `AddToProfile(...)`
+    /// Add a full ref or an app ID to the given profile, creating the profile if it doesn't exist yet
+    async fn add_to_profile(&self, profile: &str, ref_: &str) -> Result<(), DBusError> {
+        let (profile, ref_) = (profile.to_string(), ref_.to_string());
+        self.edit_payload(move |payload| {
+            payload
+                .profiles
+                .entry(profile)
+                .or_default()
+                .refs
+                .insert(ref_);
+        })
+        .await
+    }
+
+    /// ## `RemoveFromProfile(...)`
+    /// Remove a full ref or an app ID from the given profile
+    async fn remove_from_profile(&self, profile: &str, ref_: &str) -> Result<(), DBusError> {
+        let (profile, ref_) = (profile.to_string(), ref_.to_string());
+        self.edit_payload(move |payload| {
+            if let Some(profile) = payload.profiles.get_mut(&profile) {
+                profile.refs.remove(&ref_);
+            }
+        })
+        .await
+    }
+
+    /// ## `DeleteProfile(...)`
+    /// Delete the given profile. Devices subscribed to it keep their refs, but no longer sync them
+    async fn delete_profile(&self, profile: &str) -> Result<(), DBusError> {
+        let profile = profile.to_string();
+        self.edit_payload(move |payload| {
+            payload.profiles.remove(&profile);
+        })
+        .await
+    }
+
+    /// ## `SubscribedProfiles()`
+    /// Get the names of the profiles whose refs this device syncs, all refs are synced if there are none
+    async fn subscribed_profiles(&self) -> Result<Vec<String>, DBusError> {
+        Ok(self.imp.subscribed_profiles())
+    }
+
+    /// ## `SetSubscribedProfiles(...)`
+    /// Choose the profiles whose refs this device syncs, an empty list syncs all refs
+    async fn set_subscribed_profiles(&self, profiles: Vec<String>) -> Result<(), DBusError> {
+        self.imp.set_subscribed_profiles(&profiles);
+        self.sync_now().await
+    }
+
+    /// ## `FilterRules()`
+    /// Get the rules selecting the refs to sync, e.g. `exclude id=org.freedesktop.Platform.GL.* kind=runtime`
+    async fn filter_rules(&self) -> Result<Vec<String>, DBusError> {
+        Ok(self.imp.filter_rules())
+    }
+
+    /// ## `SetFilterRules(...)`
+    /// Replace the rules selecting the refs to sync. If there are include rules, only refs matching one of them are synced, refs matching an exclude rule never are
+    async fn set_filter_rules(&self, rules: Vec<String>) -> Result<(), DBusError> {
+        let rules = FilterRules::parse(&rules)
+            .map_err(|e| DBusError::InvalidFilterRules(e.to_string()))?
+            .to_strings();
+
+        if self.imp.sync_filter_rules() {
+            let shared = rules.clone();
+            self.edit_payload(move |payload| payload.filter_rules = Some(shared))
+                .await?;
+        }
+
+        self.imp
+            .set_filter_rules(&rules)
+            .map_err(|e| DBusError::InvalidFilterRules(e.to_string()))?;
+        self.sync_now().await
+    }
+
+    /// ## `SyncFilterRules()`
+    /// Get whether the filter rules are shared with the other devices syncing them
+    async fn sync_filter_rules(&self) -> Result<bool, DBusError> {
+        Ok(self.imp.sync_filter_rules())
+    }
+
+    /// ## `SetSyncFilterRules(...)`
+    /// Choose whether to share the filter rules with the other devices syncing them, or keep them to this device
+    async fn set_sync_filter_rules(&self, sync: bool) -> Result<(), DBusError> {
+        self.imp.set_sync_filter_rules(sync);
+        self.sync_now().await
+    }
+
+    /// ## `VersionPolicy()`
+    /// Get which version of a ref is installed when syncing it, one of `latest`, `exact-commit` or `not-older-than-remote`
+    async fn version_policy(&self) -> Result<String, DBusError> {
+        Ok(self.imp.version_policy().name().to_string())
+    }
+
+    /// ## `SetVersionPolicy(...)`
+    /// Choose which version of a ref is installed when syncing it, for all refs which aren't pinned
+    async fn set_version_policy(&self, policy: &str) -> Result<(), DBusError> {
+        let policy = VersionPolicy::from_name(policy).ok_or_else(|| {
+            DBusError::InvalidVersionPolicy(format!("Unknown version policy: {}", policy))
+        })?;
+        self.imp.set_version_policy(policy);
+        Ok(())
+    }
+
+    /// ## `VersionPins()`
+    /// Get the refs with their own version policy, as the full ref, the policy and the pinned commit (empty if there is none)
+    async fn version_pins(&self) -> Result<Vec<(String, String, String)>, DBusError> {
+        self.imp
+            .version_pins()
+            .await
+            .map_err(|e| DBusError::GistSyncFailure(e.to_string()))
+    }
+
+    /// ## `PinVersion(...)`
+    /// Give a full ref its own version policy on all devices. `commit` is the one to install with `exact-commit`,
+    /// if empty the one installed on the device the ref is synced from is used
+    async fn pin_version(&self, ref_: &str, policy: &str, commit: &str) -> Result<(), DBusError> {
+        let policy = VersionPolicy::from_name(policy).ok_or_else(|| {
+            DBusError::InvalidVersionPolicy(format!("Unknown version policy: {}", policy))
+        })?;
+        let pin = VersionPin {
+            policy,
+            commit: Some(commit.to_string()).filter(|c| !c.is_empty()),
+        };
+
+        let ref_ = ref_.to_string();
+        self.edit_payload(move |payload| {
+            payload.pins.insert(ref_, pin);
+        })
+        .await
+    }
+
+    /// ## `UnpinVersion(...)`
+    /// Let a full ref follow each device's version policy again
+    async fn unpin_version(&self, ref_: &str) -> Result<(), DBusError> {
+        let ref_ = ref_.to_string();
+        self.edit_payload(move |payload| {
+            payload.pins.remove(&ref_);
+        })
+        .await
+    }
+
+    /// ## `PropagateUpdates()`
+    /// Get whether installed refs are updated to the commit installed on the other devices, or the one they are pinned to
+    async fn propagate_updates(&self) -> Result<bool, DBusError> {
+        Ok(self.imp.propagate_updates())
+    }
+
+    /// ## `SetPropagateUpdates(...)`
+    /// Choose whether installed refs are updated to the commit installed on the other devices, so that all of them stay on the same versions.
+    /// Updates wait while the network is metered or power saver is on
+    async fn set_propagate_updates(&self, propagate: bool) -> Result<(), DBusError> {
+        self.imp.set_propagate_updates(propagate);
+        self.sync_now().await
+    }
+
+    /// ## `RemoteAliases()`
+    /// Get the rules mapping the names of remotes on other devices to local ones, e.g. `flathub-1=flathub`
+    async fn remote_aliases(&self) -> Result<Vec<String>, DBusError> {
+        Ok(self.imp.remote_aliases())
+    }
+
+    /// ## `SetRemoteAliases(...)`
+    /// Replace the rules mapping the names of remotes on other devices to local ones. Remotes pointing to the same URL or collection ID are matched without a rule
+    async fn set_remote_aliases(&self, aliases: Vec<String>) -> Result<(), DBusError> {
+        self.imp
+            .set_remote_aliases(&aliases)
+            .map_err(|e| DBusError::InvalidRemoteAliases(e.to_string()))?;
+        self.sync_now().await
+    }
+
+    /// ## `SyncMode()`
+    /// Get whether changes from other devices are applied right away (`automatic`), or wait for approval (`approve-removals`, `approve-all`)
+    async fn sync_mode(&self) -> Result<String, DBusError> {
+        Ok(self.imp.sync_mode().name().to_string())
+    }
+
+    /// ## `SetSyncMode(...)`
+    /// Choose whether changes from other devices are applied right away, one of `automatic`, `approve-removals` or `approve-all`
+    async fn set_sync_mode(&self, mode: &str) -> Result<(), DBusError> {
+        let mode = SyncMode::from_name(mode)
+            .ok_or_else(|| DBusError::InvalidSyncMode(format!("Unknown sync mode: {}", mode)))?;
+        self.imp.set_sync_mode(mode);
+        self.sync_now().await
+    }
+
+    /// ## `PendingChanges()`
+    /// Get the changes from other devices that wait for approval
+    async fn pending_changes(&self) -> Result<Vec<PendingChange>, DBusError> {
+        Ok(self.imp.pending_changes())
+    }
+
+    /// ## `ApprovePending(...)`
+    /// Approve the pending changes with the given IDs and apply them
+    async fn approve_pending(&self, ids: Vec<u32>) -> Result<u32, DBusError> {
+        if ids.is_empty() {
+            return Err(DBusError::NoPendingChangesGiven(
+                "No changes to approve given, use ApproveAllPending to approve all of them".into(),
+            ));
+        }
+        self.decide_pending(Some(&ids), true).await
+    }
+
+    /// ## `ApproveAllPending()`
+    /// Approve all pending changes and apply them
+    async fn approve_all_pending(&self) -> Result<u32, DBusError> {
+        self.decide_pending(None, true).await
+    }
+
+    /// ## `RejectPending(...)`
+    /// Reject the pending changes with the given IDs. They aren't offered again unless another device makes them anew
+    async fn reject_pending(&self, ids: Vec<u32>) -> Result<u32, DBusError> {
+        if ids.is_empty() {
+            return Err(DBusError::NoPendingChangesGiven(
+                "No changes to reject given, use RejectAllPending to reject all of them".into(),
+            ));
+        }
+        self.decide_pending(Some(&ids), false).await
+    }
+
+    /// ## `RejectAllPending()`
+    /// Reject all pending changes. They aren't offered again unless another device makes them anew
+    async fn reject_all_pending(&self) -> Result<u32, DBusError> {
+        self.decide_pending(None, false).await
+    }
+
+    /// ## `UninstallLimits()`
+    /// Get the number and the percentage of installed refs a sync may uninstall before it is stopped until confirmed, 0 meaning no limit
+    async fn uninstall_limits(&self) -> Result<(u32, u32), DBusError> {
+        Ok(self.imp.uninstall_limits())
+    }
+
+    /// ## `SetUninstallLimits(...)`
+    /// Set the number and the percentage of installed refs a sync may uninstall before it is stopped until confirmed, 0 meaning no limit
+    async fn set_uninstall_limits(
+        &self,
+        max_count: u32,
+        max_percentage: u32,
+    ) -> Result<(), DBusError> {
+        self.imp
+            .set_uninstall_limits(max_count, max_percentage)
+            .map_err(|e| DBusError::InvalidUninstallLimits(e.to_string()))
+    }
+
+    /// ## `HeldUninstalls()`
+    /// Get the uninstallations of the latest sync that was stopped for exceeding the limits, empty if there is none
+    async fn held_uninstalls(&self) -> Result<Vec<SyncStep>, DBusError> {
+        Ok(self.imp.held_uninstalls())
+    }
+
+    /// ## `ConfirmUninstalls()`
+    /// Let the stopped sync proceed with the held uninstallations and start it. Returns `false` if no sync was stopped
+    async fn confirm_uninstalls(&self) -> Result<bool, DBusError> {
+        let confirmed = self
+            .imp
+            .confirm_uninstalls()
+            .map_err(|e| DBusError::GistApplyFailure(e.to_string()))?;
+        if confirmed {
+            self.sync_now().await?;
+        }
+        Ok(confirmed)
+    }
+
     async fn sync_now(&self) -> Result<(), DBusError> {
         info!("Starting Manual Sync");
         self.sender
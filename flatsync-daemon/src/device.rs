@@ -0,0 +1,76 @@
+use crate::{filters, settings::Settings};
+use libflatsync_common::{config::APP_ID, Device, FlatpakInstallationPayload};
+use log::warn;
+use sha2::{Digest, Sha256};
+
+/// ## `device_id()`
+/// A stable ID for this device.
+///
+/// It is derived from the machine ID instead of being the machine ID itself, as the latter shouldn't be shared with others.
+pub fn device_id() -> String {
+    let machine_id = ["/etc/machine-id", "/var/lib/dbus/machine-id"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .map(|id| id.trim().to_string())
+        .find(|id| !id.is_empty())
+        .unwrap_or_else(|| {
+            warn!("Couldn't read the machine ID, identifying this device by its hostname instead");
+            glib::host_name().to_string()
+        });
+
+    let digest = Sha256::new()
+        .chain_update(APP_ID)
+        .chain_update(machine_id)
+        .finalize();
+    hex::encode(&digest[..16])
+}
+
+/// ## `device_name()`
+/// The name the user gave this device in the `device-name` setting, falling back to the hostname.
+pub fn device_name() -> String {
+    let name: String = Settings::instance().get("device-name");
+    if name.is_empty() {
+        glib::host_name().to_string()
+    } else {
+        name
+    }
+}
+
+pub fn set_device_name(name: &str) {
+    Settings::instance().set("device-name", name).unwrap();
+}
+
+/// ## `native_arch()`
+/// The architecture of this device as Flatpak calls it, e.g. `x86_64`.
+pub fn native_arch() -> String {
+    match std::env::consts::ARCH {
+        "x86" => "i386".into(),
+        arch => arch.into(),
+    }
+}
+
+/// ## `this_device()`
+/// The payload entry of this device, listing the refs installed in the given local installations which the filter rules allow.
+pub fn this_device(local: &FlatpakInstallationPayload) -> Device {
+    Device::new(
+        device_id(),
+        device_name(),
+        glib::host_name().to_string(),
+        native_arch(),
+        &filters::filtered(local).installations,
+    )
+}
+
+/// ## `needs_announcing()`
+/// Whether the payload's entry of this device is missing or outdated, so that we need to push even without other changes.
+///
+/// Entries are refreshed at least once a day, so that the last-seen time stays meaningful.
+pub fn needs_announcing(payload: &FlatpakInstallationPayload, device: &Device) -> bool {
+    match payload.devices.get(&device.id) {
+        Some(entry) => {
+            !entry.same_state(device)
+                || device.last_seen - entry.last_seen > chrono::Duration::days(1)
+        }
+        None => true,
+    }
+}
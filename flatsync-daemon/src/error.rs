@@ -8,10 +8,16 @@ pub enum Error {
     FlatpakInstallationFileFailure(String),
     #[error("Error while installating Flatpak reference '{0}': {1}")]
     FlatpakInstallationFailed(String, String),
+    #[error("Error while updating Flatpak reference '{0}': {1}")]
+    FlatpakUpdateFailed(String, String),
     #[error("Error while uninstalling Flatpak reference '{0}': {1}")]
     FlatpakUninstallationFailed(String, String),
     #[error("Error while adding Flatpak remote '{0}': {1}")]
     FlatpakRemoteAddFailed(String, String),
+    #[error("Error while modifying Flatpak remote '{0}': {1}")]
+    FlatpakRemoteModifyFailed(String, String),
+    #[error("Error while removing Flatpak remote '{0}': {1}")]
+    FlatpakRemoteRemoveFailed(String, String),
     #[error("Error while refreshing Flatpak remote '{0}': {1}")]
     FlatpakRemoteRefreshFailed(String, String),
     #[error("Flatpak is already installed")]
@@ -34,6 +40,38 @@ pub enum Error {
     MissingGistFiles,
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+    #[error("Error while setting up the data sink provider: {0}")]
+    ProviderSetupFailure(libflatsync_common::Error),
+    #[error("Failed to refresh OAuth tokens: {0}")]
+    OAuthTokenRefreshFailure(String),
+    #[error("Invalid data sink configuration: {0}")]
+    InvalidSinkConfiguration(String),
+    #[error("The data sink was changed by another device in the meantime")]
+    SinkConflict,
+    #[error("Running 'git {0}' failed: {1}")]
+    GitFailure(String, String),
+    #[error("LAN sync failed: {0}")]
+    LanFailure(String),
+    #[error("The other device couldn't prove it is paired with us")]
+    LanAuthenticationFailure,
+    #[error("Couldn't set filter rules: {0}")]
+    InvalidFilterRules(libflatsync_common::Error),
+    #[error("Invalid remote alias {0}")]
+    InvalidRemoteAlias(String),
+    #[error("Unknown data sink provider: {0}")]
+    UnknownSinkProvider(String),
+    #[error("The payload stored on data sink '{0}' doesn't match the one we migrated")]
+    MigrationVerificationFailed(String),
+    #[error(
+        "Data sink '{0}' already holds another FlatSync list, migrate with `--force` to replace it"
+    )]
+    MigrationTargetOccupied(String),
+    #[error("Switched data sinks, but failed to clean up the old one: {0}")]
+    OldSinkCleanupFailure(String),
+    #[error("Stopped syncing, as it would uninstall {0} of {1} installed refs. Review and confirm the uninstallations with `flatsync-cli brake`")]
+    UninstallLimitExceeded(usize, usize),
+    #[error("Invalid uninstallation limits: {0}")]
+    InvalidUninstallLimits(String),
     #[error("ASHPD error: {0}")]
     AshpdFailure(#[from] ashpd::Error),
 }
@@ -42,6 +80,8 @@ pub enum Error {
 pub enum DBusError {
     #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.GistCreateFailure")]
     GistCreateFailure(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.GistAlreadyInitialized")]
+    GistAlreadyInitialized(String),
     #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.GistUpdateFailure")]
     GistUpdateFailure(String),
     #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.GistSyncFailure")]
@@ -50,6 +90,26 @@ pub enum DBusError {
     GistApplyFailure(String),
     #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.InvalidSecret")]
     InvalidSecret,
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.InvalidSinkProvider")]
+    InvalidSinkProvider(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.MigrationFailure")]
+    MigrationFailure(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.PairingFailure")]
+    PairingFailure(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.InvalidFilterRules")]
+    InvalidFilterRules(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.InvalidRemoteAliases")]
+    InvalidRemoteAliases(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.InvalidVersionPolicy")]
+    InvalidVersionPolicy(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.InvalidSyncMode")]
+    InvalidSyncMode(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.InvalidUninstallLimits")]
+    InvalidUninstallLimits(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.InvalidJoinStrategy")]
+    InvalidJoinStrategy(String),
+    #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.NoPendingChangesGiven")]
+    NoPendingChangesGiven(String),
     #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.AutoStartFailure")]
     AutoStartFailure,
     #[dbus_error(name = "app.drey.FlatSync.Daemon.Error.SendError")]
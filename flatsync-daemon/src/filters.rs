@@ -0,0 +1,126 @@
+use crate::{settings::Settings, Error};
+use libflatsync_common::{FilterAction, FilterRule, FilterRules, FlatpakInstallationPayload};
+use log::warn;
+
+/// ## `rules()`
+/// The filter rules from the `filter-rules` setting. Rules that fail to parse are skipped with a warning.
+pub fn rules() -> FilterRules {
+    rules_from("filter-rules")
+}
+
+/// ## `active_rules()`
+/// The filter rules extended by the device-only rules of `keep_to_device()`, which together decide what is synced.
+pub fn active_rules() -> FilterRules {
+    let mut rules = rules();
+    rules.0.extend(rules_from("device-only-rules").0);
+    rules
+}
+
+fn rules_from(key: &str) -> FilterRules {
+    let rules: Vec<String> = Settings::instance().get(key);
+
+    FilterRules(
+        rules
+            .iter()
+            .filter_map(|rule| match rule.parse::<FilterRule>() {
+                Ok(rule) => Some(rule),
+                Err(e) => {
+                    warn!("Ignoring filter rule: {}", e);
+                    None
+                }
+            })
+            .collect(),
+    )
+}
+
+/// ## `set_rules()`
+/// Validates the given rules and persists them in the `filter-rules` setting.
+pub fn set_rules(rules: &[String]) -> Result<(), Error> {
+    let rules = FilterRules::parse(rules).map_err(Error::InvalidFilterRules)?;
+    Settings::instance()
+        .set("filter-rules", rules.to_strings())
+        .unwrap();
+    Ok(())
+}
+
+pub fn sync_rules() -> bool {
+    Settings::instance().get("sync-filter-rules")
+}
+
+pub fn set_sync_rules(sync: bool) {
+    Settings::instance().set("sync-filter-rules", sync).unwrap();
+}
+
+/// ## `adopt_shared_rules()`
+/// If this device syncs its filter rules, takes over the ones shared through the given payload.
+///
+/// Returns `true` if the payload doesn't contain any rules yet, so that ours should be published.
+pub fn adopt_shared_rules(payload: &FlatpakInstallationPayload) -> Result<bool, Error> {
+    if !sync_rules() {
+        return Ok(false);
+    }
+
+    match &payload.filter_rules {
+        Some(shared) => {
+            if *shared != rules().to_strings() {
+                set_rules(shared)?;
+            }
+            Ok(false)
+        }
+        None => Ok(true),
+    }
+}
+
+/// ## `keep_to_device()`
+/// Stores device-only rules excluding the refs installed locally which the payload doesn't contain from syncing,
+/// so that they stay on this device without spreading to the others. Unlike the filter rules, these are never shared.
+///
+/// Returns the number of refs kept to this device.
+pub fn keep_to_device(
+    local: &FlatpakInstallationPayload,
+    payload: &FlatpakInstallationPayload,
+) -> usize {
+    let mut rules = FilterRules::default();
+
+    for (kind, installation) in &filtered(local).installations.0 {
+        let shared = payload.installations(*kind);
+        for ref_ in &installation.refs {
+            // The payload might list the ref for the architecture of another device
+            let key = ref_.sync_key();
+            if shared.is_some_and(|i| i.refs.iter().any(|r| r.sync_key() == key)) {
+                continue;
+            }
+
+            rules.0.push(FilterRule {
+                action: FilterAction::Exclude,
+                id: Some(ref_.id.clone()),
+                kind: Some(ref_.kind),
+                origin: None,
+                branch: Some(ref_.branch.clone()),
+                arch: Some(ref_.arch.clone()),
+                installation: Some(*kind),
+            });
+        }
+    }
+
+    Settings::instance()
+        .set("device-only-rules", rules.to_strings())
+        .unwrap();
+    rules.0.len()
+}
+
+/// ## `reset_device_only_rules()`
+/// Syncs the refs `keep_to_device()` kept to this device again.
+pub fn reset_device_only_rules() {
+    Settings::instance()
+        .set("device-only-rules", Vec::<String>::new())
+        .unwrap();
+}
+
+/// ## `filtered()`
+/// A copy of the payload without the refs the filter rules or the device-only rules don't allow.
+pub fn filtered(payload: &FlatpakInstallationPayload) -> FlatpakInstallationPayload {
+    let mut payload = payload.clone();
+    active_rules().apply(&mut payload);
+    payload
+}
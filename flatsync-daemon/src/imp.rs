@@ -1,81 +1,638 @@
 use crate::{
-    data_sinks::{data_sink::DataSink, GitHubGistDataSink},
-    Error,
+    context::Context,
+    data_sinks::{health, lan, registry, BoxedDataSink},
+    device, filters, merge, pending, remotes, safety_brake,
+    settings::Settings,
+    sync_plan::SyncPlan,
+    versions, Error, MessageType,
 };
 use ashpd::desktop::background::Background;
-use libflatsync_common::{config, FlatpakInstallationPayload};
-use log::{info, trace};
-use std::path::Path;
+use libflatsync_common::{
+    config,
+    dbus::{
+        DeviceInfo, OldSinkAction, PendingChange, ProfileInfo, SinkStatus, SyncConflict, SyncMode,
+        SyncStep,
+    },
+    providers::providers_list::Providers,
+    FlatpakInstallationPayload, VersionPolicy,
+};
+use log::{info, trace, warn};
+use std::path::{Path, PathBuf};
 use tokio::fs;
 
+/// A data sink together with the name of the provider backing it.
+struct ProviderSink {
+    provider: String,
+    sink: BoxedDataSink,
+}
+
 pub struct Impl {
-    sink: Box<dyn DataSink + 'static + Send + Sync>,
+    /// The primary data sink, followed by the mirrors every change is replicated to.
+    sinks: Vec<ProviderSink>,
 }
 
 impl Impl {
     pub async fn new() -> Result<Self, Error> {
         Ok(Self {
-            sink: Box::new(GitHubGistDataSink::new().await?),
+            sinks: Self::load_sinks().await?,
         })
     }
 
+    /// Constructs the primary data sink from the `sink-provider` setting and the mirrors from `mirror-sink-providers`.
+    async fn load_sinks() -> Result<Vec<ProviderSink>, Error> {
+        let primary = registry::configured_provider();
+        let mut sinks = vec![ProviderSink {
+            sink: registry::new_sink_by_name(&primary).await?,
+            provider: primary,
+        }];
+
+        for provider in registry::mirror_providers() {
+            if sinks.iter().any(|s| s.provider == provider) {
+                continue;
+            }
+
+            match registry::new_sink_by_name(&provider).await {
+                Ok(sink) => sinks.push(ProviderSink { provider, sink }),
+                Err(e) => warn!("Skipping mirror data sink {}: {}", provider, e),
+            }
+        }
+
+        Ok(sinks)
+    }
+
+    fn primary(&self) -> &BoxedDataSink {
+        &self.sinks[0].sink
+    }
+
+    pub fn sink_provider(&self) -> String {
+        registry::configured_provider()
+    }
+
+    /// ## `sink_location()`
+    /// Identifies the list the primary data sink syncs with, by the sink's provider and ID.
+    pub fn sink_location(&self) -> (String, String) {
+        (self.sinks[0].provider.clone(), self.primary().sink_id())
+    }
+
+    /// ## `set_sink_provider()`
+    /// Switches to the data sink of the given provider and persists the choice in the settings.
+    pub async fn set_sink_provider(&mut self, provider: &str) -> Result<(), Error> {
+        Providers::from_name(provider)
+            .ok_or_else(|| Error::UnknownSinkProvider(provider.into()))?;
+        registry::set_configured_provider(provider);
+        self.reload_sink().await?;
+        info!("Switched data sink provider to {}", provider);
+        Ok(())
+    }
+
+    /// ## `reload_sink()`
+    /// Reconstructs the data sinks from the settings, e.g. after they were changed by another instance.
+    pub async fn reload_sink(&mut self) -> Result<(), Error> {
+        self.sinks = Self::load_sinks().await?;
+        Ok(())
+    }
+
+    pub fn mirror_sink_providers(&self) -> Vec<String> {
+        registry::mirror_providers()
+    }
+
+    /// ## `add_mirror_sink()`
+    /// Starts replicating to the data sink of the given provider as well.
+    /// The sink is created on the next push if it doesn't hold a FlatSync list yet.
+    pub async fn add_mirror_sink(&mut self, provider: &str) -> Result<(), Error> {
+        Providers::from_name(provider)
+            .ok_or_else(|| Error::UnknownSinkProvider(provider.into()))?;
+
+        let mut mirrors = registry::mirror_providers();
+        if provider != registry::configured_provider() && !mirrors.iter().any(|m| m == provider) {
+            mirrors.push(provider.to_string());
+            registry::set_mirror_providers(&mirrors);
+            self.reload_sink().await?;
+            info!("Added mirror data sink {}", provider);
+        }
+
+        Ok(())
+    }
+
+    /// ## `set_mirror_secret()`
+    /// Stores the secret of the given provider's data sink, leaving the primary one untouched.
+    pub async fn set_mirror_secret(&self, provider: &str, secret: &str) -> Result<(), Error> {
+        self.other_sink(provider).await?.set_secret(secret).await
+    }
+
+    /// ## `set_mirror_id()`
+    /// Points the given provider's data sink to another gist or location, leaving the primary one untouched.
+    pub async fn set_mirror_id(&self, provider: &str, id: &str) -> Result<(), Error> {
+        self.other_sink(provider).await?.set_sink_id(id);
+        Ok(())
+    }
+
+    /// Constructs the data sink of a provider other than the primary one.
+    async fn other_sink(&self, provider: &str) -> Result<BoxedDataSink, Error> {
+        Providers::from_name(provider)
+            .ok_or_else(|| Error::UnknownSinkProvider(provider.into()))?;
+        if provider == self.sinks[0].provider {
+            return Err(Error::InvalidSinkConfiguration(format!(
+                "{} is already the active data sink",
+                provider
+            )));
+        }
+
+        registry::new_sink_by_name(provider).await
+    }
+
+    /// ## `remove_mirror_sink()`
+    /// Stops replicating to the data sink of the given provider, leaving its contents untouched.
+    pub async fn remove_mirror_sink(&mut self, provider: &str) -> Result<(), Error> {
+        let mut mirrors = registry::mirror_providers();
+        mirrors.retain(|m| m != provider);
+        registry::set_mirror_providers(&mirrors);
+        self.reload_sink().await?;
+        info!("Removed mirror data sink {}", provider);
+        Ok(())
+    }
+
+    /// ## `migrate_sink()`
+    /// Copies the payload of the active data sink to the one of the given provider, verifies it arrived intact
+    /// and then makes it the active one. The old sink is dealt with according to `old_sink`.
+    /// A different list already stored on the target is only replaced with `force`, unless the target is one of our mirrors.
+    ///
+    /// # Returns
+    /// The ID of the new data sink.
+    pub async fn migrate_sink(
+        &mut self,
+        provider: &str,
+        old_sink: OldSinkAction,
+        force: bool,
+    ) -> Result<String, Error> {
+        let target = self.other_sink(provider).await?;
+        if !self.primary().is_initialised() {
+            return Err(Error::GistIdMissing);
+        }
+
+        info!(
+            "Migrating from {} to {}...",
+            self.sinks[0].provider, provider
+        );
+        let payload = self.primary().fetch().await?;
+        if !target.is_initialised() {
+            target.create(payload.clone()).await?;
+        }
+
+        // `create` joins lists which already exist on the target, so we have to make sure it holds ours
+        let same_payload = |stored: &FlatpakInstallationPayload| {
+            serde_json::to_value(stored).ok() == serde_json::to_value(&payload).ok()
+        };
+        let mut stored = target.fetch().await?;
+        if !same_payload(&stored) {
+            let mirror = self.sinks.iter().any(|s| s.provider == provider);
+            if !mirror && !force {
+                return Err(Error::MigrationTargetOccupied(provider.into()));
+            }
+            warn!("Replacing the FlatSync list stored on {}", provider);
+            target.update(payload.clone()).await?;
+            stored = target.fetch().await?;
+        }
+        if !same_payload(&stored) {
+            return Err(Error::MigrationVerificationFailed(provider.into()));
+        }
+
+        registry::switch_provider(provider);
+        let sinks = Self::load_sinks().await?;
+        let old = std::mem::replace(&mut self.sinks, sinks).remove(0);
+        info!("Switched data sink provider to {}", provider);
+
+        match old_sink {
+            OldSinkAction::Keep => {}
+            OldSinkAction::Archive => {
+                let file = Self::archive(&old.provider, &payload)
+                    .map_err(|e| Error::OldSinkCleanupFailure(e.to_string()))?;
+                info!("Archived the list of {} in {:?}", old.provider, file);
+                old.sink.set_sink_id("");
+            }
+            OldSinkAction::Delete => {
+                old.sink
+                    .delete()
+                    .await
+                    .map_err(|e| Error::OldSinkCleanupFailure(e.to_string()))?;
+                old.sink.set_sink_id("");
+            }
+        }
+
+        Ok(self.primary().sink_id())
+    }
+
+    /// Stores a copy of the payload of a data sink we stopped using, so that it can be restored later.
+    fn archive(provider: &str, payload: &FlatpakInstallationPayload) -> Result<PathBuf, Error> {
+        let mut path = glib::user_data_dir();
+        path.push("flatsync");
+        path.push("archive");
+        std::fs::create_dir_all(&path)?;
+        path.push(format!(
+            "{}-{}.json",
+            provider,
+            chrono::Utc::now().format("%Y%m%dT%H%M%S")
+        ));
+
+        payload
+            .write_to_file(&path)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+        Ok(path)
+    }
+
+    /// ## `sink_health()`
+    /// Reports the outcome of the latest operations on each data sink, starting with the primary one.
+    pub fn sink_health(&self) -> Vec<SinkStatus> {
+        self.sinks
+            .iter()
+            .enumerate()
+            .map(|(i, ProviderSink { provider, sink })| {
+                let health = health::get(provider);
+                SinkStatus {
+                    provider: provider.clone(),
+                    sink_id: sink.sink_id(),
+                    primary: i == 0,
+                    healthy: health.is_healthy(),
+                    last_success: health.last_success.map_or(0, |t| t.timestamp()),
+                    last_error: health.last_error.unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// ## `sync_plan()`
+    /// Computes what the next sync would change on this device, based on the latest version on the sinks.
+    /// Deferred updates and changes held back for approval aren't part of the plan, see `Context::sync_plan()`.
+    pub async fn sync_plan(&self) -> Result<SyncPlan, Error> {
+        let Some(remote) = self.fetch_gist().await? else {
+            return Ok(SyncPlan::default());
+        };
+
+        let ctx = Context::new()?;
+        let merge = ctx.merge(&remote);
+        ctx.sync_plan(&merge.payload, false).map(|(plan, _)| plan)
+    }
+
+    /// ## `sync_conflicts()`
+    /// The conflicts found while merging with the remote during the latest sync.
+    pub fn sync_conflicts(&self) -> Vec<SyncConflict> {
+        merge::conflicts()
+    }
+
+    /// ## `devices()`
+    /// Lists the devices syncing through the payload, as of the latest version on the sinks.
+    pub async fn devices(&self) -> Result<Vec<DeviceInfo>, Error> {
+        let Some(payload) = self.fetch_gist().await? else {
+            return Ok(vec![]);
+        };
+        let this_device = device::device_id();
+
+        Ok(payload
+            .devices
+            .into_values()
+            .map(|d| DeviceInfo {
+                this_device: d.id == this_device,
+                id: d.id,
+                name: d.name,
+                hostname: d.hostname,
+                arch: d.arch,
+                last_seen: d.last_seen.timestamp(),
+                refs: d
+                    .refs
+                    .into_iter()
+                    .flat_map(|(kind, refs)| refs.into_iter().map(move |r| (kind.to_string(), r)))
+                    .collect(),
+            })
+            .collect())
+    }
+
+    pub fn device_name(&self) -> String {
+        device::device_name()
+    }
+
+    pub fn set_device_name(&self, name: &str) {
+        device::set_device_name(name);
+    }
+
+    /// ## `profiles()`
+    /// Lists the profiles stored in the payload, as of the latest version on the sinks.
+    pub async fn profiles(&self) -> Result<Vec<ProfileInfo>, Error> {
+        let subscribed = self.subscribed_profiles();
+        let profiles = self
+            .fetch_gist()
+            .await?
+            .map(|payload| payload.profiles)
+            .unwrap_or_default();
+
+        Ok(profiles
+            .into_iter()
+            .map(|(name, profile)| ProfileInfo {
+                subscribed: subscribed.contains(&name),
+                name,
+                refs: profile.refs.into_iter().collect(),
+            })
+            .collect())
+    }
+
+    /// ## `edit_payload()`
+    /// Applies the given change to the latest payload and pushes it.
+    /// Profiles, shared filter rules and version pins are only ever changed this way, so the sinks always have the latest ones.
+    /// It has to run on the main loop (see `MessageType::EditPayload`), as a sync running at the same time would push the
+    /// payload it fetched before and so revert the edit.
+    pub async fn edit_payload(
+        &self,
+        edit: impl FnOnce(&mut FlatpakInstallationPayload),
+    ) -> Result<(), Error> {
+        let mut payload = self.fetch_gist().await?.ok_or(Error::GistIdMissing)?;
+        edit(&mut payload);
+        self.post_payload(&payload).await
+    }
+
+    pub fn subscribed_profiles(&self) -> Vec<String> {
+        Settings::instance().get("subscribed-profiles")
+    }
+
+    pub fn set_subscribed_profiles(&self, profiles: &[String]) {
+        Settings::instance()
+            .set("subscribed-profiles", profiles.to_vec())
+            .unwrap();
+    }
+
+    pub fn filter_rules(&self) -> Vec<String> {
+        filters::rules().to_strings()
+    }
+
+    /// ## `set_filter_rules()`
+    /// Replaces the filter rules of this device. Shared rules have to be published with `edit_payload()` first,
+    /// as the next sync would take the old ones back otherwise.
+    pub fn set_filter_rules(&self, rules: &[String]) -> Result<(), Error> {
+        filters::set_rules(rules)
+    }
+
+    pub fn sync_filter_rules(&self) -> bool {
+        filters::sync_rules()
+    }
+
+    pub fn set_sync_filter_rules(&self, sync: bool) {
+        filters::set_sync_rules(sync);
+    }
+
+    pub fn version_policy(&self) -> VersionPolicy {
+        versions::policy()
+    }
+
+    pub fn set_version_policy(&self, policy: VersionPolicy) {
+        versions::set_policy(policy);
+    }
+
+    /// ## `version_pins()`
+    /// Lists the refs pinned in the payload as of the latest version on the sinks, with their policy and commit (empty if none).
+    pub async fn version_pins(&self) -> Result<Vec<(String, String, String)>, Error> {
+        let pins = self
+            .fetch_gist()
+            .await?
+            .map(|payload| payload.pins)
+            .unwrap_or_default();
+
+        Ok(pins
+            .into_iter()
+            .map(|(ref_, pin)| {
+                (
+                    ref_,
+                    pin.policy.name().to_string(),
+                    pin.commit.unwrap_or_default(),
+                )
+            })
+            .collect())
+    }
+
+    pub fn propagate_updates(&self) -> bool {
+        versions::propagate_updates()
+    }
+
+    pub fn set_propagate_updates(&self, propagate: bool) {
+        versions::set_propagate_updates(propagate);
+    }
+
+    pub fn remote_aliases(&self) -> Vec<String> {
+        let mut aliases = remotes::aliases()
+            .into_iter()
+            .map(|(shared, local)| format!("{}={}", shared, local))
+            .collect::<Vec<_>>();
+        aliases.sort();
+        aliases
+    }
+
+    pub fn set_remote_aliases(&self, aliases: &[String]) -> Result<(), Error> {
+        remotes::set_aliases(aliases)
+    }
+
+    pub fn sync_mode(&self) -> SyncMode {
+        pending::sync_mode()
+    }
+
+    pub fn set_sync_mode(&self, mode: SyncMode) {
+        pending::set_sync_mode(mode);
+    }
+
+    pub fn pending_changes(&self) -> Vec<PendingChange> {
+        pending::pending()
+    }
+
+    /// ## `decide_pending()`
+    /// Approves or rejects the given pending changes, or all of them if `ids` is `None`.
+    /// Approved changes are applied by the next sync.
+    pub fn decide_pending(&self, ids: Option<&[u32]>, approve: bool) -> Result<usize, Error> {
+        pending::decide(ids, approve)
+    }
+
+    pub fn uninstall_limits(&self) -> (u32, u32) {
+        safety_brake::limits()
+    }
+
+    pub fn set_uninstall_limits(&self, max_count: u32, max_percentage: u32) -> Result<(), Error> {
+        safety_brake::set_limits(max_count, max_percentage)
+    }
+
+    pub fn held_uninstalls(&self) -> Vec<SyncStep> {
+        safety_brake::held_uninstalls()
+    }
+
+    pub fn confirm_uninstalls(&self) -> Result<bool, Error> {
+        safety_brake::confirm()
+    }
+
+    pub fn start_lan_pairing(&self) -> Result<String, Error> {
+        lan::start_pairing()
+    }
+
+    pub async fn pair_lan_device(&self, code: &str) -> Result<String, Error> {
+        lan::pair(code).await
+    }
+
+    pub async fn lan_peers(&self) -> Result<Vec<String>, Error> {
+        lan::peers().await
+    }
+
+    pub async fn unpair_lan_device(&self, name: &str) -> Result<(), Error> {
+        lan::unpair(name).await
+    }
+
     pub async fn set_gist_secret(&self, secret: &str) -> Result<(), Error> {
-        self.sink.set_secret(secret).await
+        self.primary().set_secret(secret).await
     }
 
     pub fn set_gist_id(&self, id: &str) {
-        self.sink.set_sink_id(id);
+        self.primary().set_sink_id(id);
+    }
+
+    /// ## `watch_sink()`
+    /// Lets the data sinks request polls through `sender` once they notice remote changes on their own.
+    pub fn watch_sink(&self, sender: tokio::sync::mpsc::Sender<MessageType>) -> Result<(), Error> {
+        for ProviderSink { sink, .. } in &self.sinks {
+            sink.watch(sender.clone())?;
+        }
+        Ok(())
     }
 
     pub fn autosync(&self) -> bool {
-        self.sink.autosync()
+        self.primary().autosync()
     }
 
     pub fn set_autosync(&self, autosync: bool) {
-        self.sink.set_autosync(autosync)
+        self.primary().set_autosync(autosync)
     }
 
     pub fn autosync_timer(&self) -> u32 {
-        self.sink.autosync_timer()
+        self.primary().autosync_timer()
     }
 
     pub fn set_autosync_timer(&self, timer: u32) {
-        self.sink.set_autosync_timer(timer);
+        self.primary().set_autosync_timer(timer);
+    }
+
+    /// Pushes the payload to all of the given sinks, creating the ones which don't hold a FlatSync list yet.
+    ///
+    /// # Returns
+    /// The outcome for each sink, in the order of `sinks`.
+    async fn push(
+        sinks: &[ProviderSink],
+        payload: &FlatpakInstallationPayload,
+    ) -> Vec<Result<(), Error>> {
+        let mut results = Vec::with_capacity(sinks.len());
+
+        for ProviderSink { provider, sink } in sinks {
+            let res = if sink.is_initialised() {
+                sink.update(payload.clone()).await
+            } else {
+                info!("Creating FlatSync list on mirror data sink {}", provider);
+                sink.create(payload.clone()).await
+            };
+            health::record(provider, &res);
+
+            if let Err(e) = &res {
+                warn!("Failed to update data sink {}: {}", provider, e);
+            }
+            results.push(res);
+        }
+
+        results
     }
 
     pub async fn post_gist(&self) -> Result<(), Error> {
-        let payload = FlatpakInstallationPayload::new_from_system()
+        let mut payload = FlatpakInstallationPayload::new_from_system()
             .map_err(Error::FlatpakInstallationQueryFailure)?;
-        if !self.sink.is_initialised() {
+        filters::active_rules().apply(&mut payload);
+        // Keep what is shared between the devices, there is nothing to keep before the first payload was stored
+        if let Some(remote) = self.fetch_gist().await? {
+            payload.devices = remote.devices;
+            payload.profiles = remote.profiles;
+            payload.filter_rules = remote.filter_rules;
+            payload.pins = remote.pins;
+        }
+        let device = device::this_device(&payload);
+        payload.register_device(device);
+
+        self.post_payload(&payload).await
+    }
+
+    /// ## `post_payload()`
+    /// Pushes the given payload, e.g. the result of a merge, to all sinks.
+    /// The mirrors are only updated once the primary sink was, so that they never get ahead of it, e.g. after a `SinkConflict`.
+    /// Fails if the primary sink couldn't be updated, failing mirrors only show up in their health.
+    pub async fn post_payload(&self, payload: &FlatpakInstallationPayload) -> Result<(), Error> {
+        if !self.primary().is_initialised() {
             return Err(Error::GistIdMissing);
         }
 
-        self.sink.update(payload).await?;
+        Self::push(&self.sinks[..1], payload).await.remove(0)?;
+        Self::push(&self.sinks[1..], payload).await;
         Ok(())
     }
 
+    /// ## `update_mirrors()`
+    /// Replicates a payload fetched from the sinks to the mirrors, so that they keep up with changes made by other devices.
+    pub async fn update_mirrors(&self, payload: &FlatpakInstallationPayload) -> Result<(), Error> {
+        Self::push(&self.sinks[1..], payload)
+            .await
+            .into_iter()
+            .collect()
+    }
+
     pub async fn create_gist(&self) -> Result<String, Error> {
-        if self.sink.is_initialised() {
-            return Err(Error::GistAlreadyInitialized(self.sink.sink_id()));
+        let sink = self.primary();
+        if sink.is_initialised() {
+            return Err(Error::GistAlreadyInitialized(sink.sink_id()));
         }
 
         info!("Creating new gist...");
-        let payload = FlatpakInstallationPayload::new_from_system()
+        let mut payload = FlatpakInstallationPayload::new_from_system()
             .map_err(Error::FlatpakInstallationQueryFailure)?;
+        filters::active_rules().apply(&mut payload);
+        let device = device::this_device(&payload);
+        payload.register_device(device);
+        if filters::sync_rules() {
+            payload.filter_rules = Some(filters::rules().to_strings());
+        }
         trace!("Current gist payload: {:?}", payload);
-        self.sink.create(payload).await?;
+        let res = sink.create(payload).await;
+        health::record(&self.sinks[0].provider, &res);
+        res?;
         info!("Done creating new gist.");
-        Ok(self.sink.sink_id())
+        Ok(sink.sink_id())
     }
 
+    /// Fetches the payload from the primary sink, falling back to the mirrors in order if it is unreachable.
+    ///
+    /// # Returns
+    /// `None` if the primary sink isn't set up yet, as the mirrors only replicate its list.
     pub async fn fetch_gist(&self) -> Result<Option<FlatpakInstallationPayload>, Error> {
-        let val = if self.sink.is_initialised() {
-            Some(self.sink.fetch().await?)
-        } else {
-            None
+        let ProviderSink { provider, sink } = &self.sinks[0];
+        if !sink.is_initialised() {
+            return Ok(None);
+        }
+
+        let res = sink.fetch().await;
+        health::record(provider, &res);
+        let error = match res {
+            Ok(payload) => return Ok(Some(payload)),
+            Err(e) => e,
         };
+        warn!("Failed to fetch from data sink {}: {}", provider, error);
+
+        for ProviderSink { provider, sink } in
+            self.sinks[1..].iter().filter(|s| s.sink.is_initialised())
+        {
+            let res = sink.fetch().await;
+            health::record(provider, &res);
+
+            match res {
+                Ok(payload) => return Ok(Some(payload)),
+                Err(e) => warn!("Failed to fetch from data sink {}: {}", provider, e),
+            }
+        }
 
-        Ok(val)
+        Err(error)
     }
 
     async fn autostart_file_sanbox(&self, install: bool) -> Result<(), Error> {
@@ -5,23 +5,80 @@ use zbus::ConnectionBuilder;
 
 extern crate futures_executor;
 
-use libflatsync_common::config::APP_ID;
+use libflatsync_common::{config::APP_ID, dbus::JoinStrategy, FlatpakInstallationPayload};
 
 mod context;
 mod data_sinks;
 mod dbus;
+mod device;
 mod error;
+mod filters;
 pub use error::DBusError;
 pub use error::Error;
 mod imp;
+mod merge;
+mod pending;
+mod remotes;
+mod safety_brake;
 mod settings;
+mod sync_plan;
+mod versions;
 
 pub struct ManualSync;
 
+/// A change to the profiles, shared filter rules or version pins in the payload.
+pub type PayloadEdit = Box<dyn FnOnce(&mut FlatpakInstallationPayload) + Send>;
+
 pub enum MessageType {
     FlatpakInstallationChanged,
     TimeToPoll(Option<ManualSync>),
     TimerChanged,
+    SinkProviderChanged,
+    /// Started syncing with another FlatSync list, which needs to be joined before the regular sync.
+    Join(JoinStrategy),
+    /// Edits the payload and pushes it. This is done by the main loop, so that a sync running at the same time doesn't revert the edit.
+    EditPayload(PayloadEdit, tokio::sync::oneshot::Sender<Result<(), Error>>),
+}
+
+/// ## `join_remote()`
+/// Reconciles this device with the FlatSync list it just started syncing with, so that the following sync doesn't
+/// take it for the list of the last sync.
+async fn join_remote(
+    ctx: &mut context::Context,
+    imp: &imp::Impl,
+    strategy: JoinStrategy,
+) -> Result<(), Error> {
+    ctx.refresh_local_installations()?;
+    let Some(remote) = imp.fetch_gist().await? else {
+        return Ok(());
+    };
+
+    info!("Joining FlatSync list ({})", strategy.name());
+    // refs kept to this device when joining the previous list are synced with this one, unless adopting it keeps them again
+    filters::reset_device_only_rules();
+    match strategy {
+        // without a base, the merge keeps everything from both sides
+        JoinStrategy::Merge => ctx.forget_base()?,
+        // with the local installations as the base, the list's version of everything wins. Refs missing from the list are
+        // kept to this device, as they would be uninstalled otherwise
+        JoinStrategy::AdoptRemote => {
+            filters::adopt_shared_rules(&remote)?;
+            let kept = filters::keep_to_device(ctx.local_installations(), &remote);
+            if kept > 0 {
+                info!(
+                    "Keeping {} ref(s) the list doesn't contain to this device",
+                    kept
+                );
+            }
+            ctx.mark_synced()?;
+        }
+        JoinStrategy::OverwriteRemote => {
+            imp.post_gist().await?;
+            ctx.mark_synced()?;
+        }
+    }
+
+    Ok(())
 }
 
 async fn poll_remote(
@@ -46,30 +103,68 @@ async fn poll_remote(
     let res = imp.fetch_gist().await;
     match res {
         Ok(Some(remote)) => {
-            // if the local and remote are the same, we don't need to do anything
-            if ctx.installations_changed(&remote) {
-                // compare `altered_at` values to determine which version is newer
-                // this is done so that when a user does not have an active internet connection and alters their installations, the changes are not falsely overwritten from remote
-                let altered_remote = remote.altered_at;
-                let altered_local = ctx.local_altered_at();
-
-                debug!(
-                    "Remote altered at: {:?}\nLocal altered at: {:?}",
-                    altered_remote, altered_local
+            // merge both sides against the state of the last sync, so that changes made on this device while offline
+            // and changes made on other devices in the meantime are both kept
+            let publish_rules = filters::adopt_shared_rules(&remote)?;
+            let merge = ctx.merge(&remote);
+
+            for conflict in &merge.conflicts {
+                warn!(
+                    "Sync conflict for {} '{}' in the {} installation: {}, keeping the local version",
+                    conflict.kind, conflict.name, conflict.installation, conflict.reason
                 );
+            }
+            merge::save_conflicts(&merge.conflicts)?;
 
-                // local is newer, so we push to remote
-                // otherwise, we apply the remote's changes
-                if altered_local > altered_remote {
-                    info!("Local is newer, updating remote...");
-                    imp.post_gist().await?;
-                    info!("Pushed local changes to remote");
-                } else {
-                    info!("Remote is newer, updating local state...");
-                    ctx.sync_to_system(&remote)?;
+            // with update propagation, the commits we update to are pushed so that the other devices follow
+            let propagate_updates = versions::propagate_updates();
+            let push_to_remote = merge::content_differs(&merge.payload, &remote, propagate_updates);
+
+            // updates wait for better conditions even when syncing manually, and in approval mode, changes from other devices
+            // wait for the user unless they have been approved already
+            let (plan, newly_pending) = ctx.sync_plan(&merge.payload, true)?;
+            for step in plan.steps().iter().filter(|step| step.action == "skip") {
+                info!("Skipping {}: {}", step.name, step.reason);
+            }
+            if newly_pending > 0 {
+                info!("Holding back {} change(s) for approval", newly_pending);
+                pending::notify(newly_pending).await;
+            }
+
+            let apply_locally = !plan.is_empty();
+            if apply_locally {
+                info!("Remote has changed, updating local state...");
+                let failures = ctx.apply(&plan)?;
+                if failures.is_empty() {
                     info!("Updated local state");
+                } else {
+                    warn!(
+                        "Updated local state, {} change(s) failed and are retried with the next sync",
+                        failures.len()
+                    );
                 }
             }
+
+            let device = device::this_device(ctx.local_installations());
+            let announce_device = device::needs_announcing(&remote, &device);
+            let mut payload = merge.payload;
+            payload.register_device(device);
+            if publish_rules {
+                payload.filter_rules = Some(filters::rules().to_strings());
+            }
+
+            if push_to_remote || announce_device || publish_rules {
+                info!("Local has changed, updating remote...");
+                imp.post_payload(&payload).await?;
+                info!("Pushed local changes to remote");
+            } else if apply_locally {
+                if let Err(e) = imp.update_mirrors(&remote).await {
+                    warn!("Failed to replicate remote changes to mirrors: {}", e);
+                }
+            }
+
+            // refs that failed to install or uninstall aren't part of the new base, so the next merge retries them
+            ctx.mark_synced()?;
         }
         Ok(None) => {
             debug!("Fetching remote returned empty result");
@@ -105,7 +200,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     info!("Started daemon. Press Ctrl+C to exit");
 
-    let imp = imp::Impl::new().await?;
+    let mut imp = imp::Impl::new().await?;
 
     let mut ctx = context::Context::new()?;
 
@@ -127,6 +222,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // The Setting Key only emits the `changed` signal if it has been read after the listener has been setted up
     settings.get::<u32>("autosync-timer");
 
+    let sender_sink_provider_changed = sender_flatpak_installation_changed.clone();
+
+    settings.connect_changed(Some("sink-provider"), move |_, _| {
+        debug!("Sink Provider Changed");
+        if futures_executor::block_on(
+            sender_sink_provider_changed.send(MessageType::SinkProviderChanged),
+        )
+        .is_err()
+        {
+            debug!("Failed to Send Sink Provider Update");
+        }
+    });
+    settings.get::<String>("sink-provider");
+
+    let sender_mirror_sinks_changed = sender_flatpak_installation_changed.clone();
+
+    settings.connect_changed(Some("mirror-sink-providers"), move |_, _| {
+        debug!("Mirror Sink Providers Changed");
+        if futures_executor::block_on(
+            sender_mirror_sinks_changed.send(MessageType::SinkProviderChanged),
+        )
+        .is_err()
+        {
+            debug!("Failed to Send Mirror Sink Providers Update");
+        }
+    });
+    settings.get::<Vec<String>>("mirror-sink-providers");
+
     // We need a second sender to send a signal for polling the remote every X seconds (defined by the interval above)
     let sender_remote_poll_interval = sender_flatpak_installation_changed.clone();
 
@@ -169,9 +292,28 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         None,
     ));
 
+    let mut location = imp.sink_location();
+
     loop {
+        // Sinks which notice remote changes on their own (e.g. through a file monitor) request polls themselves.
+        // This is redone on every message as the sink's location might have been changed over D-Bus in the meantime.
+        if let Err(e) = imp.watch_sink(sender_remote_poll_interval.clone()) {
+            warn!("Failed to watch data sink for changes: {}", e);
+        }
+
         // We listen for a new message, which can either indicate local installation changes or timed polling of the remote
         if let Some(msg) = reciever.recv().await {
+            // Edits are pushed right away, so they don't need a sync
+            let msg = match msg {
+                MessageType::EditPayload(edit, reply) => {
+                    if reply.send(imp.edit_payload(edit).await).is_err() {
+                        debug!("Failed to Send Payload Edit Result");
+                    }
+                    continue;
+                }
+                msg => msg,
+            };
+
             // Since we always poll the remote in both message cases, we just check if the message indicates local installation changes
             // If so, we update the app's local state to reflect the changes, and poll the remote afterwards
             if matches!(msg, MessageType::FlatpakInstallationChanged) {
@@ -188,11 +330,48 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 ));
             }
 
-            let manual_sync = matches!(msg, MessageType::TimeToPoll(Some(ManualSync)));
+            if matches!(msg, MessageType::SinkProviderChanged) {
+                if let Err(e) = imp.reload_sink().await {
+                    error!("Failed to switch data sink provider: {}", e);
+                }
+            }
+
+            // The state of the last sync belongs to the list it was synced with, merging another list against it would
+            // remove everything the two don't have in common
+            let current = imp.sink_location();
+            if current != location {
+                info!("Syncing with another FlatSync list, keeping everything from both sides");
+                ctx.forget_base()?;
+                location = current;
+            }
+
+            if let MessageType::Join(strategy) = &msg {
+                if let Err(e) = join_remote(&mut ctx, &imp, *strategy).await {
+                    error!("Failed to join the FlatSync list: {}", e);
+                }
+            }
 
-            if (imp.autosync() || manual_sync) && !matches!(msg, MessageType::TimerChanged) {
-                if let Err(e) = poll_remote(&mut ctx, &imp, manual_sync).await {
-                    error!("{}", e.to_string());
+            let manual_sync = matches!(
+                msg,
+                MessageType::TimeToPoll(Some(ManualSync)) | MessageType::Join(_)
+            );
+
+            if (imp.autosync() || manual_sync)
+                && !matches!(
+                    msg,
+                    MessageType::TimerChanged | MessageType::SinkProviderChanged
+                )
+            {
+                match poll_remote(&mut ctx, &imp, manual_sync).await {
+                    // another device pushed between our fetch and our push, so we merge their changes before pushing again
+                    Err(Error::SinkConflict) => {
+                        info!("Remote changed while syncing, syncing again");
+                        if let Err(e) = poll_remote(&mut ctx, &imp, manual_sync).await {
+                            error!("{}", e.to_string());
+                        }
+                    }
+                    Err(e) => error!("{}", e.to_string()),
+                    Ok(()) => {}
                 }
             }
         }
@@ -0,0 +1,524 @@
+use crate::{remotes, Error};
+use libflatsync_common::{
+    dbus::SyncConflict, FlatpakInstallation, FlatpakInstallationKind, FlatpakInstallationMap,
+    FlatpakInstallationPayload, FlatpakRef, FlatpakRemote,
+};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+/// ## `Merge`
+/// The outcome of merging the local and remote installations against the state of the last sync.
+pub struct Merge {
+    pub payload: FlatpakInstallationPayload,
+    pub conflicts: Vec<SyncConflict>,
+}
+
+/// ## `three_way_merge()`
+/// Merges the `local` and `remote` payloads, using `base` (the payload as of the last successful sync) to tell apart
+/// entries that were added on one side from ones that were removed on the other.
+///
+/// Refs are matched by their kind, ID and branch, so that devices of different architectures share them, remotes by their name.
+/// Refs installed from remotes pointing to the same repository under different names count as the same. An entry only disappears if one side removed it, and if both sides
+/// changed an entry in incompatible ways (e.g. installed a ref from different remotes), the local version is kept and a conflict is reported.
+/// Without a base, nothing is considered removed and the result is the union of both sides.
+pub fn three_way_merge(
+    base: Option<&FlatpakInstallationPayload>,
+    local: &FlatpakInstallationPayload,
+    remote: &FlatpakInstallationPayload,
+) -> Merge {
+    merge_with_aliases(base, local, remote, &remotes::aliases())
+}
+
+/// Like `three_way_merge()`, but with the given remote alias rules instead of the configured ones.
+fn merge_with_aliases(
+    base: Option<&FlatpakInstallationPayload>,
+    local: &FlatpakInstallationPayload,
+    remote: &FlatpakInstallationPayload,
+    aliases: &HashMap<String, String>,
+) -> Merge {
+    let mut conflicts = vec![];
+    let mut installations = BTreeMap::new();
+
+    let kinds = local
+        .installations
+        .0
+        .keys()
+        .chain(remote.installations.0.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+
+    for kind in kinds {
+        let base = base.and_then(|b| b.installations(kind));
+        let local = local.installations(kind);
+        let remote = remote.installations(kind);
+
+        // Devices may call the same remote differently, e.g. `flathub-1` after adding a `.flatpakrepo` twice
+        let known_remotes = local
+            .into_iter()
+            .chain(remote)
+            .flat_map(|i| i.remotes.iter())
+            .collect::<Vec<_>>();
+        let refs = merge_entries(
+            base.map(|i| i.refs.as_slice()),
+            local.map(|i| i.refs.as_slice()),
+            remote.map(|i| i.refs.as_slice()),
+            |r: &FlatpakRef| r.sync_key(),
+            same_ref,
+            |a, b| !remotes::same_origin(&a.origin, &b.origin, &known_remotes, aliases),
+            |name, reason| conflicts.push(conflict(kind, "ref", name, reason)),
+        );
+
+        let remotes = merge_entries(
+            base.map(|i| i.remotes.as_slice()),
+            local.map(|i| i.remotes.as_slice()),
+            remote.map(|i| i.remotes.as_slice()),
+            |r: &FlatpakRemote| r.name.clone(),
+            |a, b| a == b,
+            |a, b| a.url != b.url || a.collection_id != b.collection_id,
+            |name, reason| conflicts.push(conflict(kind, "remote", name, reason)),
+        );
+
+        // Paths and display names are specific to each device, so we stick with our own where possible
+        let mut installation: FlatpakInstallation = local.or(remote).cloned().unwrap_or_default();
+        installation.refs = refs;
+        installation.remotes = remotes;
+        installations.insert(kind, installation);
+    }
+
+    Merge {
+        payload: FlatpakInstallationPayload {
+            installations: FlatpakInstallationMap(installations),
+            altered_at: chrono::Utc::now(),
+            // Only the devices themselves update their entries, so the remote ones are always the latest
+            devices: remote.devices.clone(),
+            // Profiles, shared filter rules and pins are edited on the remote directly
+            profiles: remote.profiles.clone(),
+            filter_rules: remote.filter_rules.clone(),
+            pins: remote.pins.clone(),
+        },
+        conflicts,
+    }
+}
+
+/// Whether two versions of a ref are the same. The ones of different architectures are as long as they are installed from the same remote,
+/// since their commits and metadata differ anyway, so that devices don't keep replacing each other's version in the payload.
+fn same_ref(a: &FlatpakRef, b: &FlatpakRef) -> bool {
+    if a.arch == b.arch {
+        a == b
+    } else {
+        a.origin == b.origin
+    }
+}
+
+/// ## `merge_entries()`
+/// Three-way merges a list of refs or remotes.
+///
+/// * `key` - Identifies the same entry on all sides.
+/// * `same` - Whether two versions of an entry are equal, i.e. neither side changed it.
+/// * `incompatible` - Whether two differing versions of an entry can't be reconciled by simply taking the newer one.
+/// * `on_conflict` - Called with the entry's key and a description whenever the local version had to be kept over a remote change.
+fn merge_entries<'a, T: Clone>(
+    base: Option<&'a [T]>,
+    local: Option<&'a [T]>,
+    remote: Option<&'a [T]>,
+    key: impl Fn(&T) -> String,
+    same: impl Fn(&T, &T) -> bool,
+    incompatible: impl Fn(&T, &T) -> bool,
+    mut on_conflict: impl FnMut(String, &str),
+) -> Vec<T> {
+    let index = |entries: Option<&'a [T]>| {
+        entries
+            .unwrap_or_default()
+            .iter()
+            .map(|e| (key(e), e))
+            .collect::<BTreeMap<_, _>>()
+    };
+    let base = index(base);
+    let local = index(local);
+    let remote = index(remote);
+
+    let keys = base
+        .keys()
+        .chain(local.keys())
+        .chain(remote.keys())
+        .cloned()
+        .collect::<BTreeSet<_>>();
+
+    let mut merged = vec![];
+    for k in keys {
+        let entry = match (base.get(&k), local.get(&k), remote.get(&k)) {
+            (_, Some(l), Some(r)) if same(*l, *r) => Some(*l),
+            // Added on both sides
+            (None, Some(l), Some(r)) => {
+                if incompatible(*l, *r) {
+                    on_conflict(k, "added differently on this and another device");
+                    Some(*l)
+                } else {
+                    Some(*r)
+                }
+            }
+            // Changed on at least one side
+            (Some(b), Some(l), Some(r)) => {
+                if same(*l, *b) {
+                    Some(*r)
+                } else if same(*r, *b) || !incompatible(*l, *r) {
+                    Some(*l)
+                } else {
+                    on_conflict(k, "changed differently on this and another device");
+                    Some(*l)
+                }
+            }
+            // Removed on another device
+            (Some(b), Some(l), None) => {
+                if incompatible(*l, *b) {
+                    on_conflict(k, "changed on this device, but removed on another one");
+                    Some(*l)
+                } else {
+                    None
+                }
+            }
+            // Removed on this device
+            (Some(b), None, Some(r)) => {
+                if incompatible(*r, *b) {
+                    on_conflict(k, "changed on another device, but removed on this one");
+                }
+                None
+            }
+            // Added on one side only
+            (None, Some(e), None) | (None, None, Some(e)) => Some(*e),
+            (_, None, None) => None,
+        };
+
+        merged.extend(entry.cloned());
+    }
+
+    merged
+}
+
+fn conflict(
+    kind: FlatpakInstallationKind,
+    entry_kind: &str,
+    name: String,
+    reason: &str,
+) -> SyncConflict {
+    SyncConflict {
+        installation: kind.to_string(),
+        kind: entry_kind.into(),
+        name,
+        reason: reason.into(),
+    }
+}
+
+/// ## `content_differs()`
+/// Whether two payloads differ in the refs or remotes they contain, or in the synced attributes of the remotes.
+///
+/// Unlike a full diff, this ignores details that differ between devices anyway, like installation paths, the architectures of refs,
+/// and the installed commits unless `with_commits` is set. As commits differ between architectures, they are only compared
+/// between refs of the same one.
+pub fn content_differs(
+    a: &FlatpakInstallationPayload,
+    b: &FlatpakInstallationPayload,
+    with_commits: bool,
+) -> bool {
+    let summary = |payload: &FlatpakInstallationPayload| {
+        payload
+            .installations
+            .0
+            .iter()
+            .map(|(kind, installation)| {
+                (
+                    *kind,
+                    installation
+                        .refs
+                        .iter()
+                        .map(|r| (r.sync_key(), r.origin.clone()))
+                        .collect::<BTreeSet<_>>(),
+                    installation
+                        .remotes
+                        .iter()
+                        .map(|r| {
+                            (
+                                r.name.clone(),
+                                r.url.clone(),
+                                r.title.clone(),
+                                r.prio,
+                                r.collection_id.clone(),
+                            )
+                        })
+                        .collect::<BTreeSet<_>>(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+
+    if summary(a) != summary(b) {
+        return true;
+    }
+    if !with_commits {
+        return false;
+    }
+
+    let commits = |payload: &FlatpakInstallationPayload| {
+        payload
+            .installations
+            .0
+            .iter()
+            .flat_map(|(kind, installation)| {
+                installation
+                    .refs
+                    .iter()
+                    .map(move |r| ((*kind, r.ref_.clone()), r.commit.clone()))
+            })
+            .collect::<HashMap<_, _>>()
+    };
+    let (a, b) = (commits(a), commits(b));
+    a.iter()
+        .any(|(ref_, commit)| b.get(ref_).is_some_and(|other| other != commit))
+}
+
+fn conflicts_file() -> PathBuf {
+    let mut path = glib::user_data_dir();
+    path.push("flatsync");
+    path.push("conflicts.json");
+    path
+}
+
+/// ## `save_conflicts()`
+/// Persists the conflicts of the latest merge, replacing the previous ones.
+pub fn save_conflicts(conflicts: &[SyncConflict]) -> Result<(), Error> {
+    let serialized = serde_json::to_string(conflicts)
+        .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+    std::fs::write(conflicts_file(), serialized)?;
+    Ok(())
+}
+
+/// ## `conflicts()`
+/// The conflicts of the latest merge, empty if there were none or no merge happened yet.
+pub fn conflicts() -> Vec<SyncConflict> {
+    std::fs::read(conflicts_file())
+        .ok()
+        .and_then(|content| serde_json::from_slice(&content).ok())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libflatsync_common::FlatpakRefKind;
+
+    /// Entries of `merge_entries()` tests: a key and a version.
+    type Entry = (&'static str, u32);
+
+    fn merge(base: Option<&[Entry]>, local: &[Entry], remote: &[Entry]) -> (Vec<Entry>, usize) {
+        let mut conflicts = 0;
+        let merged = merge_entries(
+            base,
+            Some(local),
+            Some(remote),
+            |e: &Entry| e.0.to_string(),
+            |a, b| a == b,
+            |a, b| a.1 != b.1,
+            |_, _| conflicts += 1,
+        );
+        (merged, conflicts)
+    }
+
+    #[test]
+    fn keeps_entries_added_on_either_side() {
+        let base = [("a", 1)];
+        let (merged, conflicts) = merge(Some(&base), &[("a", 1), ("b", 1)], &[("a", 1), ("c", 1)]);
+
+        assert_eq!(merged, [("a", 1), ("b", 1), ("c", 1)]);
+        assert_eq!(conflicts, 0);
+    }
+
+    #[test]
+    fn drops_entries_removed_on_either_side() {
+        let base = [("a", 1), ("b", 1), ("c", 1)];
+        let (merged, conflicts) = merge(Some(&base), &[("a", 1), ("c", 1)], &[("a", 1), ("b", 1)]);
+
+        assert_eq!(merged, [("a", 1)]);
+        assert_eq!(conflicts, 0);
+    }
+
+    #[test]
+    fn keeps_everything_without_base() {
+        let (merged, conflicts) = merge(None, &[("a", 1)], &[("b", 1)]);
+
+        assert_eq!(merged, [("a", 1), ("b", 1)]);
+        assert_eq!(conflicts, 0);
+    }
+
+    #[test]
+    fn takes_changes_made_on_one_side() {
+        let base = [("a", 1), ("b", 1)];
+        let (merged, conflicts) = merge(Some(&base), &[("a", 2), ("b", 1)], &[("a", 1), ("b", 3)]);
+
+        assert_eq!(merged, [("a", 2), ("b", 3)]);
+        assert_eq!(conflicts, 0);
+    }
+
+    #[test]
+    fn keeps_the_local_version_of_conflicting_changes() {
+        let (merged, conflicts) = merge(Some(&[("a", 1)]), &[("a", 2)], &[("a", 3)]);
+        assert_eq!(merged, [("a", 2)]);
+        assert_eq!(conflicts, 1);
+
+        // Added differently on both sides without a base
+        let (merged, conflicts) = merge(None, &[("a", 2)], &[("a", 3)]);
+        assert_eq!(merged, [("a", 2)]);
+        assert_eq!(conflicts, 1);
+
+        // Changed here, but removed on another device
+        let (merged, conflicts) = merge(Some(&[("a", 1)]), &[("a", 2)], &[]);
+        assert_eq!(merged, [("a", 2)]);
+        assert_eq!(conflicts, 1);
+    }
+
+    fn app(id: &str, arch: &str, origin: &str) -> FlatpakRef {
+        FlatpakRef {
+            kind: FlatpakRefKind::App,
+            ref_: format!("app/{}/{}/stable", id, arch),
+            id: id.into(),
+            arch: arch.into(),
+            branch: "stable".into(),
+            origin: origin.into(),
+            ..Default::default()
+        }
+    }
+
+    fn payload(refs: &[FlatpakRef]) -> FlatpakInstallationPayload {
+        FlatpakInstallationPayload {
+            installations: FlatpakInstallationMap(BTreeMap::from([(
+                FlatpakInstallationKind::User,
+                FlatpakInstallation {
+                    refs: refs.to_vec(),
+                    ..Default::default()
+                },
+            )])),
+            altered_at: chrono::Utc::now(),
+            devices: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            filter_rules: None,
+            pins: BTreeMap::new(),
+        }
+    }
+
+    fn three_way(
+        base: Option<&[FlatpakRef]>,
+        local: &[FlatpakRef],
+        remote: &[FlatpakRef],
+    ) -> (Vec<FlatpakRef>, Vec<SyncConflict>) {
+        let base = base.map(payload);
+        let merge = merge_with_aliases(
+            base.as_ref(),
+            &payload(local),
+            &payload(remote),
+            &HashMap::new(),
+        );
+        let refs = merge
+            .payload
+            .installations(FlatpakInstallationKind::User)
+            .unwrap()
+            .refs
+            .clone();
+        (refs, merge.conflicts)
+    }
+
+    #[test]
+    fn merges_refs_installed_and_uninstalled_on_different_devices() {
+        let (maps, builder, boxes) = (
+            app("org.gnome.Maps", "x86_64", "flathub"),
+            app("org.gnome.Builder", "x86_64", "flathub"),
+            app("org.gnome.Boxes", "x86_64", "flathub"),
+        );
+
+        // Builder was uninstalled here and Boxes installed on another device
+        let (refs, conflicts) = three_way(
+            Some(&[maps.clone(), builder.clone()]),
+            &[maps.clone()],
+            &[maps.clone(), builder, boxes.clone()],
+        );
+
+        assert_eq!(refs, [boxes, maps]);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn unions_the_refs_without_base() {
+        let (maps, builder) = (
+            app("org.gnome.Maps", "x86_64", "flathub"),
+            app("org.gnome.Builder", "x86_64", "flathub"),
+        );
+
+        let (refs, conflicts) = three_way(None, &[maps.clone()], &[builder.clone()]);
+
+        assert_eq!(refs, [builder, maps]);
+        assert!(conflicts.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_local_ref_installed_from_a_different_remote() {
+        let local = app("org.gnome.Maps", "x86_64", "flathub");
+        let remote = app("org.gnome.Maps", "x86_64", "gnome-nightly");
+
+        let (refs, conflicts) = three_way(None, &[local.clone()], &[remote]);
+
+        assert_eq!(refs, [local]);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].name, "app/org.gnome.Maps/stable");
+    }
+
+    #[test]
+    fn shares_refs_between_architectures() {
+        let local = app("org.gnome.Maps", "x86_64", "flathub");
+        let remote = app("org.gnome.Maps", "aarch64", "flathub");
+
+        let (refs, conflicts) = three_way(Some(&[local.clone()]), &[local.clone()], &[remote]);
+
+        assert_eq!(refs, [local]);
+        assert!(conflicts.is_empty());
+    }
+
+    fn with_commit(mut ref_: FlatpakRef, commit: &str) -> FlatpakRef {
+        ref_.commit = commit.into();
+        ref_
+    }
+
+    #[test]
+    fn compares_commits_only_on_request() {
+        let old = payload(&[with_commit(
+            app("org.gnome.Maps", "x86_64", "flathub"),
+            "a1",
+        )]);
+        let new = payload(&[with_commit(
+            app("org.gnome.Maps", "x86_64", "flathub"),
+            "b2",
+        )]);
+
+        assert!(!content_differs(&old, &new, false));
+        assert!(content_differs(&old, &new, true));
+    }
+
+    #[test]
+    fn compares_commits_only_within_an_architecture() {
+        let x86_64 = payload(&[with_commit(
+            app("org.gnome.Maps", "x86_64", "flathub"),
+            "a1",
+        )]);
+        let aarch64 = payload(&[with_commit(
+            app("org.gnome.Maps", "aarch64", "flathub"),
+            "b2",
+        )]);
+
+        assert!(!content_differs(&x86_64, &aarch64, true));
+    }
+
+    #[test]
+    fn notices_different_refs_across_architectures() {
+        let x86_64 = payload(&[app("org.gnome.Maps", "x86_64", "flathub")]);
+        let aarch64 = payload(&[app("org.gnome.Builder", "aarch64", "flathub")]);
+
+        assert!(content_differs(&x86_64, &aarch64, false));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::{settings::Settings, sync_plan::SyncPlan, Error};
+use ashpd::desktop::notification::{Notification, NotificationProxy, Priority};
+use chrono::{DateTime, Utc};
+use libflatsync_common::dbus::{PendingChange, SyncMode, SyncStep};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashSet, path::PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum State {
+    Pending,
+    Approved,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct QueuedChange {
+    id: u32,
+    step: SyncStep,
+    queued_at: DateTime<Utc>,
+    state: State,
+}
+
+/// The changes held back in approval mode, persisted so that they survive restarts of the daemon.
+///
+/// Approving a change doesn't apply it directly, it only lets the next sync through with it.
+/// Rejected changes stay in the queue for as long as the sync would make them, so that they aren't offered again.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PendingQueue {
+    next_id: u32,
+    changes: Vec<QueuedChange>,
+}
+
+/// Steps are the same change if they do the same to the same remote or ref, regardless of the reason.
+fn same_change(a: &SyncStep, b: &SyncStep) -> bool {
+    a.installation == b.installation && a.action == b.action && a.name == b.name
+}
+
+impl PendingQueue {
+    fn file() -> PathBuf {
+        let mut path = glib::user_data_dir();
+        path.push("flatsync");
+        path.push("pending.json");
+        path
+    }
+
+    fn load() -> Self {
+        std::fs::read(Self::file())
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let serialized = serde_json::to_string(self)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+        std::fs::write(Self::file(), serialized)?;
+        Ok(())
+    }
+}
+
+/// ## `sync_mode()`
+/// The mode selected in the `sync-mode` setting.
+pub fn sync_mode() -> SyncMode {
+    let name: String = Settings::instance().get("sync-mode");
+    SyncMode::from_name(&name).unwrap_or(SyncMode::Automatic)
+}
+
+pub fn set_sync_mode(mode: SyncMode) {
+    Settings::instance().set("sync-mode", mode.name()).unwrap();
+}
+
+fn needs_approval(mode: SyncMode, step: &SyncStep) -> bool {
+    match mode {
+        SyncMode::Automatic => false,
+        SyncMode::ApproveRemovals => step.action == "uninstall" || step.action == "remove-remote",
+        SyncMode::ApproveAll => true,
+    }
+}
+
+/// ## `hold_back()`
+/// Removes the changes which need the user's approval in the current sync mode from the plan, queueing the ones we didn't hold back before.
+///
+/// Approved changes stay in the plan and leave the queue. Queued changes the plan doesn't contain anymore, e.g. because the other device
+/// reverted them, are dropped. Unless `record` is set, the queue is left as it is, e.g. when only previewing the sync.
+/// Returns the number of newly queued changes.
+pub fn hold_back(plan: &mut SyncPlan, record: bool) -> Result<usize, Error> {
+    let mode = sync_mode();
+    let mut queue = PendingQueue::load();
+    let mut planned = vec![];
+    let mut approved = HashSet::new();
+    let mut newly_queued = 0;
+
+    plan.retain(|step| {
+        planned.push(step.clone());
+        if !needs_approval(mode, step) {
+            return true;
+        }
+
+        match queue.changes.iter().find(|c| same_change(&c.step, step)) {
+            Some(change) if change.state == State::Approved => {
+                approved.insert(change.id);
+                true
+            }
+            Some(_) => false,
+            None => {
+                queue.changes.push(QueuedChange {
+                    id: queue.next_id,
+                    step: step.clone(),
+                    queued_at: Utc::now(),
+                    state: State::Pending,
+                });
+                queue.next_id += 1;
+                newly_queued += 1;
+                false
+            }
+        }
+    });
+
+    queue.changes.retain(|change| {
+        !approved.contains(&change.id)
+            && needs_approval(mode, &change.step)
+            && planned.iter().any(|step| same_change(&change.step, step))
+    });
+    if record {
+        queue.save()?;
+    }
+
+    Ok(newly_queued)
+}
+
+/// ## `pending()`
+/// The changes waiting for the user's approval.
+pub fn pending() -> Vec<PendingChange> {
+    PendingQueue::load()
+        .changes
+        .into_iter()
+        .filter(|change| change.state == State::Pending)
+        .map(|change| PendingChange {
+            id: change.id,
+            step: change.step,
+            queued_at: change.queued_at.timestamp(),
+        })
+        .collect()
+}
+
+/// ## `decide()`
+/// Approves or rejects the pending changes with the given IDs, or all of them if `ids` is `None`.
+///
+/// Returns the number of changes decided on.
+pub fn decide(ids: Option<&[u32]>, approve: bool) -> Result<usize, Error> {
+    let mut queue = PendingQueue::load();
+    let mut decided = 0;
+
+    for change in queue.changes.iter_mut().filter(|change| {
+        change.state == State::Pending && ids.map_or(true, |ids| ids.contains(&change.id))
+    }) {
+        change.state = if approve {
+            State::Approved
+        } else {
+            State::Rejected
+        };
+        decided += 1;
+    }
+
+    queue.save()?;
+    Ok(decided)
+}
+
+async fn send_notification(count: usize) -> ashpd::Result<()> {
+    let body = format!(
+        "FlatSync held back {} change(s). Review them with `flatsync-cli pending`.",
+        count
+    );
+
+    NotificationProxy::new()
+        .await?
+        .add_notification(
+            "pending-changes",
+            Notification::new("Changes from other devices are waiting for approval")
+                .body(body.as_str())
+                .priority(Priority::Normal),
+        )
+        .await
+}
+
+/// ## `notify()`
+/// Tells the user about newly held back changes through a desktop notification.
+pub async fn notify(count: usize) {
+    if let Err(e) = send_notification(count).await {
+        warn!("Failed to send notification about pending changes: {}", e);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::{settings::Settings, Error};
+use libflatsync_common::FlatpakRemote;
+use log::warn;
+use std::collections::HashMap;
+
+/// ## `parse_alias()`
+/// Parses an alias rule like `flathub-1=flathub`, stating that the remote other devices call `flathub-1` is `flathub` on this one.
+fn parse_alias(rule: &str) -> Option<(String, String)> {
+    let (shared, local) = rule.split_once('=')?;
+    let (shared, local) = (shared.trim(), local.trim());
+    if shared.is_empty() || local.is_empty() {
+        return None;
+    }
+    Some((shared.into(), local.into()))
+}
+
+/// ## `aliases()`
+/// The alias rules from the `remote-aliases` setting, mapping the names of remotes in the payload to local ones.
+/// Rules that fail to parse are skipped with a warning.
+pub fn aliases() -> HashMap<String, String> {
+    let rules: Vec<String> = Settings::instance().get("remote-aliases");
+
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let alias = parse_alias(rule);
+            if alias.is_none() {
+                warn!(
+                    "Ignoring remote alias '{}', expected \"shared=local\"",
+                    rule
+                );
+            }
+            alias
+        })
+        .collect()
+}
+
+/// ## `set_aliases()`
+/// Validates the given alias rules and persists them in the `remote-aliases` setting.
+pub fn set_aliases(rules: &[String]) -> Result<(), Error> {
+    let mut normalized = vec![];
+    for rule in rules {
+        let (shared, local) = parse_alias(rule).ok_or_else(|| {
+            Error::InvalidRemoteAlias(format!("'{}': expected \"shared=local\"", rule))
+        })?;
+        normalized.push(format!("{}={}", shared, local));
+    }
+
+    Settings::instance()
+        .set("remote-aliases", normalized)
+        .unwrap();
+    Ok(())
+}
+
+fn normalized_url(remote: &FlatpakRemote) -> Option<&str> {
+    remote.url.as_deref().map(|url| url.trim_end_matches('/'))
+}
+
+/// ## `same_repository()`
+/// Whether two remotes point to the same repository, regardless of their names, by their collection ID or their URL.
+pub fn same_repository(a: &FlatpakRemote, b: &FlatpakRemote) -> bool {
+    let same_collection = a.collection_id.is_some() && a.collection_id == b.collection_id;
+    let same_url = normalized_url(a).is_some() && normalized_url(a) == normalized_url(b);
+
+    same_collection || same_url
+}
+
+/// ## `RemoteResolver`
+/// Maps the remotes of a payload to the ones configured in a local installation, which might go by other names,
+/// e.g. `flathub-1` instead of `flathub` after adding a `.flatpakrepo` twice.
+pub struct RemoteResolver<'a> {
+    aliases: HashMap<String, String>,
+    local: &'a [FlatpakRemote],
+    shared: &'a [FlatpakRemote],
+}
+
+impl<'a> RemoteResolver<'a> {
+    pub fn new(local: &'a [FlatpakRemote], shared: &'a [FlatpakRemote]) -> Self {
+        Self {
+            aliases: aliases(),
+            local,
+            shared,
+        }
+    }
+
+    fn local_by_name(&self, name: &str) -> Option<&'a FlatpakRemote> {
+        self.local.iter().find(|remote| remote.name == name)
+    }
+
+    /// ## `resolve()`
+    /// The local remote a remote of the payload corresponds to, if there is one.
+    ///
+    /// Alias rules take precedence, followed by a local remote of the same name, followed by one pointing to the same repository.
+    pub fn resolve(&self, shared: &FlatpakRemote) -> Option<&'a FlatpakRemote> {
+        self.aliases
+            .get(&shared.name)
+            .and_then(|alias| self.local_by_name(alias))
+            .or_else(|| self.local_by_name(&shared.name))
+            .or_else(|| {
+                self.local
+                    .iter()
+                    .find(|local| same_repository(local, shared))
+            })
+    }
+
+    /// ## `resolve_origin()`
+    /// The name of the local remote to install a ref of the payload from, given the name of the remote it was installed from elsewhere.
+    /// Falls back to that name if there is no matching local remote, e.g. because it is about to be added.
+    pub fn resolve_origin(&self, origin: &str) -> String {
+        let resolved = match self.shared.iter().find(|remote| remote.name == origin) {
+            Some(shared) => self.resolve(shared),
+            None => self
+                .aliases
+                .get(origin)
+                .and_then(|alias| self.local_by_name(alias)),
+        };
+
+        resolved.map_or_else(|| origin.to_string(), |remote| remote.name.clone())
+    }
+
+    /// ## `is_shared()`
+    /// Whether any remote of the payload corresponds to the given local one.
+    pub fn is_shared(&self, local: &FlatpakRemote) -> bool {
+        self.shared
+            .iter()
+            .any(|shared| self.resolve(shared).is_some_and(|r| r.name == local.name))
+    }
+}
+
+/// ## `same_origin()`
+/// Whether two names of remotes refer to the same repository, by the alias rules or the given remotes they name.
+pub fn same_origin(
+    a: &str,
+    b: &str,
+    remotes: &[&FlatpakRemote],
+    aliases: &HashMap<String, String>,
+) -> bool {
+    if a == b {
+        return true;
+    }
+
+    if aliases.get(a).is_some_and(|alias| alias == b)
+        || aliases.get(b).is_some_and(|alias| alias == a)
+    {
+        return true;
+    }
+
+    let named = |name: &str| {
+        remotes
+            .iter()
+            .filter(|remote| remote.name == name)
+            .copied()
+            .collect::<Vec<_>>()
+    };
+    let (a, b) = (named(a), named(b));
+    a.iter().any(|a| b.iter().any(|b| same_repository(a, b)))
+}
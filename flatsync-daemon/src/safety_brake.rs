@@ -0,0 +1,245 @@
+use crate::{settings::Settings, sync_plan::SyncPlan, Error};
+use chrono::{DateTime, Utc};
+use libflatsync_common::dbus::SyncStep;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// A sync the safety brake stopped, persisted until it is confirmed or no longer exceeds the limits.
+#[derive(Debug, Serialize, Deserialize)]
+struct BrakeRecord {
+    tripped_at: DateTime<Utc>,
+    /// Number of refs in scope of syncing that were installed when the brake tripped.
+    installed: usize,
+    uninstalls: Vec<SyncStep>,
+    confirmed: bool,
+}
+
+impl BrakeRecord {
+    fn file() -> PathBuf {
+        let mut path = glib::user_data_dir();
+        path.push("flatsync");
+        path.push("brake.json");
+        path
+    }
+
+    fn load() -> Option<Self> {
+        std::fs::read(Self::file())
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let serialized = serde_json::to_string(self)
+            .map_err(|e| Error::FlatpakInstallationFileFailure(e.to_string()))?;
+        std::fs::write(Self::file(), serialized)?;
+        Ok(())
+    }
+
+    fn clear() -> Result<(), Error> {
+        match std::fs::remove_file(Self::file()) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether the user confirmed all of the given uninstallations, rather than a different set of them.
+    fn covers(&self, uninstalls: &[SyncStep]) -> bool {
+        self.confirmed
+            && uninstalls.iter().all(|step| {
+                self.uninstalls
+                    .iter()
+                    .any(|s| s.installation == step.installation && s.name == step.name)
+            })
+    }
+}
+
+/// ## `limits()`
+/// The maximum number and percentage of installed refs a sync may uninstall without confirmation, `0` disabling the respective limit.
+pub fn limits() -> (u32, u32) {
+    let settings = Settings::instance();
+    (
+        settings.get("max-uninstalls"),
+        settings.get("max-uninstall-percentage"),
+    )
+}
+
+/// ## `set_limits()`
+/// Persists the limits, the percentage has to be at most 100.
+pub fn set_limits(max_count: u32, max_percentage: u32) -> Result<(), Error> {
+    if max_percentage > 100 {
+        return Err(Error::InvalidUninstallLimits(format!(
+            "{}% is more than all installed refs",
+            max_percentage
+        )));
+    }
+
+    let settings = Settings::instance();
+    settings.set("max-uninstalls", max_count).unwrap();
+    settings
+        .set("max-uninstall-percentage", max_percentage)
+        .unwrap();
+    Ok(())
+}
+
+/// Whether uninstalling `count` of the `installed` refs needs confirmation with the given `limits()`.
+///
+/// Leaving fewer than a tenth of the refs behind always does, regardless of the limits, as that is what applying an empty
+/// or truncated payload looks like.
+fn exceeds_limits(count: usize, installed: usize, (max_count, max_percentage): (u32, u32)) -> bool {
+    if count == 0 {
+        return false;
+    }
+
+    let near_empty = (installed - count.min(installed)) * 10 < installed;
+
+    near_empty
+        || (max_count > 0 && count > max_count as usize)
+        || (max_percentage > 0 && count * 100 > installed * max_percentage as usize)
+}
+
+/// ## `check()`
+/// Stops a sync whose plan uninstalls more of the `installed` refs than the limits allow, recording the uninstallations so that
+/// the user can review and confirm them.
+///
+/// A confirmation lets the next sync through, as long as it doesn't uninstall anything that wasn't confirmed.
+/// Unless `record` is set, neither the stopped uninstallations nor a used up confirmation are recorded, e.g. when only previewing the sync.
+pub fn check(plan: &SyncPlan, installed: usize, record: bool) -> Result<(), Error> {
+    let uninstalls = plan
+        .steps()
+        .into_iter()
+        .filter(|step| step.action == "uninstall")
+        .collect::<Vec<_>>();
+
+    let clear = || if record { BrakeRecord::clear() } else { Ok(()) };
+
+    if !exceeds_limits(uninstalls.len(), installed, limits()) {
+        return clear();
+    }
+
+    if BrakeRecord::load().is_some_and(|brake| brake.covers(&uninstalls)) {
+        info!(
+            "Uninstalling {} ref(s) as confirmed by the user",
+            uninstalls.len()
+        );
+        return clear();
+    }
+
+    let count = uninstalls.len();
+    if record {
+        BrakeRecord {
+            tripped_at: Utc::now(),
+            installed,
+            uninstalls,
+            confirmed: false,
+        }
+        .save()?;
+    }
+
+    Err(Error::UninstallLimitExceeded(count, installed))
+}
+
+/// ## `held_uninstalls()`
+/// The uninstallations of the sync the safety brake stopped last, empty if it didn't stop any or they were confirmed.
+pub fn held_uninstalls() -> Vec<SyncStep> {
+    BrakeRecord::load()
+        .filter(|record| !record.confirmed)
+        .map(|record| record.uninstalls)
+        .unwrap_or_default()
+}
+
+/// ## `confirm()`
+/// Lets the next sync make the uninstallations the safety brake stopped. Returns `false` if there were none.
+pub fn confirm() -> Result<bool, Error> {
+    match BrakeRecord::load() {
+        Some(mut record) => {
+            record.confirmed = true;
+            record.save()?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn uninstall(name: &str) -> SyncStep {
+        SyncStep {
+            installation: "user".into(),
+            action: "uninstall".into(),
+            name: name.into(),
+            origin: "flathub".into(),
+            reason: "removed on another device".into(),
+        }
+    }
+
+    fn record(uninstalls: &[&str], confirmed: bool) -> BrakeRecord {
+        BrakeRecord {
+            tripped_at: Utc::now(),
+            installed: 20,
+            uninstalls: uninstalls.iter().map(|name| uninstall(name)).collect(),
+            confirmed,
+        }
+    }
+
+    #[test]
+    fn nothing_to_uninstall_never_exceeds_limits() {
+        assert!(!exceeds_limits(0, 0, (1, 1)));
+        assert!(!exceeds_limits(0, 20, (1, 1)));
+    }
+
+    #[test]
+    fn limits_the_count() {
+        assert!(!exceeds_limits(5, 20, (5, 0)));
+        assert!(exceeds_limits(6, 20, (5, 0)));
+        assert!(!exceeds_limits(6, 20, (0, 0)));
+    }
+
+    #[test]
+    fn limits_the_percentage() {
+        assert!(!exceeds_limits(5, 20, (0, 25)));
+        assert!(exceeds_limits(6, 20, (0, 25)));
+        assert!(exceeds_limits(6, 20, (10, 25)));
+    }
+
+    #[test]
+    fn leaving_nearly_nothing_always_exceeds_limits() {
+        assert!(exceeds_limits(20, 20, (0, 0)));
+        assert!(exceeds_limits(19, 20, (0, 0)));
+        assert!(!exceeds_limits(18, 20, (0, 0)));
+        assert!(exceeds_limits(1, 1, (0, 0)));
+    }
+
+    #[test]
+    fn confirmations_cover_subsets() {
+        let record = record(
+            &[
+                "app/org.gnome.Maps/x86_64/stable",
+                "app/org.gnome.Builder/x86_64/stable",
+            ],
+            true,
+        );
+
+        assert!(record.covers(&[uninstall("app/org.gnome.Maps/x86_64/stable")]));
+        assert!(record.covers(&[
+            uninstall("app/org.gnome.Builder/x86_64/stable"),
+            uninstall("app/org.gnome.Maps/x86_64/stable"),
+        ]));
+        assert!(!record.covers(&[
+            uninstall("app/org.gnome.Maps/x86_64/stable"),
+            uninstall("app/org.gnome.Boxes/x86_64/stable"),
+        ]));
+
+        let mut system = uninstall("app/org.gnome.Maps/x86_64/stable");
+        system.installation = "system".into();
+        assert!(!record.covers(&[system]));
+    }
+
+    #[test]
+    fn unconfirmed_records_cover_nothing() {
+        let record = record(&["app/org.gnome.Maps/x86_64/stable"], false);
+        assert!(!record.covers(&[uninstall("app/org.gnome.Maps/x86_64/stable")]));
+    }
+}
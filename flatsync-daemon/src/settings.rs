@@ -29,6 +29,8 @@ impl Settings {
 impl Settings {
     delegate::delegate! {
         to self.0 {
+            pub fn apply(&self);
+
             pub fn bind<'a, P: IsA<glib::Object>>(
                 &'a self,
                 key: &'a str,
@@ -42,6 +44,8 @@ impl Settings {
                 f: F
             ) -> glib::SignalHandlerId;
 
+            pub fn delay(&self);
+
             pub fn disconnect(&self, handler_id: glib::SignalHandlerId);
 
             fn enum_(&self, key: &str) -> i32;
@@ -0,0 +1,130 @@
+use libflatsync_common::{dbus::SyncStep, FlatpakInstallationKind, FlatpakRef, FlatpakRemote};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// ## `PlannedChange`
+/// A remote or ref to change, together with the reason for it.
+pub struct PlannedChange<T> {
+    pub item: T,
+    pub reason: String,
+}
+
+/// ## `InstallationPlan`
+/// The changes a sync makes to a single installation.
+#[derive(Default)]
+pub struct InstallationPlan {
+    pub add_remotes: Vec<PlannedChange<FlatpakRemote>>,
+    /// Remotes to bring in line with the given version, matched by name.
+    pub modify_remotes: Vec<PlannedChange<FlatpakRemote>>,
+    /// Refs to install at their `commit`, or at the latest one if it is empty.
+    pub install: Vec<PlannedChange<FlatpakRef>>,
+    /// Remotes to refresh the metadata of before installing, see `VersionPolicy::NotOlderThanRemote`.
+    pub refresh_remotes: BTreeSet<String>,
+    /// Installed refs to update to their `commit`.
+    pub update: Vec<PlannedChange<FlatpakRef>>,
+    pub uninstall: Vec<PlannedChange<FlatpakRef>>,
+    /// Remotes to remove once the refs are uninstalled.
+    pub remove_remotes: Vec<PlannedChange<FlatpakRemote>>,
+    /// Refs of the payload which can't be installed here, e.g. because the remote doesn't offer them for this architecture.
+    /// They are only reported and not part of the changes.
+    pub skipped: Vec<PlannedChange<FlatpakRef>>,
+}
+
+/// ## `SyncPlan`
+/// The changes a sync makes to the local installations, computed by `Context::plan()` and carried out by `Context::apply()`.
+#[derive(Default)]
+pub struct SyncPlan {
+    pub installations: BTreeMap<FlatpakInstallationKind, InstallationPlan>,
+}
+
+fn remote_step(
+    kind: FlatpakInstallationKind,
+    action: &str,
+    change: &PlannedChange<FlatpakRemote>,
+) -> SyncStep {
+    SyncStep {
+        installation: kind.to_string(),
+        action: action.into(),
+        name: change.item.name.clone(),
+        origin: String::new(),
+        reason: change.reason.clone(),
+    }
+}
+
+fn ref_step(
+    kind: FlatpakInstallationKind,
+    action: &str,
+    change: &PlannedChange<FlatpakRef>,
+) -> SyncStep {
+    SyncStep {
+        installation: kind.to_string(),
+        action: action.into(),
+        name: change.item.ref_.clone(),
+        origin: change.item.origin.clone(),
+        reason: change.reason.clone(),
+    }
+}
+
+impl SyncPlan {
+    pub fn is_empty(&self) -> bool {
+        self.installations.values().all(|plan| {
+            plan.add_remotes.is_empty()
+                && plan.modify_remotes.is_empty()
+                && plan.install.is_empty()
+                && plan.update.is_empty()
+                && plan.uninstall.is_empty()
+                && plan.remove_remotes.is_empty()
+        })
+    }
+
+    /// ## `steps()`
+    /// Flattens the plan into the steps reported over D-Bus, including the skipped refs with the `skip` action.
+    pub fn steps(&self) -> Vec<SyncStep> {
+        let mut steps = vec![];
+
+        for (kind, plan) in &self.installations {
+            steps.extend(
+                plan.add_remotes
+                    .iter()
+                    .map(|c| remote_step(*kind, "add-remote", c)),
+            );
+            steps.extend(
+                plan.modify_remotes
+                    .iter()
+                    .map(|c| remote_step(*kind, "modify-remote", c)),
+            );
+            steps.extend(plan.install.iter().map(|c| ref_step(*kind, "install", c)));
+            steps.extend(plan.update.iter().map(|c| ref_step(*kind, "update", c)));
+            steps.extend(
+                plan.uninstall
+                    .iter()
+                    .map(|c| ref_step(*kind, "uninstall", c)),
+            );
+            steps.extend(
+                plan.remove_remotes
+                    .iter()
+                    .map(|c| remote_step(*kind, "remove-remote", c)),
+            );
+            steps.extend(plan.skipped.iter().map(|c| ref_step(*kind, "skip", c)));
+        }
+
+        steps
+    }
+
+    /// ## `retain()`
+    /// Drops the changes for whose steps `keep` returns `false`.
+    pub fn retain(&mut self, mut keep: impl FnMut(&SyncStep) -> bool) {
+        for (kind, plan) in self.installations.iter_mut() {
+            plan.add_remotes
+                .retain(|c| keep(&remote_step(*kind, "add-remote", c)));
+            plan.modify_remotes
+                .retain(|c| keep(&remote_step(*kind, "modify-remote", c)));
+            plan.install
+                .retain(|c| keep(&ref_step(*kind, "install", c)));
+            plan.update.retain(|c| keep(&ref_step(*kind, "update", c)));
+            plan.uninstall
+                .retain(|c| keep(&ref_step(*kind, "uninstall", c)));
+            plan.remove_remotes
+                .retain(|c| keep(&remote_step(*kind, "remove-remote", c)));
+        }
+    }
+}
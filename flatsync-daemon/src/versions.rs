@@ -0,0 +1,53 @@
+use crate::settings::Settings;
+use libflatsync_common::{FlatpakInstallationPayload, FlatpakRef, VersionPolicy};
+
+/// ## `policy()`
+/// The version policy from the `version-policy` setting, which applies to all refs the payload doesn't pin.
+pub fn policy() -> VersionPolicy {
+    let name: String = Settings::instance().get("version-policy");
+    VersionPolicy::from_name(&name).unwrap_or(VersionPolicy::Latest)
+}
+
+pub fn set_policy(policy: VersionPolicy) {
+    Settings::instance()
+        .set("version-policy", policy.name())
+        .unwrap();
+}
+
+/// ## `propagate_updates()`
+/// Whether the `propagate-updates` setting is on, so that installed refs are updated to the commit installed on the other devices.
+pub fn propagate_updates() -> bool {
+    Settings::instance().get("propagate-updates")
+}
+
+pub fn set_propagate_updates(propagate: bool) {
+    Settings::instance()
+        .set("propagate-updates", propagate)
+        .unwrap();
+}
+
+/// ## `policy_for()`
+/// The version policy of a ref of the payload, together with the commit it is pinned to, if any.
+///
+/// Pins apply to the ref on all architectures, as devices list it for their own one. Commits differ between architectures though,
+/// so the pinned commit is only returned for the architecture of the pinned ref.
+pub fn policy_for<'a>(
+    payload: &'a FlatpakInstallationPayload,
+    ref_: &FlatpakRef,
+) -> (VersionPolicy, Option<&'a str>) {
+    let pin = payload.pins.get_key_value(&ref_.ref_).or_else(|| {
+        let key = ref_.sync_key();
+        payload
+            .pins
+            .iter()
+            .find(|(pinned, _)| FlatpakRef::sync_key_of(pinned).as_deref() == Some(key.as_str()))
+    });
+
+    match pin {
+        Some((pinned, pin)) => (
+            pin.policy,
+            pin.commit.as_deref().filter(|_| *pinned == ref_.ref_),
+        ),
+        None => (policy(), None),
+    }
+}
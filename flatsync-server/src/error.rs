@@ -0,0 +1,44 @@
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Missing or invalid access token")]
+    Unauthorized,
+    #[error("No payload has been stored yet")]
+    NotFound,
+    #[error("Invalid name '{0}', only ASCII letters, digits, '-', '_' and '.' are allowed")]
+    InvalidName(String),
+    #[error("Invalid payload: {0}")]
+    InvalidPayload(String),
+    #[error("Account '{0}' already exists")]
+    AccountExists(String),
+    #[error("The account's payloads changed since they were fetched")]
+    Conflict,
+    #[error("Writes need an `If-Match` header with the ETag of the latest payload, or `If-None-Match: *` for the first one")]
+    PreconditionRequired,
+    #[error("Failed to (de)serialize stored data: {0}")]
+    Serde(#[from] serde_json::Error),
+    #[error("IO error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl IntoResponse for Error {
+    fn into_response(self) -> Response {
+        let status = match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::InvalidName(_) | Self::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            Self::AccountExists(_) | Self::Conflict => StatusCode::CONFLICT,
+            Self::PreconditionRequired => StatusCode::PRECONDITION_REQUIRED,
+            Self::Serde(_) | Self::Io(_) => {
+                log::error!("{}", self);
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        };
+
+        (status, self.to_string()).into_response()
+    }
+}
@@ -0,0 +1,283 @@
+use axum::{
+    routing::{delete, get},
+    Router,
+};
+use clap::{Parser, Subcommand};
+use log::info;
+use std::{net::SocketAddr, path::PathBuf, sync::Arc};
+
+mod error;
+pub use error::Error;
+mod routes;
+mod storage;
+
+use storage::Storage;
+
+pub type AppState = Arc<Storage>;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// Directory to keep the stored payloads and access tokens in
+    #[arg(long, default_value = "flatsync-server-data")]
+    data_dir: PathBuf,
+    #[command(subcommand)]
+    cmd: Commands,
+}
+
+#[derive(Debug, Subcommand)]
+enum Commands {
+    /// Serve the FlatSync API
+    Serve {
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: SocketAddr,
+    },
+    /// Create a new account and print its access token
+    AddAccount { name: String },
+}
+
+fn router(state: AppState) -> Router {
+    Router::new()
+        .route("/api/v1/payload", get(routes::latest_payload))
+        .route("/api/v1/devices", get(routes::list_devices))
+        .route(
+            "/api/v1/devices/:device/payload",
+            get(routes::device_payload).put(routes::put_device_payload),
+        )
+        .route("/api/v1/devices/:device", delete(routes::delete_device))
+        .with_state(state)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    pretty_env_logger::init();
+
+    let args = Args::parse();
+    let storage = Storage::open(&args.data_dir)?;
+
+    match args.cmd {
+        Commands::Serve { listen } => {
+            let listener = tokio::net::TcpListener::bind(listen).await?;
+            info!("Listening on {}", listener.local_addr()?);
+            axum::serve(listener, router(Arc::new(storage))).await?;
+        }
+        Commands::AddAccount { name } => {
+            let token = storage.add_account(&name)?;
+            println!("{}", token);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+    use serde_json::{json, Value};
+
+    /// A server with a fresh data directory on a free port of the loopback interface.
+    ///
+    /// # Returns
+    /// Its base URL and the access token of an account on it.
+    async fn serve(name: &str) -> (String, String) {
+        let data_dir = std::env::temp_dir().join(format!(
+            "flatsync-server-test-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&data_dir);
+        let storage = Storage::open(&data_dir).unwrap();
+        let token = storage.add_account("team").unwrap();
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, router(Arc::new(storage)))
+                .await
+                .unwrap()
+        });
+
+        (format!("http://{}", address), token)
+    }
+
+    fn payload(altered_at: &str) -> Value {
+        json!({
+            "installations": {},
+            "altered_at": altered_at,
+        })
+    }
+
+    /// The first payload of an account.
+    const NO_PAYLOAD: (&str, &str) = ("if-none-match", "*");
+
+    async fn put(
+        url: &str,
+        token: &str,
+        device: &str,
+        payload: &Value,
+        precondition: (&str, &str),
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .put(format!("{}/api/v1/devices/{}/payload", url, device))
+            .bearer_auth(token)
+            .header(precondition.0, precondition.1)
+            .json(payload)
+            .send()
+            .await
+            .unwrap()
+    }
+
+    /// The latest payload of the account, along with its ETag.
+    async fn fetch(url: &str, token: &str) -> (Value, String) {
+        let resp = reqwest::Client::new()
+            .get(format!("{}/api/v1/payload", url))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap()
+            .error_for_status()
+            .unwrap();
+        let etag = resp.headers()["etag"].to_str().unwrap().to_string();
+        (resp.json().await.unwrap(), etag)
+    }
+
+    #[tokio::test]
+    async fn rejects_requests_without_valid_token() {
+        let (url, _) = serve("auth").await;
+        let client = reqwest::Client::new();
+
+        let resp = client
+            .get(format!("{}/api/v1/payload", url))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+        let resp = put(
+            &url,
+            "not-a-token",
+            "device",
+            &payload("2024-01-01T00:00:00Z"),
+            NO_PAYLOAD,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn serves_latest_payload_of_all_devices() {
+        let (url, token) = serve("latest").await;
+        let client = reqwest::Client::new();
+
+        let resp = client
+            .get(format!("{}/api/v1/payload", url))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+        let older = payload("2024-01-01T00:00:00Z");
+        let newer = payload("2024-02-01T00:00:00Z");
+        let resp = put(&url, &token, "laptop", &older, NO_PAYLOAD).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let (_, etag) = fetch(&url, &token).await;
+        let resp = put(&url, &token, "desktop", &newer, ("if-match", &etag)).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+
+        let (latest, _) = fetch(&url, &token).await;
+        assert_eq!(latest, newer);
+
+        let devices: Value = client
+            .get(format!("{}/api/v1/devices", url))
+            .bearer_auth(&token)
+            .send()
+            .await
+            .unwrap()
+            .json()
+            .await
+            .unwrap();
+        let names = devices
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|device| device["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["desktop", "laptop"]);
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_device_names_and_payloads() {
+        let (url, token) = serve("invalid").await;
+
+        let valid = payload("2024-01-01T00:00:00Z");
+        let resp = put(&url, &token, ".hidden", &valid, NO_PAYLOAD).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let invalid = json!({ "installations": {} });
+        let resp = put(&url, &token, "device", &invalid, NO_PAYLOAD).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let resp = reqwest::Client::new()
+            .put(format!("{}/api/v1/devices/device/payload", url))
+            .bearer_auth(&token)
+            .json(&valid)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::PRECONDITION_REQUIRED);
+    }
+
+    #[tokio::test]
+    async fn lets_only_one_of_concurrent_writers_through() {
+        let (url, token) = serve("concurrent").await;
+        let resp = put(
+            &url,
+            &token,
+            "laptop",
+            &payload("2024-01-01T00:00:00Z"),
+            NO_PAYLOAD,
+        )
+        .await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        let (_, etag) = fetch(&url, &token).await;
+
+        // Every device fetched the same revision and writes a payload altered later than the others
+        let writes = (2..=17).map(|day| {
+            let (url, token, etag) = (url.clone(), token.clone(), etag.clone());
+            tokio::spawn(async move {
+                let payload = payload(&format!("2024-01-{:02}T00:00:00Z", day));
+                let device = format!("device-{}", day);
+                put(&url, &token, &device, &payload, ("if-match", &etag))
+                    .await
+                    .status()
+            })
+        });
+        let mut statuses = vec![];
+        for write in writes.collect::<Vec<_>>() {
+            statuses.push(write.await.unwrap());
+        }
+        let written = statuses
+            .iter()
+            .filter(|status| **status == StatusCode::NO_CONTENT)
+            .count();
+        assert_eq!(written, 1);
+        assert!(statuses
+            .iter()
+            .all(|status| [StatusCode::NO_CONTENT, StatusCode::CONFLICT].contains(status)));
+
+        // The others have to fetch the winner's payload before they may write theirs
+        let (latest, etag) = fetch(&url, &token).await;
+        let merged = payload("2024-02-01T00:00:00Z");
+        assert_ne!(latest, merged);
+        let resp = put(&url, &token, "laptop", &merged, ("if-match", &etag)).await;
+        assert_eq!(resp.status(), StatusCode::NO_CONTENT);
+        assert_ne!(resp.headers()["etag"], etag.as_str());
+
+        let (latest, _) = fetch(&url, &token).await;
+        assert_eq!(latest, merged);
+
+        let resp = put(&url, &token, "laptop", &merged, ("if-match", &etag)).await;
+        assert_eq!(resp.status(), StatusCode::CONFLICT);
+    }
+}
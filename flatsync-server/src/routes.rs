@@ -0,0 +1,117 @@
+use crate::{
+    storage::{DeviceInfo, Precondition},
+    AppState, Error,
+};
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, Path, State},
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    Json,
+};
+use log::info;
+use serde_json::Value;
+
+/// The account the request's bearer token belongs to.
+pub struct Account(String);
+
+#[async_trait]
+impl FromRequestParts<AppState> for Account {
+    type Rejection = Error;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Error> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.strip_prefix("Bearer "))
+            .ok_or(Error::Unauthorized)?;
+
+        state
+            .account_for_token(token)
+            .map(Account)
+            .ok_or(Error::Unauthorized)
+    }
+}
+
+/// The account's revision as an ETag.
+fn etag(revision: u64) -> [(header::HeaderName, String); 1] {
+    [(header::ETAG, format!("\"{}\"", revision))]
+}
+
+/// The precondition of a write, from its `If-Match` or `If-None-Match: *` header.
+///
+/// An `If-Match` header with an ETag we never handed out can't be fulfilled, so it's treated like an outdated one.
+fn precondition(headers: &HeaderMap) -> Result<Precondition, Error> {
+    if let Some(etag) = headers.get(header::IF_MATCH) {
+        return etag
+            .to_str()
+            .ok()
+            .and_then(|etag| etag.trim_matches('"').parse().ok())
+            .map(Precondition::Revision)
+            .ok_or(Error::Conflict);
+    }
+
+    match headers.get(header::IF_NONE_MATCH) {
+        Some(etag) if etag == "*" => Ok(Precondition::NoPayload),
+        _ => Err(Error::PreconditionRequired),
+    }
+}
+
+/// ## `GET /api/v1/payload`
+/// The most recently altered payload of any of the account's devices, with the account's revision as its ETag
+pub async fn latest_payload(
+    State(state): State<AppState>,
+    Account(account): Account,
+) -> Result<([(header::HeaderName, String); 1], Json<Value>), Error> {
+    let (payload, revision) = state.latest_payload(&account).await?;
+    Ok((etag(revision), Json(payload)))
+}
+
+/// ## `GET /api/v1/devices`
+/// The devices of the account, along with when their payload was last altered
+pub async fn list_devices(
+    State(state): State<AppState>,
+    Account(account): Account,
+) -> Result<Json<Vec<DeviceInfo>>, Error> {
+    Ok(Json(state.devices(&account).await?))
+}
+
+/// ## `GET /api/v1/devices/:device/payload`
+pub async fn device_payload(
+    State(state): State<AppState>,
+    Account(account): Account,
+    Path(device): Path<String>,
+) -> Result<Json<Value>, Error> {
+    Ok(Json(state.device_payload(&account, &device).await?))
+}
+
+/// ## `PUT /api/v1/devices/:device/payload`
+/// Only succeeds if the account's revision still matches the `If-Match` header, or for the account's first payload with
+/// `If-None-Match: *`. Otherwise another device wrote in the meantime, which is answered with `409 Conflict`.
+pub async fn put_device_payload(
+    State(state): State<AppState>,
+    Account(account): Account,
+    Path(device): Path<String>,
+    headers: HeaderMap,
+    Json(payload): Json<Value>,
+) -> Result<(StatusCode, [(header::HeaderName, String); 1]), Error> {
+    let revision = state
+        .put_device_payload(&account, &device, payload, precondition(&headers)?)
+        .await?;
+    info!(
+        "Stored payload of device {} for account {}, now at revision {}",
+        device, account, revision
+    );
+    Ok((StatusCode::NO_CONTENT, etag(revision)))
+}
+
+/// ## `DELETE /api/v1/devices/:device`
+pub async fn delete_device(
+    State(state): State<AppState>,
+    Account(account): Account,
+    Path(device): Path<String>,
+) -> Result<StatusCode, Error> {
+    state.delete_device(&account, &device).await?;
+    info!("Deleted device {} of account {}", device, account);
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,245 @@
+use crate::Error;
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+use tokio::{fs, sync::Mutex};
+
+static TOKENS_FILE_NAME: &str = "tokens.json";
+static REVISION_FILE_NAME: &str = "revision";
+
+/// Summary of the payload a device stored last.
+#[derive(Debug, serde::Serialize)]
+pub struct DeviceInfo {
+    pub name: String,
+    pub altered_at: Option<DateTime<Utc>>,
+}
+
+/// What a write expects the account's payloads to look like, so that devices never overwrite changes they haven't fetched.
+#[derive(Debug, PartialEq)]
+pub enum Precondition {
+    /// The account's revision is still the one the device fetched.
+    Revision(u64),
+    /// None of the account's devices stored a payload yet.
+    NoPayload,
+}
+
+/// File based storage of the payloads, laid out as `<data_dir>/accounts/<account>/devices/<device>.json`.
+///
+/// Access tokens are kept in `<data_dir>/tokens.json`, which maps the SHA-256 hash of every token to its account.
+/// Every account has a revision in `<data_dir>/accounts/<account>/revision`, which counts the writes of its devices.
+pub struct Storage {
+    data_dir: PathBuf,
+    tokens: RwLock<HashMap<String, String>>,
+    /// Held while checking the precondition of a write and carrying it out.
+    writes: Mutex<()>,
+}
+
+/// Account and device names end up in paths, so we're rather strict about them.
+fn validate_name(name: &str) -> Result<(), Error> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(Error::InvalidName(name.into()));
+    }
+
+    Ok(())
+}
+
+fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Reads the `altered_at` timestamp of a payload without needing to understand the rest of it.
+fn altered_at(payload: &Value) -> Option<DateTime<Utc>> {
+    payload
+        .get("altered_at")
+        .and_then(|v| v.as_str())
+        .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Writes `contents` to a temporary file first, so that readers never see a half-written payload.
+///
+/// Every write gets its own temporary file, so that concurrent writes of the same payload don't interfere. The last rename wins.
+async fn write_atomically(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let tmp_path = path.with_extension(format!("json.{:016x}.tmp", rand::random::<u64>()));
+    fs::write(&tmp_path, contents).await?;
+    if let Err(e) = fs::rename(&tmp_path, path).await {
+        let _ = fs::remove_file(&tmp_path).await;
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+impl Storage {
+    pub fn open<P: AsRef<Path>>(data_dir: P) -> Result<Self, Error> {
+        let data_dir = data_dir.as_ref().to_path_buf();
+        std::fs::create_dir_all(&data_dir)?;
+
+        let tokens = match std::fs::read(data_dir.join(TOKENS_FILE_NAME)) {
+            Ok(contents) => serde_json::from_slice(&contents)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(Self {
+            data_dir,
+            tokens: RwLock::new(tokens),
+            writes: Mutex::new(()),
+        })
+    }
+
+    /// Creates a new account and returns the access token for it.
+    pub fn add_account(&self, account: &str) -> Result<String, Error> {
+        validate_name(account)?;
+
+        let mut tokens = self.tokens.write().unwrap();
+        if tokens.values().any(|a| a == account) {
+            return Err(Error::AccountExists(account.into()));
+        }
+
+        let mut bytes = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut bytes);
+        let token = hex::encode(bytes);
+
+        tokens.insert(hash_token(&token), account.into());
+        std::fs::write(
+            self.data_dir.join(TOKENS_FILE_NAME),
+            serde_json::to_vec_pretty(&*tokens)?,
+        )?;
+
+        Ok(token)
+    }
+
+    pub fn account_for_token(&self, token: &str) -> Option<String> {
+        self.tokens.read().unwrap().get(&hash_token(token)).cloned()
+    }
+
+    fn account_dir(&self, account: &str) -> PathBuf {
+        self.data_dir.join("accounts").join(account)
+    }
+
+    fn devices_dir(&self, account: &str) -> PathBuf {
+        self.account_dir(account).join("devices")
+    }
+
+    /// The number of writes to the account's payloads so far.
+    pub async fn revision(&self, account: &str) -> Result<u64, Error> {
+        match fs::read_to_string(self.account_dir(account).join(REVISION_FILE_NAME)).await {
+            Ok(contents) => Ok(serde_json::from_str(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn device_file(&self, account: &str, device: &str) -> Result<PathBuf, Error> {
+        validate_name(device)?;
+        Ok(self.devices_dir(account).join(format!("{}.json", device)))
+    }
+
+    pub async fn device_payload(&self, account: &str, device: &str) -> Result<Value, Error> {
+        match fs::read(self.device_file(account, device)?).await {
+            Ok(contents) => Ok(serde_json::from_slice(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Stores the payload of a device, if the account's payloads still are as described by `precondition`.
+    ///
+    /// # Returns
+    /// The account's new revision.
+    pub async fn put_device_payload(
+        &self,
+        account: &str,
+        device: &str,
+        payload: Value,
+        precondition: Precondition,
+    ) -> Result<u64, Error> {
+        if altered_at(&payload).is_none() || payload.get("installations").is_none() {
+            return Err(Error::InvalidPayload(
+                "expected a FlatSync payload with `installations` and `altered_at`".into(),
+            ));
+        }
+        let path = self.device_file(account, device)?;
+
+        let _write = self.writes.lock().await;
+        let revision = self.revision(account).await?;
+        let fulfilled = match precondition {
+            Precondition::Revision(expected) => expected == revision,
+            Precondition::NoPayload => self.devices(account).await?.is_empty(),
+        };
+        if !fulfilled {
+            return Err(Error::Conflict);
+        }
+
+        fs::create_dir_all(self.devices_dir(account)).await?;
+        write_atomically(&path, &serde_json::to_vec(&payload)?).await?;
+        write_atomically(
+            &self.account_dir(account).join(REVISION_FILE_NAME),
+            (revision + 1).to_string().as_bytes(),
+        )
+        .await?;
+
+        Ok(revision + 1)
+    }
+
+    pub async fn delete_device(&self, account: &str, device: &str) -> Result<(), Error> {
+        match fs::remove_file(self.device_file(account, device)?).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Err(Error::NotFound),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub async fn devices(&self, account: &str) -> Result<Vec<DeviceInfo>, Error> {
+        let mut entries = match fs::read_dir(self.devices_dir(account)).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut devices = vec![];
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+            let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+                continue;
+            };
+
+            let payload = self.device_payload(account, name).await?;
+            devices.push(DeviceInfo {
+                name: name.into(),
+                altered_at: altered_at(&payload),
+            });
+        }
+
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(devices)
+    }
+
+    /// The most recently altered payload stored by any of the account's devices, along with the account's revision.
+    pub async fn latest_payload(&self, account: &str) -> Result<(Value, u64), Error> {
+        let _write = self.writes.lock().await;
+        let latest = self
+            .devices(account)
+            .await?
+            .into_iter()
+            .max_by_key(|device| device.altered_at)
+            .ok_or(Error::NotFound)?;
+
+        let payload = self.device_payload(account, &latest.name).await?;
+        Ok((payload, self.revision(account).await?))
+    }
+}
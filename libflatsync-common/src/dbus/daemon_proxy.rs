@@ -1,3 +1,4 @@
+use super::{DeviceInfo, PendingChange, ProfileInfo, SinkStatus, SyncConflict, SyncStep};
 use zbus::{dbus_proxy, Result};
 
 /// This is the D-Bus interface of the daemon, which
@@ -13,6 +14,55 @@ pub trait Daemon {
     async fn create_gist(&self) -> Result<String>;
     async fn post_gist(&self) -> Result<()>;
     async fn set_gist_id(&self, id: &str) -> Result<()>;
+    async fn join_gist(&self, id: &str, strategy: &str) -> Result<()>;
+    async fn sink_provider(&self) -> Result<String>;
+    async fn set_sink_provider(&self, provider: &str) -> Result<()>;
+    async fn migrate_sink(&self, provider: &str, old_sink: &str, force: bool) -> Result<String>;
+    async fn mirror_sink_providers(&self) -> Result<Vec<String>>;
+    async fn add_mirror_sink(&self, provider: &str) -> Result<()>;
+    async fn set_mirror_secret(&self, provider: &str, secret: &str) -> Result<()>;
+    async fn set_mirror_id(&self, provider: &str, id: &str) -> Result<()>;
+    async fn remove_mirror_sink(&self, provider: &str) -> Result<()>;
+    async fn sink_health(&self) -> Result<Vec<SinkStatus>>;
+    async fn start_lan_pairing(&self) -> Result<String>;
+    async fn pair_lan_device(&self, code: &str) -> Result<String>;
+    async fn lan_peers(&self) -> Result<Vec<String>>;
+    async fn unpair_lan_device(&self, name: &str) -> Result<()>;
+    async fn get_sync_plan(&self) -> Result<Vec<SyncStep>>;
+    async fn sync_conflicts(&self) -> Result<Vec<SyncConflict>>;
+    async fn devices(&self) -> Result<Vec<DeviceInfo>>;
+    async fn device_name(&self) -> Result<String>;
+    async fn set_device_name(&self, name: &str) -> Result<()>;
+    async fn profiles(&self) -> Result<Vec<ProfileInfo>>;
+    async fn add_to_profile(&self, profile: &str, ref_: &str) -> Result<()>;
+    async fn remove_from_profile(&self, profile: &str, ref_: &str) -> Result<()>;
+    async fn delete_profile(&self, profile: &str) -> Result<()>;
+    async fn subscribed_profiles(&self) -> Result<Vec<String>>;
+    async fn set_subscribed_profiles(&self, profiles: &[&str]) -> Result<()>;
+    async fn filter_rules(&self) -> Result<Vec<String>>;
+    async fn set_filter_rules(&self, rules: &[&str]) -> Result<()>;
+    async fn sync_filter_rules(&self) -> Result<bool>;
+    async fn set_sync_filter_rules(&self, sync: bool) -> Result<()>;
+    async fn version_policy(&self) -> Result<String>;
+    async fn set_version_policy(&self, policy: &str) -> Result<()>;
+    async fn version_pins(&self) -> Result<Vec<(String, String, String)>>;
+    async fn pin_version(&self, ref_: &str, policy: &str, commit: &str) -> Result<()>;
+    async fn unpin_version(&self, ref_: &str) -> Result<()>;
+    async fn propagate_updates(&self) -> Result<bool>;
+    async fn set_propagate_updates(&self, propagate: bool) -> Result<()>;
+    async fn remote_aliases(&self) -> Result<Vec<String>>;
+    async fn set_remote_aliases(&self, aliases: &[&str]) -> Result<()>;
+    async fn sync_mode(&self) -> Result<String>;
+    async fn set_sync_mode(&self, mode: &str) -> Result<()>;
+    async fn pending_changes(&self) -> Result<Vec<PendingChange>>;
+    async fn approve_pending(&self, ids: &[u32]) -> Result<u32>;
+    async fn approve_all_pending(&self) -> Result<u32>;
+    async fn reject_pending(&self, ids: &[u32]) -> Result<u32>;
+    async fn reject_all_pending(&self) -> Result<u32>;
+    async fn uninstall_limits(&self) -> Result<(u32, u32)>;
+    async fn set_uninstall_limits(&self, max_count: u32, max_percentage: u32) -> Result<()>;
+    async fn held_uninstalls(&self) -> Result<Vec<SyncStep>>;
+    async fn confirm_uninstalls(&self) -> Result<bool>;
     async fn sync_now(&self) -> Result<()>;
     async fn autosync(&self) -> Result<bool>;
     async fn set_autosync(&self, autosync: bool) -> Result<()>;
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+/// A device syncing through the same payload, as reported over D-Bus.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct DeviceInfo {
+    /// Stable ID of the device.
+    pub id: String,
+    /// Name chosen by the user, defaults to the hostname.
+    pub name: String,
+    pub hostname: String,
+    /// The device's architecture, e.g. `x86_64`.
+    pub arch: String,
+    /// Unix timestamp of when the device last wrote the payload.
+    pub last_seen: i64,
+    /// The refs installed on the device, each together with the installation it's in, e.g. `user`.
+    pub refs: Vec<(String, String)>,
+    /// Whether this is the device the daemon runs on.
+    pub this_device: bool,
+}
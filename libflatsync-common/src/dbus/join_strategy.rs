@@ -0,0 +1,31 @@
+use clap::ValueEnum;
+
+/// How a device reconciles its installations with a FlatSync list that already exists when it starts syncing with it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum JoinStrategy {
+    /// Install everything from the list, but keep the refs only installed here to this device.
+    AdoptRemote,
+    /// Combine the list with the refs installed here.
+    Merge,
+    /// Replace the list with the refs installed here.
+    OverwriteRemote,
+}
+
+impl JoinStrategy {
+    /// The name of the strategy, as passed over D-Bus and accepted by the CLI.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::AdoptRemote => "adopt-remote",
+            Self::Merge => "merge",
+            Self::OverwriteRemote => "overwrite-remote",
+        }
+    }
+
+    /// Looks up a strategy by the name returned from [`JoinStrategy::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .find(|strategy| strategy.name() == name)
+            .copied()
+    }
+}
@@ -1,3 +1,21 @@
 pub mod daemon_proxy;
+pub mod device_info;
+pub mod join_strategy;
+pub mod old_sink_action;
+pub mod pending_change;
+pub mod profile_info;
+pub mod sink_status;
+pub mod sync_conflict;
+pub mod sync_mode;
+pub mod sync_step;
 
 pub use daemon_proxy::DaemonProxy;
+pub use device_info::DeviceInfo;
+pub use join_strategy::JoinStrategy;
+pub use old_sink_action::OldSinkAction;
+pub use pending_change::PendingChange;
+pub use profile_info::ProfileInfo;
+pub use sink_status::SinkStatus;
+pub use sync_conflict::SyncConflict;
+pub use sync_mode::SyncMode;
+pub use sync_step::SyncStep;
@@ -0,0 +1,31 @@
+use clap::ValueEnum;
+
+/// What happens to the previously active data sink after migrating to another one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OldSinkAction {
+    /// Leave the old sink configured, so that it can be switched back to.
+    Keep,
+    /// Leave the payload on the old sink, but forget about it on this device after storing a copy of it locally.
+    Archive,
+    /// Delete the payload from the old sink.
+    Delete,
+}
+
+impl OldSinkAction {
+    /// The name of the action, as passed over D-Bus and accepted by the CLI.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Keep => "keep",
+            Self::Archive => "archive",
+            Self::Delete => "delete",
+        }
+    }
+
+    /// Looks up an action by the name returned from [`OldSinkAction::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .find(|action| action.name() == name)
+            .copied()
+    }
+}
@@ -0,0 +1,13 @@
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+/// A change from another device waiting for the user's approval, as reported over D-Bus.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct PendingChange {
+    /// ID to approve or reject the change with.
+    pub id: u32,
+    /// The step the change consists of.
+    pub step: super::SyncStep,
+    /// Unix timestamp of when the change was first held back.
+    pub queued_at: i64,
+}
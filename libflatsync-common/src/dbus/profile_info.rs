@@ -0,0 +1,12 @@
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+/// A profile stored in the payload, as reported over D-Bus.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct ProfileInfo {
+    pub name: String,
+    /// Full refs or bare IDs, see `Profile`.
+    pub refs: Vec<String>,
+    /// Whether this device subscribed to the profile.
+    pub subscribed: bool,
+}
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+/// The health of one of the daemon's data sinks, as reported over D-Bus.
+#[derive(Debug, Clone, Serialize, Deserialize, Type)]
+pub struct SinkStatus {
+    /// Name of the provider backing the sink, e.g. `github`.
+    pub provider: String,
+    /// The sink's ID, empty if it hasn't been set up yet.
+    pub sink_id: String,
+    /// Whether this is the sink we fetch from first.
+    pub primary: bool,
+    /// Whether the last operation on the sink succeeded.
+    pub healthy: bool,
+    /// Unix timestamp of the last successful operation, `0` if there hasn't been one yet.
+    pub last_success: i64,
+    /// Error message of the last failed operation, empty if the last operation succeeded.
+    pub last_error: String,
+}
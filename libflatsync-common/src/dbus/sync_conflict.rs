@@ -0,0 +1,17 @@
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+/// A change made on this device that contradicts one made on another device since the last sync, as reported over D-Bus.
+///
+/// Until the user resolves it, the daemon keeps what is currently installed on this device.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct SyncConflict {
+    /// The installation the conflict occurred in, e.g. `user`.
+    pub installation: String,
+    /// Either `ref` or `remote`.
+    pub kind: String,
+    /// The ref (e.g. `app/org.gnome.Maps/x86_64/stable`) or remote name in question.
+    pub name: String,
+    /// What happened on both sides.
+    pub reason: String,
+}
@@ -0,0 +1,31 @@
+use clap::ValueEnum;
+
+/// Whether changes coming from other devices are applied right away or wait for the user's approval.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SyncMode {
+    /// Apply all changes right away.
+    Automatic,
+    /// Add and modify remotes and install refs right away, but hold uninstallations and removals of remotes until they are approved.
+    ApproveRemovals,
+    /// Hold all changes until they are approved.
+    ApproveAll,
+}
+
+impl SyncMode {
+    /// The name of the mode, as stored in the settings, passed over D-Bus and accepted by the CLI.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Automatic => "automatic",
+            Self::ApproveRemovals => "approve-removals",
+            Self::ApproveAll => "approve-all",
+        }
+    }
+
+    /// Looks up a mode by the name returned from [`SyncMode::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .find(|mode| mode.name() == name)
+            .copied()
+    }
+}
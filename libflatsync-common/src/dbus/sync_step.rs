@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+use zbus::zvariant::Type;
+
+/// A single change the next sync would make to this device, as reported over D-Bus.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Type)]
+pub struct SyncStep {
+    /// The installation the change is made to, e.g. `user`.
+    pub installation: String,
+    /// One of `add-remote`, `modify-remote`, `install`, `update`, `uninstall` or `remove-remote`,
+    /// or `skip` for a ref that can't be installed on this device.
+    pub action: String,
+    /// The remote name or the ref, e.g. `app/org.gnome.Maps/x86_64/stable`.
+    pub name: String,
+    /// The remote a ref is installed from, empty for remotes.
+    pub origin: String,
+    /// Why the change is made.
+    pub reason: String,
+}
@@ -12,4 +12,8 @@ pub enum Error {
     Io(#[from] std::io::Error),
     #[error("Error while dealing with reqwest and OAuth2: {0}")]
     OAuth2ReqwestFailure(String),
+    #[error("Invalid provider URL: {0}")]
+    InvalidProviderUrl(String),
+    #[error("Invalid filter rule {0}")]
+    InvalidFilterRule(String),
 }
@@ -0,0 +1,57 @@
+use crate::models::{FlatpakInstallationKind, FlatpakInstallationMap};
+use chrono::{DateTime, Utc};
+use std::collections::BTreeMap;
+
+/// Represents a device syncing its installations through the payload, so that we can tell which device has what.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Device {
+    /// Stable ID derived from the device's machine ID.
+    pub id: String,
+    /// Name chosen by the user, defaults to the hostname.
+    pub name: String,
+    pub hostname: String,
+    /// The device's default Flatpak architecture, e.g. `x86_64`.
+    pub arch: String,
+    /// When the device last wrote the payload.
+    pub last_seen: DateTime<Utc>,
+    /// The refs installed on the device, e.g. `app/org.gnome.Maps/x86_64/stable`, per installation.
+    pub refs: BTreeMap<FlatpakInstallationKind, Vec<String>>,
+}
+
+impl Device {
+    /// Creates the entry of a device with the given installations, last seen now.
+    pub fn new(
+        id: String,
+        name: String,
+        hostname: String,
+        arch: String,
+        installations: &FlatpakInstallationMap,
+    ) -> Self {
+        Self {
+            id,
+            name,
+            hostname,
+            arch,
+            last_seen: Utc::now(),
+            refs: installations
+                .0
+                .iter()
+                .map(|(kind, installation)| {
+                    (
+                        *kind,
+                        installation.refs.iter().map(|r| r.ref_.clone()).collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Whether both entries describe the same state of a device, apart from when it was last seen.
+    pub fn same_state(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.name == other.name
+            && self.hostname == other.hostname
+            && self.arch == other.arch
+            && self.refs == other.refs
+    }
+}
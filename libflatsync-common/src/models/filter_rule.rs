@@ -0,0 +1,299 @@
+use crate::{
+    error::Error,
+    models::{FlatpakInstallationKind, FlatpakInstallationPayload, FlatpakRef, FlatpakRefKind},
+};
+use std::{fmt, str::FromStr};
+
+/// Whether refs matched by a `FilterRule` are synced or not.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FilterAction {
+    Include,
+    Exclude,
+}
+
+/// A rule selecting refs to sync or to keep to this device, like drivers or test builds.
+///
+/// Every condition that is set has to match. Rules are written as the action followed by the conditions, e.g.
+/// `exclude id=org.freedesktop.Platform.GL.* kind=runtime` or `include installation=user`.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FilterRule {
+    pub action: FilterAction,
+    /// Glob on the ref's ID, supporting `*` and `?`.
+    pub id: Option<String>,
+    pub kind: Option<FlatpakRefKind>,
+    /// Name of the remote the ref was installed from.
+    pub origin: Option<String>,
+    pub branch: Option<String>,
+    pub arch: Option<String>,
+    pub installation: Option<FlatpakInstallationKind>,
+}
+
+impl FilterRule {
+    pub fn matches(&self, installation: FlatpakInstallationKind, ref_: &FlatpakRef) -> bool {
+        self.id.as_ref().map_or(true, |id| glob_match(id, &ref_.id))
+            && self.kind.map_or(true, |kind| kind == ref_.kind)
+            && self.origin.as_ref().map_or(true, |o| *o == ref_.origin)
+            && self.branch.as_ref().map_or(true, |b| *b == ref_.branch)
+            && self.arch.as_ref().map_or(true, |a| *a == ref_.arch)
+            && self.installation.map_or(true, |i| i == installation)
+    }
+}
+
+impl FromStr for FilterRule {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| Error::InvalidFilterRule(format!("'{}': {}", s, reason));
+
+        let mut words = s.split_whitespace();
+        let action = match words.next() {
+            Some("include") => FilterAction::Include,
+            Some("exclude") => FilterAction::Exclude,
+            _ => return Err(invalid("expected \"include\" or \"exclude\"")),
+        };
+
+        let mut rule = Self {
+            action,
+            id: None,
+            kind: None,
+            origin: None,
+            branch: None,
+            arch: None,
+            installation: None,
+        };
+
+        for condition in words {
+            let (key, value) = condition
+                .split_once('=')
+                .ok_or_else(|| invalid("expected conditions like \"key=value\""))?;
+
+            match key {
+                "id" => rule.id = Some(value.into()),
+                "kind" => {
+                    rule.kind = Some(match value {
+                        "app" => FlatpakRefKind::App,
+                        "runtime" => FlatpakRefKind::Runtime,
+                        _ => return Err(invalid("kind must be \"app\" or \"runtime\"")),
+                    })
+                }
+                "origin" => rule.origin = Some(value.into()),
+                "branch" => rule.branch = Some(value.into()),
+                "arch" => rule.arch = Some(value.into()),
+                "installation" => {
+                    rule.installation = Some(match value {
+                        "user" => FlatpakInstallationKind::User,
+                        "system" | "default" => FlatpakInstallationKind::System,
+                        _ => return Err(invalid("installation must be \"user\" or \"system\"")),
+                    })
+                }
+                _ => return Err(invalid(&format!("unknown condition \"{}\"", key))),
+            }
+        }
+
+        Ok(rule)
+    }
+}
+
+impl fmt::Display for FilterRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            FilterAction::Include => write!(f, "include")?,
+            FilterAction::Exclude => write!(f, "exclude")?,
+        }
+        if let Some(id) = &self.id {
+            write!(f, " id={}", id)?;
+        }
+        if let Some(kind) = self.kind {
+            match kind {
+                FlatpakRefKind::App => write!(f, " kind=app")?,
+                FlatpakRefKind::Runtime => write!(f, " kind=runtime")?,
+            }
+        }
+        if let Some(origin) = &self.origin {
+            write!(f, " origin={}", origin)?;
+        }
+        if let Some(branch) = &self.branch {
+            write!(f, " branch={}", branch)?;
+        }
+        if let Some(arch) = &self.arch {
+            write!(f, " arch={}", arch)?;
+        }
+        match self.installation {
+            Some(FlatpakInstallationKind::User) => write!(f, " installation=user")?,
+            Some(FlatpakInstallationKind::System) => write!(f, " installation=system")?,
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+/// A set of `FilterRule`s deciding which refs are synced.
+///
+/// If there are include rules, a ref has to match at least one of them. Exclude rules take precedence, so a ref matching
+/// any of them is never synced, even if it is included.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct FilterRules(pub Vec<FilterRule>);
+
+impl FilterRules {
+    /// Parses rules in their textual form, e.g. as stored in the settings.
+    pub fn parse<S: AsRef<str>>(rules: &[S]) -> Result<Self, Error> {
+        rules
+            .iter()
+            .map(|rule| rule.as_ref().parse())
+            .collect::<Result<_, _>>()
+            .map(Self)
+    }
+
+    pub fn allows(&self, installation: FlatpakInstallationKind, ref_: &FlatpakRef) -> bool {
+        let matches = |action| {
+            self.0
+                .iter()
+                .any(|rule| rule.action == action && rule.matches(installation, ref_))
+        };
+
+        let has_includes = self.0.iter().any(|r| r.action == FilterAction::Include);
+        let included = !has_includes || matches(FilterAction::Include);
+
+        included && !matches(FilterAction::Exclude)
+    }
+
+    /// Removes the refs these rules don't allow from the payload.
+    pub fn apply(&self, payload: &mut FlatpakInstallationPayload) {
+        for (kind, installation) in payload.installations.0.iter_mut() {
+            installation.refs.retain(|ref_| self.allows(*kind, ref_));
+        }
+    }
+
+    pub fn to_strings(&self) -> Vec<String> {
+        self.0.iter().map(|rule| rule.to_string()).collect()
+    }
+}
+
+/// Matches `text` against a glob `pattern`, in which `*` matches any sequence of characters and `?` any single one.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+
+    // Position after the last `*` seen, and the text position it currently swallows up to
+    let mut star = None;
+    let (mut p, mut t) = (0, 0);
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p + 1, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p;
+            t = star_t + 1;
+            star = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn runtime(id: &str, arch: &str) -> FlatpakRef {
+        FlatpakRef {
+            kind: FlatpakRefKind::Runtime,
+            ref_: format!("runtime/{}/{}/23.08", id, arch),
+            id: id.into(),
+            arch: arch.into(),
+            branch: "23.08".into(),
+            origin: "flathub".into(),
+            ..Default::default()
+        }
+    }
+
+    fn rules(rules: &[&str]) -> FilterRules {
+        FilterRules::parse(rules).unwrap()
+    }
+
+    #[test]
+    fn parses_rules_with_conditions() {
+        let rule: FilterRule =
+            "exclude id=org.freedesktop.Platform.GL.* kind=runtime installation=default"
+                .parse()
+                .unwrap();
+
+        assert_eq!(rule.action, FilterAction::Exclude);
+        assert_eq!(rule.id.as_deref(), Some("org.freedesktop.Platform.GL.*"));
+        assert_eq!(rule.kind, Some(FlatpakRefKind::Runtime));
+        assert_eq!(rule.installation, Some(FlatpakInstallationKind::System));
+        assert_eq!(rule.origin, None);
+        assert_eq!(
+            rule.to_string(),
+            "exclude id=org.freedesktop.Platform.GL.* kind=runtime installation=system"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        for rule in [
+            "",
+            "ignore id=org.gnome.Maps",
+            "include org.gnome.Maps",
+            "include kind=extension",
+            "include installation=portable",
+            "include version=46",
+        ] {
+            assert!(rule.parse::<FilterRule>().is_err(), "{}", rule);
+        }
+    }
+
+    #[test]
+    fn allows_everything_without_rules() {
+        let gl = runtime("org.freedesktop.Platform.GL.nvidia", "x86_64");
+        assert!(FilterRules::default().allows(FlatpakInstallationKind::User, &gl));
+    }
+
+    #[test]
+    fn includes_only_matching_refs() {
+        let rules = rules(&["include installation=user", "include arch=aarch64"]);
+
+        let platform = runtime("org.freedesktop.Platform", "x86_64");
+        assert!(rules.allows(FlatpakInstallationKind::User, &platform));
+        assert!(!rules.allows(FlatpakInstallationKind::System, &platform));
+
+        let platform = runtime("org.freedesktop.Platform", "aarch64");
+        assert!(rules.allows(FlatpakInstallationKind::System, &platform));
+    }
+
+    #[test]
+    fn excludes_take_precedence() {
+        let rules = rules(&[
+            "include kind=runtime",
+            "exclude id=org.freedesktop.Platform.GL.* origin=flathub",
+        ]);
+
+        let gl = runtime("org.freedesktop.Platform.GL.nvidia", "x86_64");
+        assert!(!rules.allows(FlatpakInstallationKind::System, &gl));
+
+        let platform = runtime("org.freedesktop.Platform", "x86_64");
+        assert!(rules.allows(FlatpakInstallationKind::System, &platform));
+    }
+
+    #[test]
+    fn matches_globs() {
+        assert!(glob_match("org.gnome.*", "org.gnome.Maps"));
+        assert!(glob_match("org.gnome.*", "org.gnome."));
+        assert!(glob_match("*.Devel", "org.gnome.Maps.Devel"));
+        assert!(glob_match("org.*.Maps", "org.gnome.Maps"));
+        assert!(glob_match("org.gnome.Map?", "org.gnome.Maps"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("org.gnome.Maps", "org.gnome.Maps"));
+
+        assert!(!glob_match("org.gnome.Map?", "org.gnome.Map"));
+        assert!(!glob_match("org.gnome.*", "org.kde.kate"));
+        assert!(!glob_match("*.Devel", "org.gnome.Maps"));
+        assert!(!glob_match("org.gnome.Maps", "org.gnome.Maps.Devel"));
+    }
+}
@@ -1,16 +1,33 @@
 use crate::{
     error::Error,
-    models::{FlatpakInstallation, FlatpakInstallationKind, FlatpakInstallationMap},
+    models::{
+        Device, FlatpakInstallation, FlatpakInstallationKind, FlatpakInstallationMap, FlatpakRef,
+        Profile, VersionPin,
+    },
 };
 use chrono::{DateTime, Utc};
 use log::trace;
-use std::path::Path;
+use std::{collections::BTreeMap, path::Path};
 
 /// Represents a payload containing a list of Flatpak installations. This is either stored locally or sent to the gists server.
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct FlatpakInstallationPayload {
     pub installations: FlatpakInstallationMap,
     pub altered_at: DateTime<Utc>,
+    /// The devices syncing through this payload, keyed by their ID.
+    /// Payloads written by older versions don't have any.
+    #[serde(default)]
+    pub devices: BTreeMap<String, Device>,
+    /// Named sets of refs devices can subscribe to, so that they only sync a part of the installations.
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Profile>,
+    /// Filter rules shared by the devices which opted into syncing them, `None` if none of them published theirs yet.
+    #[serde(default)]
+    pub filter_rules: Option<Vec<String>>,
+    /// Version policies of single refs, keyed by their full ref and applying to the ref on all architectures.
+    /// Refs without one are installed according to each device's own policy.
+    #[serde(default)]
+    pub pins: BTreeMap<String, VersionPin>,
 }
 
 impl FlatpakInstallationPayload {
@@ -21,6 +38,10 @@ impl FlatpakInstallationPayload {
         Ok(Self {
             installations,
             altered_at,
+            devices: BTreeMap::new(),
+            profiles: BTreeMap::new(),
+            filter_rules: None,
+            pins: BTreeMap::new(),
         })
     }
 
@@ -71,4 +92,20 @@ impl FlatpakInstallationPayload {
     pub fn installations(&self, kind: FlatpakInstallationKind) -> Option<&FlatpakInstallation> {
         self.installations.get(kind)
     }
+
+    /// Whether a ref is part of any of the given profiles.
+    ///
+    /// Without any subscribed profiles, every ref is.
+    pub fn in_profiles(&self, subscribed: &[String], ref_: &FlatpakRef) -> bool {
+        subscribed.is_empty()
+            || subscribed
+                .iter()
+                .filter_map(|name| self.profiles.get(name))
+                .any(|profile| profile.contains(ref_))
+    }
+
+    /// Adds the given device's entry, replacing its previous one.
+    pub fn register_device(&mut self, device: Device) {
+        self.devices.insert(device.id.clone(), device);
+    }
 }
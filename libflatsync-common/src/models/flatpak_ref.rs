@@ -30,6 +30,39 @@ pub struct FlatpakRef {
     pub oars: Option<String>,
 }
 
+impl FlatpakRef {
+    /// The parts of a full ref like `app/org.gnome.Maps/x86_64/stable` which identify it on devices of any architecture,
+    /// e.g. `app/org.gnome.Maps/stable`. Returns `None` if the given string isn't a full ref.
+    pub fn sync_key_of(ref_: &str) -> Option<String> {
+        let mut parts = ref_.split('/');
+        let (kind, id, _arch, branch) =
+            (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+        Some(format!("{}/{}/{}", kind, id, branch))
+    }
+
+    /// Identifies the ref across devices regardless of their architecture, by its kind, ID and branch.
+    pub fn sync_key(&self) -> String {
+        Self::sync_key_of(&self.ref_).unwrap_or_else(|| self.ref_.clone())
+    }
+
+    /// The same ref for the given architecture.
+    ///
+    /// The commit is cleared, as commits are specific to each architecture.
+    pub fn for_arch(&self, arch: &str) -> Self {
+        let mut ref_ = self.clone();
+        ref_.ref_ = format!(
+            "{}/{}/{}/{}",
+            self.ref_.split('/').next().unwrap_or_default(),
+            self.id,
+            arch,
+            self.branch
+        );
+        ref_.arch = arch.into();
+        ref_.commit.clear();
+        ref_
+    }
+}
+
 /// Converts a `libflatpak::InstalledRef` into a `FlatpakRef` struct.
 ///
 /// # Arguments
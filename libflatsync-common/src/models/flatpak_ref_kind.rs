@@ -38,3 +38,13 @@ impl From<libflatpak::RefKind> for FlatpakRefKind {
         }
     }
 }
+
+/// Converts a `FlatpakRefKind` into a `libflatpak::RefKind`.
+impl From<FlatpakRefKind> for libflatpak::RefKind {
+    fn from(value: FlatpakRefKind) -> Self {
+        match value {
+            FlatpakRefKind::App => Self::App,
+            FlatpakRefKind::Runtime => Self::Runtime,
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod device;
+pub mod filter_rule;
 pub mod flatpak_installation;
 pub mod flatpak_installation_kind;
 pub mod flatpak_installation_map;
@@ -7,7 +9,11 @@ pub mod flatpak_ref;
 pub mod flatpak_ref_kind;
 pub mod flatpak_remote;
 pub mod flatpak_remote_type;
+pub mod profile;
+pub mod version_policy;
 
+pub use device::*;
+pub use filter_rule::*;
 pub use flatpak_installation::*;
 pub use flatpak_installation_kind::*;
 pub use flatpak_installation_map::*;
@@ -17,3 +23,5 @@ pub use flatpak_ref::*;
 pub use flatpak_ref_kind::*;
 pub use flatpak_remote::*;
 pub use flatpak_remote_type::*;
+pub use profile::*;
+pub use version_policy::*;
@@ -0,0 +1,22 @@
+use crate::models::FlatpakRef;
+use std::collections::BTreeSet;
+
+/// Represents a named set of refs, like "work" or "gaming", which devices can subscribe to.
+#[derive(Debug, Default, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct Profile {
+    /// Either full refs like `app/org.gnome.Maps/x86_64/stable`, which match the ref on all arches, or bare IDs like `org.gnome.Maps`
+    /// which match all arches and branches.
+    pub refs: BTreeSet<String>,
+}
+
+impl Profile {
+    pub fn contains(&self, ref_: &FlatpakRef) -> bool {
+        let key = ref_.sync_key();
+
+        self.refs.contains(&ref_.id)
+            || self
+                .refs
+                .iter()
+                .any(|r| FlatpakRef::sync_key_of(r).is_some_and(|k| k == key))
+    }
+}
@@ -0,0 +1,42 @@
+use clap::ValueEnum;
+
+/// Which version of a ref a sync installs on a device it is missing on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum VersionPolicy {
+    /// Install the latest version known from the remote's cached metadata.
+    Latest,
+    /// Install the pinned commit, or the one installed on the device the ref was synced from.
+    ExactCommit,
+    /// Refresh the remote's metadata before installing its latest version, so that it isn't older than the one on the device
+    /// the ref was synced from.
+    NotOlderThanRemote,
+}
+
+impl VersionPolicy {
+    /// The name of the policy, as stored in the settings, passed over D-Bus and accepted by the CLI.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Latest => "latest",
+            Self::ExactCommit => "exact-commit",
+            Self::NotOlderThanRemote => "not-older-than-remote",
+        }
+    }
+
+    /// Looks up a policy by the name returned from [`VersionPolicy::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .find(|policy| policy.name() == name)
+            .copied()
+    }
+}
+
+/// The version policy of a single ref, overriding the one devices use otherwise.
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct VersionPin {
+    pub policy: VersionPolicy,
+    /// The commit to install with the `ExactCommit` policy. Without one, the commit installed on the device the ref was synced from is used.
+    #[serde(default)]
+    pub commit: Option<String>,
+}
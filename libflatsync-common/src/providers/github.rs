@@ -9,11 +9,13 @@ use oauth2::{
 use crate::Error;
 
 use super::oauth_client::{AccessTokenData, OauthClientDeviceFlow, TokenPair};
+use serde::{Deserialize, Serialize};
 
-static GH_CLIENT_ID: &str = "Iv1.1bf99f29c6b7d129";
-static GH_AUTH_URL: &str = "https://github.com/login/oauth/authorize";
-static GH_DEVICE_AUTH_URL: &str = "https://github.com/login/device/code";
-static GH_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+pub static GH_CLIENT_ID: &str = "Iv1.1bf99f29c6b7d129";
+pub static GH_API_URL: &str = "https://api.github.com";
+pub static GH_AUTH_URL: &str = "https://github.com/login/oauth/authorize";
+pub static GH_DEVICE_AUTH_URL: &str = "https://github.com/login/device/code";
+pub static GH_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
 
 // Specific to the behavior of GitHub Apps. We need the "Repository Metadata" permission to interact with the Gist API, and we only get that by installing the app the the user's account
 pub static GH_APP_INSTALLATION_URL: &str = "https://github.com/apps/flatsync/installations/new";
@@ -41,14 +43,71 @@ async fn custom_async_http_client(request: HttpRequest) -> Result<HttpResponse,
     }
 }
 
-pub fn get_github_basic_client() -> BasicClient {
-    BasicClient::new(
-        ClientId::new(GH_CLIENT_ID.into()),
+/// The API and OAuth endpoints as well as the OAuth application used to talk to GitHub.
+///
+/// These differ for GitHub Enterprise Server instances, which also need their own GitHub App.
+/// Fields missing when deserializing fall back to github.com.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GitHubConfig {
+    pub api_url: String,
+    pub auth_url: String,
+    pub device_auth_url: String,
+    pub token_url: String,
+    pub client_id: String,
+}
+
+impl Default for GitHubConfig {
+    fn default() -> Self {
+        Self {
+            api_url: GH_API_URL.into(),
+            auth_url: GH_AUTH_URL.into(),
+            device_auth_url: GH_DEVICE_AUTH_URL.into(),
+            token_url: GH_TOKEN_URL.into(),
+            client_id: GH_CLIENT_ID.into(),
+        }
+    }
+}
+
+impl GitHubConfig {
+    /// The endpoints of the GitHub Enterprise Server instance at `instance_url`, e.g. `https://github.example.com`.
+    pub fn for_instance(instance_url: &str, client_id: &str) -> Self {
+        let instance_url = instance_url.trim_end_matches('/');
+
+        Self {
+            api_url: format!("{}/api/v3", instance_url),
+            auth_url: format!("{}/login/oauth/authorize", instance_url),
+            device_auth_url: format!("{}/login/device/code", instance_url),
+            token_url: format!("{}/login/oauth/access_token", instance_url),
+            client_id: client_id.into(),
+        }
+    }
+}
+
+/// The tokens of a GitHub account together with the instance they were issued by.
+///
+/// This is what gets stored in the keyring, so every account keeps its own endpoints.
+/// Secrets only holding a [`TokenPair`] belong to github.com.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GitHubAccount {
+    #[serde(flatten)]
+    pub tokens: TokenPair,
+    #[serde(default)]
+    pub config: GitHubConfig,
+}
+
+pub fn get_github_basic_client(config: &GitHubConfig) -> Result<BasicClient, Error> {
+    let invalid_url = |e: oauth2::url::ParseError| Error::InvalidProviderUrl(e.to_string());
+
+    Ok(BasicClient::new(
+        ClientId::new(config.client_id.clone()),
         None,
-        AuthUrl::new(GH_AUTH_URL.into()).unwrap(),
-        Some(TokenUrl::new(GH_TOKEN_URL.into()).unwrap()),
+        AuthUrl::new(config.auth_url.clone()).map_err(invalid_url)?,
+        Some(TokenUrl::new(config.token_url.clone()).map_err(invalid_url)?),
     )
-    .set_device_authorization_url(DeviceAuthorizationUrl::new(GH_DEVICE_AUTH_URL.into()).unwrap())
+    .set_device_authorization_url(
+        DeviceAuthorizationUrl::new(config.device_auth_url.clone()).map_err(invalid_url)?,
+    ))
 }
 
 pub struct GitHubProvider {
@@ -106,8 +165,39 @@ impl Default for GitHubProvider {
 
 impl GitHubProvider {
     pub fn new() -> Self {
-        Self {
-            client: get_github_basic_client(),
-        }
+        Self::with_config(&GitHubConfig::default()).unwrap()
+    }
+
+    pub fn with_config(config: &GitHubConfig) -> Result<Self, Error> {
+        Ok(Self {
+            client: get_github_basic_client(config)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accounts_without_endpoints_belong_to_github_com() {
+        let secret =
+            r#"{"access_token_data":{"token":"secret","expires_in":null},"refresh_token":null}"#;
+
+        let account: GitHubAccount = serde_json::from_str(secret).unwrap();
+        assert_eq!(account.config, GitHubConfig::default());
+    }
+
+    #[test]
+    fn accounts_keep_their_endpoints() {
+        let secret =
+            r#"{"access_token_data":{"token":"secret","expires_in":null},"refresh_token":null}"#;
+        let mut account: GitHubAccount = serde_json::from_str(secret).unwrap();
+        account.config = GitHubConfig::for_instance("https://github.example.com/", "Iv1.example");
+
+        let account: GitHubAccount =
+            serde_json::from_str(&serde_json::to_string(&account).unwrap()).unwrap();
+        assert_eq!(account.config.api_url, "https://github.example.com/api/v3");
+        assert_eq!(account.config.client_id, "Iv1.example");
     }
 }
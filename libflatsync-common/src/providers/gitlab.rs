@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use log::error;
+use oauth2::{
+    basic::BasicClient, reqwest::async_http_client, AuthUrl, ClientId, DeviceAuthorizationUrl,
+    Scope, StandardDeviceAuthorizationResponse, TokenResponse, TokenUrl,
+};
+
+use crate::Error;
+
+use super::oauth_client::{AccessTokenData, OauthClientDeviceFlow, TokenPair};
+
+pub static GITLAB_DEFAULT_INSTANCE_URL: &str = "https://gitlab.com";
+// We need the `api` scope, as the snippet endpoints aren't covered by any narrower one
+static GITLAB_SCOPE: &str = "api";
+
+/// Builds the OAuth client for the GitLab instance at `instance_url`.
+///
+/// Unlike GitHub, every GitLab instance needs its own OAuth application, so the client ID has to be supplied by the user.
+pub fn get_gitlab_basic_client(instance_url: &str, client_id: &str) -> Result<BasicClient, Error> {
+    let instance_url = instance_url.trim_end_matches('/');
+    let invalid_url = |e: oauth2::url::ParseError| Error::InvalidProviderUrl(e.to_string());
+
+    Ok(BasicClient::new(
+        ClientId::new(client_id.into()),
+        None,
+        AuthUrl::new(format!("{}/oauth/authorize", instance_url)).map_err(invalid_url)?,
+        Some(TokenUrl::new(format!("{}/oauth/token", instance_url)).map_err(invalid_url)?),
+    )
+    .set_device_authorization_url(
+        DeviceAuthorizationUrl::new(format!("{}/oauth/authorize_device", instance_url))
+            .map_err(invalid_url)?,
+    ))
+}
+
+pub struct GitLabProvider {
+    client: BasicClient,
+}
+
+#[async_trait]
+impl OauthClientDeviceFlow for GitLabProvider {
+    async fn device_code(&self) -> Result<StandardDeviceAuthorizationResponse, Error> {
+        let req = self
+            .client
+            .exchange_device_code()
+            .map_err(|e| Error::OAuth2ReqwestFailure(e.to_string()))?
+            .add_scope(Scope::new(GITLAB_SCOPE.into()));
+
+        req.request_async(async_http_client)
+            .await
+            .map_err(|e| Error::OAuth2ReqwestFailure(e.to_string()))
+    }
+
+    async fn register_device(
+        &self,
+        device_auth_res: StandardDeviceAuthorizationResponse,
+    ) -> Result<TokenPair, Error> {
+        // GitLab follows RFC 8628 when responding with pending authorizations, so there's no need for a custom HTTP client here
+        let token = self
+            .client
+            .exchange_device_access_token(&device_auth_res)
+            .request_async(async_http_client, tokio::time::sleep, None)
+            .await;
+
+        let token = match token {
+            Ok(t) => t,
+            Err(e) => {
+                error!(
+                    "Error when trying to authenticate against GitLab's API: {:?}",
+                    e
+                );
+                return Err(Error::OAuth2ReqwestFailure(e.to_string()));
+            }
+        };
+
+        let token_pair = TokenPair {
+            access_token_data: AccessTokenData {
+                token: token.access_token().clone(),
+                expires_in: token
+                    .expires_in()
+                    .map(|d| chrono::Utc::now() + chrono::Duration::seconds(d.as_secs() as i64)),
+            },
+            refresh_token: token.refresh_token().cloned(),
+        };
+
+        Ok(token_pair)
+    }
+}
+
+impl GitLabProvider {
+    pub fn new(instance_url: &str, client_id: &str) -> Result<Self, Error> {
+        Ok(Self {
+            client: get_gitlab_basic_client(instance_url, client_id)?,
+        })
+    }
+}
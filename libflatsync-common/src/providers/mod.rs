@@ -1,3 +1,4 @@
 pub mod github;
+pub mod gitlab;
 pub mod oauth_client;
 pub mod providers_list;
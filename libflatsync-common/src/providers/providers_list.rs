@@ -1,6 +1,58 @@
 use clap::ValueEnum;
 
-#[derive(Debug, Clone, ValueEnum)]
+/// The data sink backends FlatSync can sync against.
+///
+/// This is the list the daemon's sink registry is driven from, so every variant is selectable
+/// through the `sink-provider` setting as well as `flatsync-cli init --provider`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum Providers {
     Github,
+    LocalDirectory,
+    Git,
+    Gitlab,
+    Webdav,
+    S3,
+    FlatsyncServer,
+    Forgejo,
+    Lan,
+}
+
+impl Providers {
+    /// The name of the provider, as stored in the `sink-provider` setting and accepted by the CLI.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Github => "github",
+            Self::LocalDirectory => "local-directory",
+            Self::Git => "git",
+            Self::Gitlab => "gitlab",
+            Self::Webdav => "webdav",
+            Self::S3 => "s3",
+            Self::FlatsyncServer => "flatsync-server",
+            Self::Forgejo => "forgejo",
+            Self::Lan => "lan",
+        }
+    }
+
+    /// The name of the data sink backing this provider, used for its settings keys and keyring entries.
+    pub fn sink_name(&self) -> &'static str {
+        match self {
+            Self::Github => "github-gists",
+            Self::LocalDirectory => "local-directory",
+            Self::Git => "git-repository",
+            Self::Gitlab => "gitlab-snippets",
+            Self::Webdav => "webdav",
+            Self::S3 => "s3",
+            Self::FlatsyncServer => "flatsync-server",
+            Self::Forgejo => "forgejo",
+            Self::Lan => "lan",
+        }
+    }
+
+    /// Looks up a provider by the name returned from [`Providers::name`].
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::value_variants()
+            .iter()
+            .find(|provider| provider.name() == name)
+            .copied()
+    }
 }
@@ -7,7 +7,7 @@ use gtk::{
     glib::{self, clone, Boxed, Properties},
 };
 use libflatsync_common::config::{APP_ID, PROFILE};
-use libflatsync_common::dbus::DaemonProxy;
+use libflatsync_common::dbus::{DaemonProxy, JoinStrategy};
 use log::error;
 
 #[derive(Boxed, Clone, Debug)]
@@ -28,6 +28,8 @@ mod imp {
         #[template_child]
         pub github_id_entry: TemplateChild<adw::EntryRow>,
         #[template_child]
+        pub join_strategy_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
         pub autosync_switch: TemplateChild<adw::SwitchRow>,
         #[template_child]
         pub autosync_timer_spin: TemplateChild<adw::SpinRow>,
@@ -43,6 +45,7 @@ mod imp {
             Self {
                 github_token_entry: TemplateChild::default(),
                 github_id_entry: TemplateChild::default(),
+                join_strategy_row: TemplateChild::default(),
                 autosync_switch: TemplateChild::default(),
                 autosync_timer_spin: TemplateChild::default(),
                 autosync_timer_adjustment: TemplateChild::default(),
@@ -113,8 +116,9 @@ impl FlatsyncPreferencesWindow {
             .connect_apply(clone!(@weak self as obj => move |entry| {
                 let ctx = MainContext::default();
                 let text = entry.text();
+                let strategy = obj.join_strategy();
                 ctx.spawn_local(clone!(@weak obj => async move {
-                    if let Err(e) = obj.proxy().set_gist_id(text.as_str()).await {
+                    if let Err(e) = obj.proxy().join_gist(text.as_str(), strategy.name()).await {
                         error!("{e}");
                     }
                 }));
@@ -131,6 +135,15 @@ impl FlatsyncPreferencesWindow {
             }));
     }
 
+    /// The strategy selected in `join_strategy_row`, in the order of its model.
+    fn join_strategy(&self) -> JoinStrategy {
+        match self.imp().join_strategy_row.selected() {
+            0 => JoinStrategy::AdoptRemote,
+            2 => JoinStrategy::OverwriteRemote,
+            _ => JoinStrategy::Merge,
+        }
+    }
+
     fn proxy(&self) -> &DaemonProxy<'static> {
         &self.imp().proxy.get().unwrap().0
     }